@@ -0,0 +1,303 @@
+use crate::utils::get_os;
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// 未指定 `--config` 时读取的配置文件，不存在时使用默认配置
+/// The configuration file read when `--config` is not given, the defaults being used when it does not exist
+const DEFAULT_CONFIG_FILE: &str = "easydb.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// 服务配置，优先级依次为命令行参数、环境变量、配置文件与默认值
+/// The server configuration, taken from command line flags, environment variables, the
+/// configuration file and the defaults, in that order of priority
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub query: QueryConfig,
+    /// 按桶的 S3 配置，如 `[s3.lake]`，环境变量 `S3_<BUCKET>_<KEY>` 优先
+    /// Per-bucket S3 settings such as `[s3.lake]`, `S3_<BUCKET>_<KEY>` environment variables taking priority
+    pub s3: BTreeMap<String, S3BucketConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: String,
+    /// 工作线程数，未设置时为 CPU 核数
+    /// The number of workers, the number of CPU cores when not set
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: "0.0.0.0:8080".to_string(),
+            workers: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// 保存 catalog 与查询历史的 SQLite 数据库，相对路径基于工作目录
+    /// The SQLite database holding the catalog and the query history, relative to the working directory
+    pub metadata_db: PathBuf,
+    pub data_dir: PathBuf,
+    pub tmp_dir: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        let os = get_os();
+        StorageConfig {
+            metadata_db: PathBuf::from("sqlite/easydb.db"),
+            data_dir: PathBuf::from(os.default_data_dir()),
+            tmp_dir: PathBuf::from(os.tmp_dir()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    /// `/fetch` 最多返回的行数
+    /// The maximum number of rows returned by `/fetch`
+    pub row_limit: usize,
+    /// 查询的超时时间（秒），未设置时不限制
+    /// The query timeout in seconds, unlimited when not set
+    pub timeout_secs: Option<u64>,
+    /// 单个查询可使用的内存（MB），超出时查询失败，未设置时不限制
+    /// The memory a single query may use in MB, failing the query beyond it, unlimited when not set
+    pub memory_limit_mb: Option<usize>,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        QueryConfig {
+            row_limit: 200,
+            timeout_secs: None,
+            memory_limit_mb: None,
+        }
+    }
+}
+
+/// 单个 S3 桶的配置，对应 `AWS_<KEY>` 环境变量
+/// The settings of one S3 bucket, matching the `AWS_<KEY>` environment variables
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3BucketConfig {
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub allow_http: Option<bool>,
+    pub virtual_hosted_style_request: Option<bool>,
+    pub skip_signature: Option<bool>,
+}
+
+impl S3BucketConfig {
+    /// 已设置的配置项，键与 `S3_<BUCKET>_<KEY>` 中的 `<KEY>` 相同
+    /// The settings that are set, keyed like `<KEY>` in `S3_<BUCKET>_<KEY>`
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let strings = [
+            ("ACCESS_KEY_ID", &self.access_key_id),
+            ("SECRET_ACCESS_KEY", &self.secret_access_key),
+            ("SESSION_TOKEN", &self.session_token),
+            ("REGION", &self.region),
+            ("ENDPOINT", &self.endpoint),
+        ];
+        let flags = [
+            ("ALLOW_HTTP", self.allow_http),
+            (
+                "VIRTUAL_HOSTED_STYLE_REQUEST",
+                self.virtual_hosted_style_request,
+            ),
+            ("SKIP_SIGNATURE", self.skip_signature),
+        ];
+        IntoIterator::into_iter(strings)
+            .filter_map(|(key, value)| Some((key, value.clone()?)))
+            .chain(
+                IntoIterator::into_iter(flags)
+                    .filter_map(|(key, value)| Some((key, value?.to_string()))),
+            )
+            .collect()
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "EasyDB server, querying files with SQL")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        env = "EASYDB_CONFIG",
+        help = "TOML configuration file [default: easydb.toml when it exists]"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "EASYDB_LISTEN",
+        help = "Listen address [default: 0.0.0.0:8080]"
+    )]
+    listen: Option<String>,
+    #[arg(
+        long,
+        env = "EASYDB_WORKERS",
+        help = "Number of workers [default: CPU cores]"
+    )]
+    workers: Option<usize>,
+    #[arg(
+        long,
+        env = "EASYDB_METADATA_DB",
+        help = "SQLite database of the catalog [default: sqlite/easydb.db]"
+    )]
+    metadata_db: Option<PathBuf>,
+    #[arg(long, env = "DATA_DIR", help = "Data directory")]
+    data_dir: Option<PathBuf>,
+    #[arg(long, env = "EASYDB_TMP_DIR", help = "Temporary directory")]
+    tmp_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "EASYDB_ROW_LIMIT",
+        help = "Maximum number of rows returned by /fetch [default: 200]"
+    )]
+    row_limit: Option<usize>,
+    #[arg(long, env = "EASYDB_QUERY_TIMEOUT", help = "Query timeout in seconds")]
+    query_timeout: Option<u64>,
+    #[arg(
+        long,
+        env = "EASYDB_QUERY_MEMORY_LIMIT",
+        help = "Memory limit of a query in MB"
+    )]
+    query_memory_limit: Option<usize>,
+}
+
+/// 读取并校验配置，配置有误时输出错误并退出
+/// Load and validate the configuration, printing the error and exiting when it is invalid
+pub fn init_config() {
+    let config = load_config(Args::parse()).and_then(|config| {
+        validate_config(&config)?;
+        Ok(config)
+    });
+    match config {
+        Ok(config) => {
+            let _ = CONFIG.set(config);
+        }
+        Err(e) => {
+            eprintln!("Error: invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// 当前配置，未初始化时为默认配置
+/// The current configuration, the defaults when it was not initialized
+pub fn get_config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+fn load_config(args: Args) -> Result<Config, String> {
+    let file = match &args.config {
+        Some(file) => Some(file.as_path()),
+        None => Some(Path::new(DEFAULT_CONFIG_FILE)).filter(|file| file.is_file()),
+    };
+    let mut config = match file {
+        Some(file) => {
+            let content = fs::read_to_string(file)
+                .map_err(|e| format!("failed to read '{}': {}", file.display(), e))?;
+            toml::from_str::<Config>(&content)
+                .map_err(|e| format!("failed to parse '{}': {}", file.display(), e))?
+        }
+        None => Config::default(),
+    };
+
+    if let Some(listen) = args.listen {
+        config.server.listen = listen;
+    }
+    if args.workers.is_some() {
+        config.server.workers = args.workers;
+    }
+    if let Some(metadata_db) = args.metadata_db {
+        config.storage.metadata_db = metadata_db;
+    }
+    if let Some(data_dir) = args.data_dir {
+        config.storage.data_dir = data_dir;
+    }
+    if let Some(tmp_dir) = args.tmp_dir {
+        config.storage.tmp_dir = tmp_dir;
+    }
+    if let Some(row_limit) = args.row_limit {
+        config.query.row_limit = row_limit;
+    }
+    if args.query_timeout.is_some() {
+        config.query.timeout_secs = args.query_timeout;
+    }
+    if args.query_memory_limit.is_some() {
+        config.query.memory_limit_mb = args.query_memory_limit;
+    }
+    Ok(config)
+}
+
+/// 校验配置，并创建不存在的元数据库目录与临时目录
+/// Validate the configuration, creating the directories of the metadata database and the tmp dir when missing
+fn validate_config(config: &Config) -> Result<(), String> {
+    let listen = &config.server.listen;
+    listen
+        .to_socket_addrs()
+        .map_err(|e| format!("server.listen '{}' is not a valid address: {}", listen, e))?;
+    if config.server.workers == Some(0) {
+        return Err("server.workers must be greater than 0".to_string());
+    }
+    if config.query.row_limit == 0 {
+        return Err("query.row_limit must be greater than 0".to_string());
+    }
+    if config.query.timeout_secs == Some(0) {
+        return Err("query.timeout_secs must be greater than 0".to_string());
+    }
+    if config.query.memory_limit_mb == Some(0) {
+        return Err("query.memory_limit_mb must be greater than 0".to_string());
+    }
+
+    let storage = &config.storage;
+    if storage.metadata_db.is_dir() {
+        return Err(format!(
+            "storage.metadata_db '{}' is a directory",
+            storage.metadata_db.display()
+        ));
+    }
+    if let Some(parent) = storage.metadata_db.parent() {
+        create_dir(parent, "storage.metadata_db")?;
+    }
+    if storage.data_dir.exists() && !storage.data_dir.is_dir() {
+        return Err(format!(
+            "storage.data_dir '{}' is not a directory",
+            storage.data_dir.display()
+        ));
+    }
+    create_dir(&storage.tmp_dir, "storage.tmp_dir")?;
+
+    for bucket in config.s3.keys() {
+        if bucket.is_empty() || bucket.contains('/') {
+            return Err(format!("s3.{} is not a valid bucket name", bucket));
+        }
+    }
+    Ok(())
+}
+
+fn create_dir(dir: &Path, name: &str) -> Result<(), String> {
+    if dir.as_os_str().is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create {} '{}': {}", name, dir.display(), e))
+}
@@ -1,35 +1,79 @@
-use crate::data_source::context::{execute, get_data_frame, register_listing_table};
-use crate::request::body::{ExportFile, Fetch};
+use crate::config::get_config;
+use crate::data_source::context::{
+    execute, get_data_frame, infer_table_schema, infer_table_schema_with_conflicts,
+    register_listing_table, with_query_timeout,
+};
+use crate::data_source::discovery::scan_directory;
+use crate::data_source::remote::{split_credentials, with_credentials, RemoteKind};
+use crate::data_source::schema::TableOptions;
+use crate::request::body::{ExportFile, Fetch, ScanDirectory};
 use crate::response::http_error::Exception;
 use crate::response::schema::{FetchHistory, FetchResult, HttpResponseResult, TableCatalog};
 use crate::response::utils::get_encoded_file_name;
-use crate::server::schema::TableFieldSchema;
-use crate::sql::parse::get_sql_type;
+use crate::server::schema::{TableCatalog as ServerTableCatalog, TableFieldSchema};
+use crate::sql::parse::{
+    get_sql_type, get_table_options, parse_create_external_table, parse_scan_directory,
+};
 use crate::sql::schema::SQLType;
-use crate::sqlite::insert_query_history;
+use crate::sqlite::{from_json_column, insert_query_history};
 use crate::utils::FileType;
 use crate::{sqlite, utils};
 use actix_web::{get, post, web, web::Json, HttpResponse, Result};
 use arrow::error::ArrowError;
 use arrow::util::display::{ArrayFormatter, FormatOptions};
-use chrono::{Local, Utc};
+use chrono::{DateTime, Local, Utc};
 use datafusion::config::CsvOptions;
 use datafusion::dataframe::DataFrameWriteOptions;
-use datafusion::logical_expr::sqlparser::ast::Statement;
-use rusqlite::params;
+use datafusion::logical_expr::sqlparser::ast::{ColumnOption, HiveDistributionStyle, Statement};
+use datafusion::sql::parser::CreateExternalTable;
+use rusqlite::{params, OptionalExtension};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use urlencoding::encode;
 
 #[post("/fetch")]
 async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
     let sql = body.sql.trim();
-    let (statements, sql_type) = get_sql_type(sql)?;
     let start = Utc::now();
+    if let Some(path) = parse_scan_directory(sql)? {
+        let tables = scan_directory(path.as_deref()).await?;
+        return HttpResponseResult::success(
+            Some(FetchResult {
+                header: Some(vec![
+                    "table_ref".to_string(),
+                    "table_path".to_string(),
+                    "status".to_string(),
+                ]),
+                rows: Some(
+                    tables
+                        .into_iter()
+                        .map(|table| vec![table.table_ref, table.table_path, table.status])
+                        .collect(),
+                ),
+                sql_type: Some(SQLType::DDL),
+                query_time: utils::time_difference_from_now(start),
+            }),
+            "",
+        );
+    }
+    if let Some(table) = parse_create_external_table(sql)? {
+        let messages = match RemoteKind::from_name(&table.file_type) {
+            Some(kind) => create_external_database_table(kind, table).await?,
+            None => create_external_lake_table(table).await?,
+        };
+        return ddl_result(start, &messages);
+    }
+    let (statements, sql_type) = get_sql_type(sql)?;
     match sql_type {
         SQLType::DML => {
-            let sql = format!("select * from ({}) limit 200", sql.trim_end_matches(";"));
-            let (ctx, execute_sql) = register_listing_table(&sql).await?;
+            let sql = format!(
+                "select * from ({}) limit {}",
+                sql.trim_end_matches(";"),
+                get_config().query.row_limit
+            );
+            let (ctx, execute_sql, messages) = register_listing_table(&sql).await?;
+            let resp_msg = messages.join("; ");
 
             let results = execute(&ctx, &execute_sql).await?;
             if results.is_empty() {
@@ -42,7 +86,7 @@ async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
                         sql_type: Some(SQLType::DML),
                         query_time: utils::time_difference_from_now(start),
                     }),
-                    "",
+                    &resp_msg,
                 );
             }
             let options = FormatOptions::default().with_null("null");
@@ -59,14 +103,13 @@ async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
                     .iter()
                     .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
                     .collect::<std::result::Result<Vec<_>, ArrowError>>()
-                    .map_err(|err| {
+                    .inspect_err(|_| {
                         insert_query_history(&body.sql, "fail");
-                        err
                     })?;
 
                 for row in 0..batch.num_rows() {
                     let mut cells = Vec::new();
-                    for (_, formatter) in formatters.iter().enumerate() {
+                    for formatter in formatters.iter() {
                         cells.push(formatter.value(row).to_string());
                     }
                     rows.push(cells);
@@ -82,10 +125,11 @@ async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
                     sql_type: Some(SQLType::DML),
                     query_time: utils::time_difference_from_now(start),
                 }),
-                "",
+                &resp_msg,
             )
         }
         SQLType::DDL => {
+            let mut messages: Vec<String> = Vec::new();
             for statement in statements {
                 match statement {
                     Statement::CreateTable(query) => {
@@ -99,31 +143,67 @@ async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
                         };
 
                         let table_ref = query.name.to_string();
-                        let table_schemas: Vec<TableFieldSchema> = query
+                        let mut table_schemas: Vec<TableFieldSchema> = query
                             .columns
                             .iter()
                             .map(|column| TableFieldSchema {
                                 field: column.name.to_string(),
                                 field_type: column.data_type.to_string(),
-                                comment: None,
+                                comment: column.options.iter().find_map(|option| {
+                                    match &option.option {
+                                        ColumnOption::Comment(comment) => Some(comment.clone()),
+                                        _ => None,
+                                    }
+                                }),
                             })
                             .collect();
                         let table_comment = query.comment.map(|x| x.to_string());
+                        let mut table_options = get_table_options(&query.with_options);
+                        table_options.merge_defaults(&get_table_options(&query.table_properties));
+                        if let HiveDistributionStyle::PARTITIONED { columns } =
+                            &query.hive_distribution
+                        {
+                            table_options.insert(
+                                "partition_columns",
+                                columns
+                                    .iter()
+                                    .map(|column| format!("{} {}", column.name, column.data_type))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                            );
+                        }
 
-                        let conn = sqlite::conn();
-                        conn.execute(
-                            r#"
-                        insert into catalog ( table_ref, table_path, table_schema, table_comment )
-                        values
-                        (?1, ?2, ?3, ?4)
-                        "#,
-                            params![
-                                table_ref,
-                                location,
-                                serde_json::to_string(&table_schemas)?,
-                                table_comment
-                            ],
-                        )?;
+                        // 未声明列时根据文件推断 schema，文件暂不存在时仍然创建表
+                        // Infer the schema from the files when no columns are declared, still
+                        // creating the table when the files do not exist yet
+                        if table_schemas.is_empty() {
+                            let table = ServerTableCatalog {
+                                table_name: table_ref.clone(),
+                                table_path: location.clone(),
+                                table_options: table_options.clone(),
+                                table_schema: Vec::new(),
+                            };
+                            match infer_table_schema_with_conflicts(&table).await {
+                                Ok((table_schema, conflicts)) => {
+                                    table_schemas = table_schema;
+                                    messages.extend(conflicts);
+                                }
+                                Err(e) => messages.push(format!("Cannot infer the schema: {}", e)),
+                            }
+                        }
+
+                        if !insert_catalog(
+                            &table_ref,
+                            &location,
+                            &table_schemas,
+                            table_comment,
+                            &table_options,
+                        )? {
+                            return Err(Exception::bad_request_error(format!(
+                                "Table '{}' already exists",
+                                table_ref
+                            )));
+                        }
                     }
                     _ => {
                         return Err(Exception::unprocessable_entity_error(
@@ -133,24 +213,185 @@ async fn fetch(body: Json<Fetch>) -> Result<HttpResponse, Exception> {
                 }
             }
 
-            HttpResponseResult::success(
-                Some(FetchResult::<String> {
-                    rows: Some(vec![vec!["successful".to_string()]]),
-                    header: Some(vec!["summary".to_string()]),
-                    sql_type: Some(SQLType::DDL),
-                    query_time: utils::time_difference_from_now(start),
-                }),
-                "",
-            )
+            ddl_result(start, &messages)
         }
     }
 }
 
+fn ddl_result(start: DateTime<Utc>, messages: &[String]) -> Result<HttpResponse, Exception> {
+    HttpResponseResult::success(
+        Some(FetchResult::<String> {
+            rows: Some(vec![vec!["successful".to_string()]]),
+            header: Some(vec!["summary".to_string()]),
+            sql_type: Some(SQLType::DDL),
+            query_time: utils::time_difference_from_now(start),
+        }),
+        &messages.join("; "),
+    )
+}
+
+/// 写入 catalog，用户创建的表会替换同名的自动发现表；已存在同名的用户表时返回 false
+/// Insert into the catalog, replacing an automatically discovered table with the same name;
+/// returns false when a user table with the same name exists
+fn insert_catalog(
+    table_ref: &str,
+    location: &str,
+    table_schemas: &[TableFieldSchema],
+    table_comment: Option<String>,
+    table_options: &TableOptions,
+) -> Result<bool, Exception> {
+    let rows = sqlite::conn().execute(
+        r#"
+                        insert into catalog ( table_ref, table_path, table_schema, table_comment, table_options )
+                        values
+                        (?1, ?2, ?3, ?4, ?5)
+                        on conflict ( table_ref ) do update
+                        set table_path = excluded.table_path, table_schema = excluded.table_schema,
+                            table_comment = excluded.table_comment, table_options = excluded.table_options,
+                            type = 'USER', status = 'OK'
+                        where catalog.type = 'AUTO'
+                        "#,
+        params![
+            table_ref,
+            location,
+            serde_json::to_string(table_schemas)?,
+            table_comment,
+            serde_json::to_string(table_options)?
+        ],
+    )?;
+    Ok(rows > 0)
+}
+
+/// 创建 MySQL 或 PostgreSQL 外部表：连接地址与 `user`、`password` 选项中的凭据单独保存，
+/// catalog 中只保存不含凭据的地址；未声明列时从数据库读取表结构
+/// Create a MySQL or PostgreSQL external table: the credentials from the connection URL and the
+/// `user` and `password` options are saved separately, so the catalog only keeps the URL without
+/// them; the columns are read from the database when none are declared
+async fn create_external_database_table(
+    kind: RemoteKind,
+    table: CreateExternalTable,
+) -> Result<Vec<String>, Exception> {
+    let table_ref = table.name.to_string();
+    let mut table_options = get_external_table_options(&table);
+    let (location, mut credentials) = split_credentials(&table.location);
+    let password = table_options.remove("password");
+    if let Some(user) = table_options.remove("user") {
+        credentials = Some(match password {
+            Some(password) => format!("{}:{}", encode(&user), encode(&password)),
+            None => encode(&user).into_owned(),
+        });
+    }
+    table_options.insert("format", kind.name());
+    if table_options.get("table").is_none() {
+        table_options.insert("table", table_ref.as_str());
+    }
+
+    let mut messages = Vec::new();
+    let mut table_schemas: Vec<TableFieldSchema> = table
+        .columns
+        .iter()
+        .map(|column| TableFieldSchema {
+            field: column.name.to_string(),
+            field_type: column.data_type.to_string(),
+            comment: None,
+        })
+        .collect();
+    if table_schemas.is_empty() {
+        let table = ServerTableCatalog {
+            table_name: table_ref.clone(),
+            table_path: with_credentials(&location, credentials.as_deref()),
+            table_options: table_options.clone(),
+            table_schema: Vec::new(),
+        };
+        match infer_table_schema(&table).await {
+            Ok(table_schema) => table_schemas = table_schema,
+            Err(e) => messages.push(format!("Cannot infer the schema: {}", e)),
+        }
+    }
+
+    if !insert_catalog(&table_ref, &location, &table_schemas, None, &table_options)? {
+        if table.if_not_exists {
+            return Ok(messages);
+        }
+        return Err(Exception::bad_request_error(format!(
+            "Table '{}' already exists",
+            table_ref
+        )));
+    }
+    if let Some(credentials) = credentials {
+        sqlite::save_credentials(&table_ref, &credentials)?;
+    }
+    Ok(messages)
+}
+
+/// 创建 Delta Lake 或 Iceberg 外部表，未声明列时从表的最新快照读取表结构
+/// Create a Delta Lake or Iceberg external table, reading the columns from the latest snapshot of
+/// the table when none are declared
+async fn create_external_lake_table(table: CreateExternalTable) -> Result<Vec<String>, Exception> {
+    let table_ref = table.name.to_string();
+    let mut table_options = get_external_table_options(&table);
+    table_options.insert("format", table.file_type.to_lowercase());
+
+    let mut messages = Vec::new();
+    let mut table_schemas: Vec<TableFieldSchema> = table
+        .columns
+        .iter()
+        .map(|column| TableFieldSchema {
+            field: column.name.to_string(),
+            field_type: column.data_type.to_string(),
+            comment: None,
+        })
+        .collect();
+    if table_schemas.is_empty() {
+        let lake_table = ServerTableCatalog {
+            table_name: table_ref.clone(),
+            table_path: table.location.clone(),
+            table_options: table_options.clone(),
+            table_schema: Vec::new(),
+        };
+        match infer_table_schema(&lake_table).await {
+            Ok(table_schema) => table_schemas = table_schema,
+            Err(e) => messages.push(format!("Cannot infer the schema: {}", e)),
+        }
+    }
+
+    if !insert_catalog(
+        &table_ref,
+        &table.location,
+        &table_schemas,
+        None,
+        &table_options,
+    )? {
+        if table.if_not_exists {
+            return Ok(messages);
+        }
+        return Err(Exception::bad_request_error(format!(
+            "Table '{}' already exists",
+            table_ref
+        )));
+    }
+    Ok(messages)
+}
+
+fn get_external_table_options(table: &CreateExternalTable) -> TableOptions {
+    let mut table_options = TableOptions::default();
+    for (key, value) in &table.options {
+        table_options.insert(
+            key.as_str(),
+            value
+                .clone()
+                .into_string()
+                .unwrap_or_else(|| value.to_string()),
+        );
+    }
+    table_options
+}
+
 #[get("/catalog")]
 async fn catalog() -> Result<HttpResponse, Exception> {
     let conn = sqlite::conn();
     let mut stmt = conn.prepare(
-        "select id, table_ref, table_path, table_schema from catalog where type != 'TEMP'",
+        "select id, table_ref, table_path, table_schema, type, status from catalog where type != 'TEMP'",
     )?;
 
     let catalog_iter = stmt.query_map([], |row| {
@@ -161,12 +402,16 @@ async fn catalog() -> Result<HttpResponse, Exception> {
         let table_schema = serde_json::from_str(&table_schema).map_err(|e| {
             rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Blob, Box::new(e))
         })?;
+        let table_type = row.get::<usize, String>(4)?;
+        let status = row.get::<usize, String>(5)?;
 
         Ok(TableCatalog {
             id,
             table_ref,
             table_path,
             table_schema,
+            table_type,
+            status,
         })
     })?;
 
@@ -178,50 +423,102 @@ async fn catalog() -> Result<HttpResponse, Exception> {
     HttpResponseResult::success(Some(tables), "")
 }
 
+#[post("/catalog/scan")]
+async fn catalog_scan(body: Option<Json<ScanDirectory>>) -> Result<HttpResponse, Exception> {
+    let path = body.and_then(|body| body.into_inner().path);
+    let tables = scan_directory(path.as_deref()).await?;
+    HttpResponseResult::success(Some(tables), "")
+}
+
+#[post("/catalog/{table}/infer")]
+async fn catalog_infer(path: web::Path<String>) -> Result<HttpResponse, Exception> {
+    let table_ref = path.into_inner();
+    let conn = sqlite::conn();
+    let table = conn
+        .query_row(
+            "select table_ref, table_path, table_options, table_schema from catalog where table_ref = ?1 and type != 'TEMP'",
+            params![&table_ref],
+            |row| {
+                Ok(ServerTableCatalog {
+                    table_name: row.get(0)?,
+                    table_path: row.get(1)?,
+                    table_options: from_json_column(row, 2)?,
+                    table_schema: from_json_column(row, 3)?,
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| {
+            Exception::bad_request_error(format!("Table '{}' does not exist", table_ref))
+        })?;
+
+    let (table_schema, conflicts) = infer_table_schema_with_conflicts(&table).await?;
+    conn.execute(
+        "update catalog set table_schema = ?1 where table_ref = ?2",
+        params![serde_json::to_string(&table_schema)?, &table_ref],
+    )?;
+    HttpResponseResult::success(Some(table_schema), &conflicts.join("; "))
+}
+
 #[post("/query/export")]
 async fn fetch_export(body: Json<ExportFile>) -> Result<HttpResponse, Exception> {
     let sql = &body.sql;
     let (_, sql_type) = get_sql_type(sql)?;
     match sql_type {
         SQLType::DML => {
-            let (ctx, execute_sql) = register_listing_table(&sql).await?;
+            let (ctx, execute_sql, _) = register_listing_table(sql).await?;
             let data_frame = get_data_frame(&ctx, &execute_sql).await?;
             let now = Local::now();
-            let mut file_path = format!(
-                "{}query-{}{}",
-                utils::get_os().tmp_dir(),
-                now.format("%Y%m%d%H%M%S"),
-                now.timestamp_subsec_millis()
-            );
+            let mut file_path = get_config()
+                .storage
+                .tmp_dir
+                .join(format!(
+                    "query-{}{}",
+                    now.format("%Y%m%d%H%M%S"),
+                    now.timestamp_subsec_millis()
+                ))
+                .display()
+                .to_string();
 
             match &body.file_type {
                 FileType::JSON | FileType::DnJson => {
                     file_path.push_str(".json");
-                    data_frame
-                        .write_json(&file_path, DataFrameWriteOptions::new(), None)
-                        .await?;
+                    with_query_timeout(data_frame.write_json(
+                        &file_path,
+                        DataFrameWriteOptions::new(),
+                        None,
+                    ))
+                    .await?;
                 }
                 FileType::CSV => {
                     file_path.push_str(".csv");
-                    data_frame
-                        .write_csv(&file_path, DataFrameWriteOptions::new(), None)
-                        .await?;
+                    with_query_timeout(data_frame.write_csv(
+                        &file_path,
+                        DataFrameWriteOptions::new(),
+                        None,
+                    ))
+                    .await?;
                 }
                 FileType::TSV => {
                     file_path.push_str(".tsv");
-                    let mut options = CsvOptions::default();
-                    options.delimiter = b'\t';
-                    data_frame
-                        .write_csv(&file_path, DataFrameWriteOptions::new(), None)
-                        .await?;
+                    let options = CsvOptions {
+                        delimiter: b'\t',
+                        ..Default::default()
+                    };
+                    with_query_timeout(data_frame.write_csv(
+                        &file_path,
+                        DataFrameWriteOptions::new(),
+                        Some(options),
+                    ))
+                    .await?;
                 }
             }
             let path = Path::new(&file_path);
-            match File::open(&path) {
+            match File::open(path) {
                 Ok(mut file) => {
                     let mut contents = Vec::new();
-                    let name = get_encoded_file_name(&path)
-                        .map_err(|e| Exception::unprocessable_entity_error(e))?;
+                    let name = get_encoded_file_name(path)
+                        .map_err(Exception::unprocessable_entity_error)?;
 
                     match file.read_to_end(&mut contents) {
                         Ok(_) => Ok(HttpResponse::Ok()
@@ -272,6 +569,8 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         web::scope("")
             .service(fetch)
             .service(catalog)
+            .service(catalog_scan)
+            .service(catalog_infer)
             .service(fetch_export)
             .service(query_history)
             .service(health),
@@ -0,0 +1,792 @@
+use crate::data_source::avro_decoder::Decoder;
+use crate::data_source::compression::open_decompressed;
+use crate::data_source::merge::merge_schemas;
+use crate::data_source::metadata::{
+    get_modified, FILE_COLUMN, MODIFIED_COLUMN, MODIFIED_DATA_TYPE, ROW_COLUMN,
+};
+use crate::data_source::schema::TableOptions;
+use crate::data_source::utils::find_files;
+use crate::response::http_error::Exception;
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::compute::{cast, concat_batches};
+use arrow::datatypes::{i256, DataType, Field, Fields, Schema, TimeUnit, DECIMAL128_MAX_PRECISION};
+use arrow_array::{
+    new_null_array, ArrayRef, BinaryArray, BooleanArray, Decimal128Array, Decimal256Array,
+    FixedSizeBinaryArray, Float32Array, Float64Array, Int32Array, Int64Array, ListArray, MapArray,
+    RecordBatch, RecordBatchOptions, StringArray, StructArray, TimestampNanosecondArray,
+};
+use bzip2::read::BzDecoder;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use flate2::read::DeflateDecoder;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use xz2::read::XzDecoder;
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+const SYNC_SIZE: usize = 16;
+
+/// Avro 的 schema，逻辑类型（如 `date`、`timestamp-micros`、`decimal`）单独表示，值仍按底层类型编码
+/// An Avro schema, with logical types such as `date`, `timestamp-micros` or `decimal` kept apart
+/// while their values are still encoded as the underlying type
+#[derive(Debug, Clone, PartialEq)]
+pub enum AvroSchema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record {
+        name: String,
+        fields: Vec<AvroField>,
+    },
+    Enum {
+        name: String,
+        symbols: Vec<String>,
+    },
+    Array(Box<AvroSchema>),
+    Map(Box<AvroSchema>),
+    Union(Vec<AvroSchema>),
+    Fixed {
+        name: String,
+        size: usize,
+    },
+    Date,
+    TimeMillis,
+    TimeMicros,
+    TimestampMillis {
+        utc: bool,
+    },
+    TimestampMicros {
+        utc: bool,
+    },
+    TimestampNanos {
+        utc: bool,
+    },
+    Decimal {
+        precision: usize,
+        scale: usize,
+        fixed_size: Option<usize>,
+    },
+    Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvroField {
+    pub name: String,
+    pub schema: AvroSchema,
+}
+
+/// 解码后的 Avro 值，联合类型直接表示为所选分支的值
+/// A decoded Avro value, a union being represented by the value of the chosen branch
+#[derive(Debug, Clone, PartialEq)]
+pub enum AvroValue {
+    Null,
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    Record(Vec<(String, AvroValue)>),
+    Enum(String),
+    Array(Vec<AvroValue>),
+    Map(Vec<(String, AvroValue)>),
+}
+
+impl AvroValue {
+    /// 读取记录中的字段
+    /// Read a field of a record
+    pub fn get(&self, name: &str) -> Option<&AvroValue> {
+        match self {
+            AvroValue::Record(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AvroValue::String(value) | AvroValue::Enum(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            AvroValue::Int(value) => Some(*value as i64),
+            AvroValue::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl AvroSchema {
+    /// 解析 JSON 格式的 schema，命名类型可以在之后按名称引用
+    /// Parse a schema in JSON, named types may be referenced by name afterwards
+    pub fn parse(schema: &str) -> Result<Self, Exception> {
+        let json: JsonValue = serde_json::from_str(schema)?;
+        parse_schema(&json, None, &mut HashMap::new())
+    }
+}
+
+fn parse_schema(
+    json: &JsonValue,
+    namespace: Option<&str>,
+    names: &mut HashMap<String, AvroSchema>,
+) -> Result<AvroSchema, Exception> {
+    match json {
+        JsonValue::String(name) => parse_type_name(name, namespace, names),
+        JsonValue::Array(branches) => Ok(AvroSchema::Union(
+            branches
+                .iter()
+                .map(|branch| parse_schema(branch, namespace, names))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        JsonValue::Object(object) => {
+            let type_name = object.get("type").ok_or_else(|| invalid_schema(json))?;
+            let type_name = match type_name {
+                JsonValue::String(type_name) => type_name.as_str(),
+                // `{"type": {"type": "array", ...}}` 等嵌套定义
+                // A nested definition such as `{"type": {"type": "array", ...}}`
+                _ => return parse_schema(type_name, namespace, names),
+            };
+            let name = object.get("name").and_then(|name| name.as_str());
+            let namespace = object
+                .get("namespace")
+                .and_then(|namespace| namespace.as_str())
+                .or(namespace);
+            let schema = match type_name {
+                "record" | "error" => {
+                    let name = name.ok_or_else(|| invalid_schema(json))?;
+                    let fields = object
+                        .get("fields")
+                        .and_then(|fields| fields.as_array())
+                        .ok_or_else(|| invalid_schema(json))?
+                        .iter()
+                        .map(|field| {
+                            Ok(AvroField {
+                                name: field
+                                    .get("name")
+                                    .and_then(|name| name.as_str())
+                                    .ok_or_else(|| invalid_schema(field))?
+                                    .to_string(),
+                                schema: parse_schema(
+                                    field.get("type").ok_or_else(|| invalid_schema(field))?,
+                                    namespace,
+                                    names,
+                                )?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>, Exception>>()?;
+                    AvroSchema::Record {
+                        name: name.to_string(),
+                        fields,
+                    }
+                }
+                "enum" => AvroSchema::Enum {
+                    name: name.ok_or_else(|| invalid_schema(json))?.to_string(),
+                    symbols: object
+                        .get("symbols")
+                        .and_then(|symbols| symbols.as_array())
+                        .ok_or_else(|| invalid_schema(json))?
+                        .iter()
+                        .map(|symbol| symbol.as_str().unwrap_or_default().to_string())
+                        .collect(),
+                },
+                "array" => AvroSchema::Array(Box::new(parse_schema(
+                    object.get("items").ok_or_else(|| invalid_schema(json))?,
+                    namespace,
+                    names,
+                )?)),
+                "map" => AvroSchema::Map(Box::new(parse_schema(
+                    object.get("values").ok_or_else(|| invalid_schema(json))?,
+                    namespace,
+                    names,
+                )?)),
+                "fixed" => AvroSchema::Fixed {
+                    name: name.ok_or_else(|| invalid_schema(json))?.to_string(),
+                    size: object
+                        .get("size")
+                        .and_then(|size| size.as_u64())
+                        .ok_or_else(|| invalid_schema(json))? as usize,
+                },
+                type_name => parse_type_name(type_name, namespace, names)?,
+            };
+            if let Some(name) = name {
+                register_name(name, namespace, &schema, names);
+            }
+            Ok(parse_logical_type(object, schema))
+        }
+        _ => Err(invalid_schema(json)),
+    }
+}
+
+fn parse_type_name(
+    name: &str,
+    namespace: Option<&str>,
+    names: &HashMap<String, AvroSchema>,
+) -> Result<AvroSchema, Exception> {
+    match name {
+        "null" => Ok(AvroSchema::Null),
+        "boolean" => Ok(AvroSchema::Boolean),
+        "int" => Ok(AvroSchema::Int),
+        "long" => Ok(AvroSchema::Long),
+        "float" => Ok(AvroSchema::Float),
+        "double" => Ok(AvroSchema::Double),
+        "bytes" => Ok(AvroSchema::Bytes),
+        "string" => Ok(AvroSchema::String),
+        name => namespace
+            .and_then(|namespace| names.get(&format!("{}.{}", namespace, name)))
+            .or_else(|| names.get(name))
+            .cloned()
+            .ok_or_else(|| {
+                Exception::bad_request_error(format!(
+                    "Unknown Avro type '{}', recursive types are not supported",
+                    name
+                ))
+            }),
+    }
+}
+
+/// 命名类型同时以全名与短名保存
+/// Named types are kept under both their full and their short name
+fn register_name(
+    name: &str,
+    namespace: Option<&str>,
+    schema: &AvroSchema,
+    names: &mut HashMap<String, AvroSchema>,
+) {
+    let short_name = name.rsplit('.').next().unwrap_or(name);
+    let full_name = match namespace {
+        Some(namespace) if !name.contains('.') && !namespace.is_empty() => {
+            format!("{}.{}", namespace, name)
+        }
+        _ => name.to_string(),
+    };
+    names.insert(full_name, schema.clone());
+    names.insert(short_name.to_string(), schema.clone());
+}
+
+/// 不认识的逻辑类型按底层类型读取
+/// Unknown logical types are read as their underlying type
+fn parse_logical_type(
+    object: &serde_json::Map<String, JsonValue>,
+    schema: AvroSchema,
+) -> AvroSchema {
+    let logical_type = object
+        .get("logicalType")
+        .and_then(|logical_type| logical_type.as_str());
+    match (logical_type, &schema) {
+        (Some("date"), AvroSchema::Int) => AvroSchema::Date,
+        (Some("time-millis"), AvroSchema::Int) => AvroSchema::TimeMillis,
+        (Some("time-micros"), AvroSchema::Long) => AvroSchema::TimeMicros,
+        (Some("timestamp-millis"), AvroSchema::Long) => AvroSchema::TimestampMillis { utc: true },
+        (Some("timestamp-micros"), AvroSchema::Long) => AvroSchema::TimestampMicros { utc: true },
+        (Some("timestamp-nanos"), AvroSchema::Long) => AvroSchema::TimestampNanos { utc: true },
+        (Some("local-timestamp-millis"), AvroSchema::Long) => {
+            AvroSchema::TimestampMillis { utc: false }
+        }
+        (Some("local-timestamp-micros"), AvroSchema::Long) => {
+            AvroSchema::TimestampMicros { utc: false }
+        }
+        (Some("local-timestamp-nanos"), AvroSchema::Long) => {
+            AvroSchema::TimestampNanos { utc: false }
+        }
+        (Some("uuid"), AvroSchema::String) => AvroSchema::Uuid,
+        (Some("decimal"), AvroSchema::Bytes | AvroSchema::Fixed { .. }) => AvroSchema::Decimal {
+            precision: object
+                .get("precision")
+                .and_then(|precision| precision.as_u64())
+                .unwrap_or(38) as usize,
+            scale: object
+                .get("scale")
+                .and_then(|scale| scale.as_u64())
+                .unwrap_or(0) as usize,
+            fixed_size: match schema {
+                AvroSchema::Fixed { size, .. } => Some(size),
+                _ => None,
+            },
+        },
+        _ => schema,
+    }
+}
+
+fn invalid_schema(json: &JsonValue) -> Exception {
+    Exception::bad_request_error(format!("Invalid Avro schema: {}", json))
+}
+
+/// 读取 Avro 对象容器文件（本地文件或对象存储中的对象）的 schema 与全部记录，支持 null、deflate、snappy、zstandard、bzip2 与 xz 压缩
+/// Read the schema and all records of an Avro object container file (a local file or an object
+/// in object storage), supporting the null, deflate, snappy, zstandard, bzip2 and xz codecs
+pub fn read_avro_file(file_path: &str) -> Result<(AvroSchema, Vec<AvroValue>), Exception> {
+    let mut data = Vec::new();
+    open_decompressed(file_path, FileCompressionType::UNCOMPRESSED)?.read_to_end(&mut data)?;
+    if !data.starts_with(MAGIC) {
+        return Err(Exception::bad_request_error(format!(
+            "'{}' is not an Avro file",
+            file_path
+        )));
+    }
+
+    let mut decoder = Decoder::new(&data[MAGIC.len()..]);
+    let mut metadata = HashMap::new();
+    decoder.read_blocks(|decoder| {
+        let key = decoder.read_string()?;
+        metadata.insert(key, decoder.read_bytes()?.to_vec());
+        Ok(())
+    })?;
+    let schema = metadata
+        .get("avro.schema")
+        .ok_or_else(|| Exception::bad_request_error("The Avro file has no schema"))?;
+    let schema = AvroSchema::parse(&String::from_utf8_lossy(schema))?;
+    let codec = metadata
+        .get("avro.codec")
+        .map(|codec| String::from_utf8_lossy(codec).into_owned())
+        .unwrap_or_else(|| "null".to_string());
+    let sync = decoder.read_fixed(SYNC_SIZE)?.to_vec();
+
+    let mut values = Vec::new();
+    while !decoder.is_empty() {
+        let count = decoder.read_length()?;
+        let size = decoder.read_length()?;
+        let block = decompress(&codec, decoder.read_fixed(size)?)?;
+        let mut block_decoder = Decoder::new(&block);
+        for _ in 0..count {
+            values.push(block_decoder.read_value(&schema)?);
+        }
+        if decoder.read_fixed(SYNC_SIZE)? != sync.as_slice() {
+            return Err(Exception::bad_request_error(format!(
+                "Invalid sync marker in the Avro file '{}'",
+                file_path
+            )));
+        }
+    }
+    Ok((schema, values))
+}
+
+pub struct AvroOptions {
+    pub metadata_columns: bool,
+    pub strict_schema: bool,
+}
+
+impl AvroOptions {
+    pub fn try_new(table_options: &TableOptions) -> Result<Self, Exception> {
+        Ok(AvroOptions {
+            metadata_columns: table_options.get_bool("metadata_columns")?.unwrap_or(false),
+            strict_schema: table_options.get_bool("strict_schema")?.unwrap_or(false),
+        })
+    }
+}
+
+/// 读取 glob 匹配的全部 Avro 文件，各文件的 schema 不一致时按合并后的 schema 对齐
+/// Read every Avro file matched by the glob, aligning files with different schemas to the merged schema
+pub fn from_file_to_record_batch(
+    file_path: &str,
+    options: &AvroOptions,
+) -> Result<RecordBatch, Exception> {
+    let files = find_files(file_path)?;
+    if files.is_empty() {
+        return Err(Exception::file_not_found_error(file_path));
+    }
+
+    let mut batches = Vec::new();
+    for file in &files {
+        let (schema, values) = read_avro_file(file)?;
+        let fields = match &schema {
+            AvroSchema::Record { fields, .. } => fields
+                .iter()
+                .map(|field| to_field(&field.name, &field.schema))
+                .collect::<Vec<_>>(),
+            // 顶层不是记录时整个值作为 `value` 列
+            // A top-level schema other than a record becomes the `value` column
+            schema => vec![to_field("value", schema)],
+        };
+        let columns = match &schema {
+            AvroSchema::Record { .. } => fields
+                .iter()
+                .map(|field| {
+                    let column = values
+                        .iter()
+                        .map(|value| value.get(field.name()).unwrap_or(&AvroValue::Null))
+                        .collect::<Vec<_>>();
+                    to_array(field.data_type(), &column)
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![to_array(
+                fields[0].data_type(),
+                &values.iter().collect::<Vec<_>>(),
+            )?],
+        };
+        let batch = RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            columns,
+            &RecordBatchOptions::new().with_row_count(Some(values.len())),
+        )?;
+        batches.push((file.clone(), batch));
+    }
+
+    let schemas = batches
+        .iter()
+        .map(|(file, batch)| (file.clone(), batch.schema()))
+        .collect::<Vec<_>>();
+    let schema = merge_schemas(&schemas, options.strict_schema)?;
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    if options.metadata_columns {
+        fields.push(Arc::new(Field::new(FILE_COLUMN, DataType::Utf8, false)));
+        fields.push(Arc::new(Field::new(ROW_COLUMN, DataType::Int64, false)));
+        fields.push(Arc::new(Field::new(
+            MODIFIED_COLUMN,
+            MODIFIED_DATA_TYPE,
+            true,
+        )));
+    }
+    let output_schema = Arc::new(Schema::new(fields));
+
+    let mut aligned = Vec::new();
+    for (file, batch) in batches {
+        let rows = batch.num_rows();
+        let mut columns = schema
+            .fields()
+            .iter()
+            .map(|field| match batch.column_by_name(field.name()) {
+                Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+                Some(column) => cast(column, field.data_type()).map_err(Exception::from),
+                None => Ok(new_null_array(field.data_type(), rows)),
+            })
+            .collect::<Result<Vec<_>, Exception>>()?;
+        if options.metadata_columns {
+            columns.push(Arc::new(StringArray::from(vec![file.clone(); rows])));
+            columns.push(Arc::new(Int64Array::from_iter_values(1..=rows as i64)));
+            columns.push(Arc::new(TimestampNanosecondArray::from(vec![
+                get_modified(
+                    &file
+                );
+                rows
+            ])));
+        }
+        aligned.push(RecordBatch::try_new_with_options(
+            output_schema.clone(),
+            columns,
+            &RecordBatchOptions::new().with_row_count(Some(rows)),
+        )?);
+    }
+    concat_batches(&output_schema, &aligned).map_err(Exception::from)
+}
+
+/// `["null", T]` 形式的联合类型读取为可空的 `T`，其他有多个分支的联合类型读取为 JSON 文本
+/// A `["null", T]` union is read as a nullable `T`, other unions with several branches as JSON text
+fn to_field(name: &str, schema: &AvroSchema) -> Field {
+    match schema {
+        AvroSchema::Union(branches) => {
+            let others = branches
+                .iter()
+                .filter(|branch| **branch != AvroSchema::Null)
+                .collect::<Vec<_>>();
+            match others.as_slice() {
+                [branch] => Field::new(name, to_data_type(branch), true),
+                _ => Field::new(name, DataType::Utf8, true),
+            }
+        }
+        AvroSchema::Null => Field::new(name, DataType::Null, true),
+        schema => Field::new(name, to_data_type(schema), false),
+    }
+}
+
+fn to_data_type(schema: &AvroSchema) -> DataType {
+    match schema {
+        AvroSchema::Null => DataType::Null,
+        AvroSchema::Boolean => DataType::Boolean,
+        AvroSchema::Int => DataType::Int32,
+        AvroSchema::Long => DataType::Int64,
+        AvroSchema::Float => DataType::Float32,
+        AvroSchema::Double => DataType::Float64,
+        AvroSchema::Bytes => DataType::Binary,
+        AvroSchema::String | AvroSchema::Uuid | AvroSchema::Enum { .. } => DataType::Utf8,
+        AvroSchema::Record { fields, .. } => DataType::Struct(
+            fields
+                .iter()
+                .map(|field| to_field(&field.name, &field.schema))
+                .collect(),
+        ),
+        AvroSchema::Array(items) => DataType::List(Arc::new(to_field("item", items))),
+        AvroSchema::Map(values) => DataType::Map(
+            Arc::new(Field::new(
+                "entries",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("key", DataType::Utf8, false),
+                    to_field("value", values),
+                ])),
+                false,
+            )),
+            false,
+        ),
+        AvroSchema::Union(_) => to_field("", schema).data_type().clone(),
+        AvroSchema::Fixed { size, .. } => DataType::FixedSizeBinary(*size as i32),
+        AvroSchema::Date => DataType::Date32,
+        AvroSchema::TimeMillis => DataType::Time32(TimeUnit::Millisecond),
+        AvroSchema::TimeMicros => DataType::Time64(TimeUnit::Microsecond),
+        AvroSchema::TimestampMillis { utc } => timestamp(TimeUnit::Millisecond, *utc),
+        AvroSchema::TimestampMicros { utc } => timestamp(TimeUnit::Microsecond, *utc),
+        AvroSchema::TimestampNanos { utc } => timestamp(TimeUnit::Nanosecond, *utc),
+        AvroSchema::Decimal {
+            precision, scale, ..
+        } if *precision <= DECIMAL128_MAX_PRECISION as usize => {
+            DataType::Decimal128(*precision as u8, *scale as i8)
+        }
+        AvroSchema::Decimal {
+            precision, scale, ..
+        } => DataType::Decimal256(*precision as u8, *scale as i8),
+    }
+}
+
+fn timestamp(unit: TimeUnit, utc: bool) -> DataType {
+    DataType::Timestamp(unit, utc.then(|| "UTC".into()))
+}
+
+/// 按 Arrow 类型构建一列，日期与时间类型先按整数构建再转换
+/// Build a column of the Arrow type, dates and times being built as integers and then cast
+fn to_array(data_type: &DataType, values: &[&AvroValue]) -> Result<ArrayRef, Exception> {
+    let array: ArrayRef = match data_type {
+        DataType::Null => new_null_array(data_type, values.len()),
+        DataType::Boolean => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Boolean(value) => Some(*value),
+                    _ => None,
+                })
+                .collect::<BooleanArray>(),
+        ),
+        DataType::Int32 | DataType::Date32 | DataType::Time32(_) => {
+            let array = values
+                .iter()
+                .map(|value| value.as_i64().map(|value| value as i32))
+                .collect::<Int32Array>();
+            cast(&array, data_type)?
+        }
+        DataType::Int64 | DataType::Time64(_) | DataType::Timestamp(_, _) => {
+            let array = values
+                .iter()
+                .map(|value| value.as_i64())
+                .collect::<Int64Array>();
+            cast(&array, data_type)?
+        }
+        DataType::Float32 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Float(value) => Some(*value),
+                    _ => None,
+                })
+                .collect::<Float32Array>(),
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Double(value) => Some(*value),
+                    _ => None,
+                })
+                .collect::<Float64Array>(),
+        ),
+        DataType::Binary => Arc::new(
+            values
+                .iter()
+                .map(|value| as_bytes(value))
+                .collect::<BinaryArray>(),
+        ),
+        DataType::FixedSizeBinary(size) => {
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+                values.iter().map(|value| as_bytes(value)),
+                *size,
+            )?)
+        }
+        DataType::Decimal128(precision, scale) => Arc::new(
+            values
+                .iter()
+                .map(|value| as_bytes(value).map(|bytes| i128::from_be_bytes(sign_extend(bytes))))
+                .collect::<Decimal128Array>()
+                .with_precision_and_scale(*precision, *scale)?,
+        ),
+        DataType::Decimal256(precision, scale) => Arc::new(
+            values
+                .iter()
+                .map(|value| as_bytes(value).map(|bytes| i256::from_be_bytes(sign_extend(bytes))))
+                .collect::<Decimal256Array>()
+                .with_precision_and_scale(*precision, *scale)?,
+        ),
+        DataType::Struct(fields) => {
+            let columns = fields
+                .iter()
+                .map(|field| {
+                    let column = values
+                        .iter()
+                        .map(|value| value.get(field.name()).unwrap_or(&AvroValue::Null))
+                        .collect::<Vec<_>>();
+                    to_array(field.data_type(), &column)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Arc::new(StructArray::try_new(
+                fields.clone(),
+                columns,
+                get_nulls(values),
+            )?)
+        }
+        DataType::List(field) => {
+            let items = values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Array(items) => items.as_slice(),
+                    _ => &[],
+                })
+                .collect::<Vec<_>>();
+            let child = items
+                .iter()
+                .flat_map(|items| items.iter())
+                .collect::<Vec<_>>();
+            Arc::new(ListArray::try_new(
+                field.clone(),
+                OffsetBuffer::from_lengths(items.iter().map(|items| items.len())),
+                to_array(field.data_type(), &child)?,
+                get_nulls(values),
+            )?)
+        }
+        DataType::Map(field, _) => {
+            let entries = values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Map(entries) => entries.as_slice(),
+                    _ => &[],
+                })
+                .collect::<Vec<_>>();
+            let DataType::Struct(entry_fields) = field.data_type() else {
+                return Err(Exception::bad_request_error("Invalid Avro map type"));
+            };
+            let keys = entries
+                .iter()
+                .flat_map(|entries| entries.iter().map(|(key, _)| Some(key.as_str())))
+                .collect::<StringArray>();
+            let map_values = entries
+                .iter()
+                .flat_map(|entries| entries.iter().map(|(_, value)| value))
+                .collect::<Vec<_>>();
+            let entries_array = StructArray::try_new(
+                entry_fields.clone(),
+                vec![
+                    Arc::new(keys),
+                    to_array(entry_fields[1].data_type(), &map_values)?,
+                ],
+                None,
+            )?;
+            Arc::new(MapArray::try_new(
+                field.clone(),
+                OffsetBuffer::from_lengths(entries.iter().map(|entries| entries.len())),
+                entries_array,
+                get_nulls(values),
+                false,
+            )?)
+        }
+        _ => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    AvroValue::Null => None,
+                    AvroValue::String(value) | AvroValue::Enum(value) => Some(value.clone()),
+                    value => Some(to_json(value).to_string()),
+                })
+                .collect::<StringArray>(),
+        ),
+    };
+    Ok(array)
+}
+
+fn as_bytes(value: &AvroValue) -> Option<&[u8]> {
+    match value {
+        AvroValue::Bytes(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// 小数是大端序的补码，按符号位扩展到 Arrow 小数的宽度
+/// Decimals are big-endian two's complement, sign-extended to the width of the Arrow decimal
+fn sign_extend<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let fill = if bytes.first().is_some_and(|byte| *byte & 0x80 != 0) {
+        0xFF
+    } else {
+        0
+    };
+    let mut extended = [fill; N];
+    let bytes = &bytes[bytes.len().saturating_sub(N)..];
+    extended[N - bytes.len()..].copy_from_slice(bytes);
+    extended
+}
+
+fn get_nulls(values: &[&AvroValue]) -> Option<NullBuffer> {
+    let nulls = NullBuffer::from(
+        values
+            .iter()
+            .map(|value| **value != AvroValue::Null)
+            .collect::<Vec<_>>(),
+    );
+    (nulls.null_count() > 0).then_some(nulls)
+}
+
+fn to_json(value: &AvroValue) -> JsonValue {
+    match value {
+        AvroValue::Null => JsonValue::Null,
+        AvroValue::Boolean(value) => JsonValue::from(*value),
+        AvroValue::Int(value) => JsonValue::from(*value),
+        AvroValue::Long(value) => JsonValue::from(*value),
+        AvroValue::Float(value) => JsonValue::from(*value),
+        AvroValue::Double(value) => JsonValue::from(*value),
+        AvroValue::Bytes(value) => JsonValue::from(String::from_utf8_lossy(value).into_owned()),
+        AvroValue::String(value) | AvroValue::Enum(value) => JsonValue::from(value.as_str()),
+        AvroValue::Record(fields) | AvroValue::Map(fields) => JsonValue::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), to_json(value)))
+                .collect(),
+        ),
+        AvroValue::Array(items) => JsonValue::Array(items.iter().map(to_json).collect()),
+    }
+}
+
+fn decompress(codec: &str, block: &[u8]) -> Result<Vec<u8>, Exception> {
+    let mut data = Vec::new();
+    match codec {
+        "null" => data.extend_from_slice(block),
+        "deflate" => {
+            DeflateDecoder::new(block).read_to_end(&mut data)?;
+        }
+        // snappy 块的末尾是未压缩数据的 4 字节 CRC32
+        // A snappy block ends with the 4-byte CRC32 of the uncompressed data
+        "snappy" => {
+            let block = &block[..block.len().saturating_sub(4)];
+            data = snap::raw::Decoder::new()
+                .decompress_vec(block)
+                .map_err(|e| Exception::bad_request_error(e.to_string()))?;
+        }
+        "zstandard" => data = zstd::decode_all(block)?,
+        "bzip2" => {
+            BzDecoder::new(block).read_to_end(&mut data)?;
+        }
+        "xz" => {
+            XzDecoder::new(block).read_to_end(&mut data)?;
+        }
+        codec => {
+            return Err(Exception::bad_request_error(format!(
+                "Unsupported Avro codec '{}'",
+                codec
+            )))
+        }
+    }
+    Ok(data)
+}
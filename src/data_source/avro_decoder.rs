@@ -0,0 +1,255 @@
+use crate::data_source::avro::{AvroSchema, AvroValue};
+use crate::response::http_error::Exception;
+use std::convert::{TryFrom, TryInto};
+
+/// 按 Avro 二进制编码读取值
+/// Reads values in the Avro binary encoding
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    /// 读取固定数量的字节，超出数据末尾时报错
+    /// Read a fixed number of bytes, failing past the end of the data
+    pub fn read_fixed(&mut self, size: usize) -> Result<&'a [u8], Exception> {
+        let end = match self.position.checked_add(size) {
+            Some(end) if end <= self.data.len() => end,
+            _ => {
+                return Err(Exception::bad_request_error(
+                    "Unexpected end of the Avro data",
+                ))
+            }
+        };
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// 变长的 zigzag 编码整数
+    /// A variable-length zigzag encoded integer
+    pub fn read_long(&mut self) -> Result<i64, Exception> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_fixed(1)?[0];
+            if shift >= 64 {
+                return Err(Exception::bad_request_error(
+                    "Invalid integer in the Avro data",
+                ));
+            }
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// 长度或数量，负数是无效数据
+    /// A length or count, negative values being invalid data
+    pub fn read_length(&mut self) -> Result<usize, Exception> {
+        let length = self.read_long()?;
+        usize::try_from(length).map_err(|_| {
+            Exception::bad_request_error(format!("Invalid length {} in the Avro data", length))
+        })
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], Exception> {
+        let length = self.read_length()?;
+        self.read_fixed(length)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Exception> {
+        Ok(String::from_utf8_lossy(self.read_bytes()?).into_owned())
+    }
+
+    /// 数组与 map 按块编码，块的数量为负数时之后是块的字节数
+    /// Arrays and maps are encoded in blocks, a negative count being followed by the block size in bytes
+    pub fn read_blocks(
+        &mut self,
+        mut read_item: impl FnMut(&mut Self) -> Result<(), Exception>,
+    ) -> Result<(), Exception> {
+        loop {
+            let count = self.read_long()?;
+            if count == 0 {
+                return Ok(());
+            }
+            if count < 0 {
+                self.read_long()?;
+            }
+            for _ in 0..count.unsigned_abs() {
+                read_item(self)?;
+            }
+        }
+    }
+
+    pub fn read_value(&mut self, schema: &AvroSchema) -> Result<AvroValue, Exception> {
+        Ok(match schema {
+            AvroSchema::Null => AvroValue::Null,
+            AvroSchema::Boolean => AvroValue::Boolean(self.read_fixed(1)?[0] != 0),
+            AvroSchema::Int | AvroSchema::Date | AvroSchema::TimeMillis => {
+                AvroValue::Int(self.read_long()? as i32)
+            }
+            AvroSchema::Long
+            | AvroSchema::TimeMicros
+            | AvroSchema::TimestampMillis { .. }
+            | AvroSchema::TimestampMicros { .. }
+            | AvroSchema::TimestampNanos { .. } => AvroValue::Long(self.read_long()?),
+            AvroSchema::Float => AvroValue::Float(f32::from_le_bytes(
+                self.read_fixed(4)?.try_into().unwrap_or_default(),
+            )),
+            AvroSchema::Double => AvroValue::Double(f64::from_le_bytes(
+                self.read_fixed(8)?.try_into().unwrap_or_default(),
+            )),
+            AvroSchema::Bytes
+            | AvroSchema::Decimal {
+                fixed_size: None, ..
+            } => AvroValue::Bytes(self.read_bytes()?.to_vec()),
+            AvroSchema::Fixed { size, .. }
+            | AvroSchema::Decimal {
+                fixed_size: Some(size),
+                ..
+            } => AvroValue::Bytes(self.read_fixed(*size)?.to_vec()),
+            AvroSchema::String | AvroSchema::Uuid => AvroValue::String(self.read_string()?),
+            AvroSchema::Record { fields, .. } => AvroValue::Record(
+                fields
+                    .iter()
+                    .map(|field| Ok((field.name.clone(), self.read_value(&field.schema)?)))
+                    .collect::<Result<Vec<_>, Exception>>()?,
+            ),
+            AvroSchema::Enum { symbols, .. } => {
+                let index = self.read_long()?;
+                AvroValue::Enum(
+                    symbols
+                        .get(index as usize)
+                        .cloned()
+                        .ok_or_else(|| invalid_index("enum symbol", index))?,
+                )
+            }
+            AvroSchema::Array(items) => {
+                let mut values = Vec::new();
+                self.read_blocks(|decoder| {
+                    values.push(decoder.read_value(items)?);
+                    Ok(())
+                })?;
+                AvroValue::Array(values)
+            }
+            AvroSchema::Map(values_schema) => {
+                let mut values = Vec::new();
+                self.read_blocks(|decoder| {
+                    let key = decoder.read_string()?;
+                    values.push((key, decoder.read_value(values_schema)?));
+                    Ok(())
+                })?;
+                AvroValue::Map(values)
+            }
+            AvroSchema::Union(branches) => {
+                let index = self.read_long()?;
+                let branch = branches
+                    .get(index as usize)
+                    .ok_or_else(|| invalid_index("union branch", index))?;
+                self.read_value(branch)?
+            }
+        })
+    }
+}
+
+fn invalid_index(kind: &str, index: i64) -> Exception {
+    Exception::bad_request_error(format!("Invalid {} {} in the Avro data", kind, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_schema() -> AvroSchema {
+        AvroSchema::parse(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": ["null", "string"]},
+                {"name": "tags", "type": {"type": "array", "items": "string"}}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn read_long_decodes_zigzag_varints() {
+        let data = [0x00, 0x01, 0x02, 0x7F, 0x80, 0x01];
+        let mut decoder = Decoder::new(&data);
+        let values = (0..5)
+            .map(|_| decoder.read_long().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0, -1, 1, -64, 64]);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn read_long_rejects_truncated_and_overlong_integers() {
+        assert!(Decoder::new(&[0x80]).read_long().is_err());
+        assert!(Decoder::new(&[0xFF; 11]).read_long().is_err());
+    }
+
+    #[test]
+    fn read_bytes_rejects_negative_lengths() {
+        // -1 与 i64::MIN 的 zigzag 编码
+        // The zigzag encodings of -1 and i64::MIN
+        assert!(Decoder::new(&[0x01, 0x61]).read_bytes().is_err());
+        let min = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert!(Decoder::new(&min).read_bytes().is_err());
+    }
+
+    #[test]
+    fn read_bytes_rejects_lengths_past_the_end() {
+        let mut decoder = Decoder::new(&[0x06, 0x61, 0x62]);
+        assert!(decoder.read_bytes().is_err());
+    }
+
+    #[test]
+    fn read_fixed_rejects_sizes_that_overflow() {
+        let mut decoder = Decoder::new(&[0x61, 0x62]);
+        decoder.read_fixed(1).unwrap();
+        assert!(decoder.read_fixed(usize::MAX).is_err());
+        assert_eq!(decoder.read_fixed(1).unwrap(), b"b");
+    }
+
+    #[test]
+    fn read_value_decodes_records() {
+        // id = 2, name = "ab", tags = ["x"]
+        let data = [0x04, 0x02, 0x04, b'a', b'b', 0x02, 0x02, b'x', 0x00];
+        let value = Decoder::new(&data).read_value(&record_schema()).unwrap();
+        assert_eq!(
+            value,
+            AvroValue::Record(vec![
+                ("id".to_string(), AvroValue::Long(2)),
+                ("name".to_string(), AvroValue::String("ab".to_string())),
+                (
+                    "tags".to_string(),
+                    AvroValue::Array(vec![AvroValue::String("x".to_string())])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn read_value_rejects_invalid_union_branches() {
+        // 分支 -1 与分支 2
+        // Branch -1 and branch 2
+        assert!(Decoder::new(&[0x02, 0x01])
+            .read_value(&record_schema())
+            .is_err());
+        assert!(Decoder::new(&[0x02, 0x04])
+            .read_value(&record_schema())
+            .is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::data_source::store::{is_object_store_path, ObjectReader};
+use crate::response::http_error::Exception;
+use bzip2::read::MultiBzDecoder;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+use xz2::read::XzDecoder;
+
+const COMPRESSION_EXTENSIONS: [(&str, FileCompressionType); 4] = [
+    (".gz", FileCompressionType::GZIP),
+    (".zst", FileCompressionType::ZSTD),
+    (".bz2", FileCompressionType::BZIP2),
+    (".xz", FileCompressionType::XZ),
+];
+
+/// 拆分压缩扩展名，例如 `app.log.gz` 拆分为 `app.log` 与 `.gz`
+/// Split off the compression extension, e.g. `app.log.gz` into `app.log` and `.gz`
+pub fn split_compression_extension(file_name: &str) -> (&str, &str) {
+    for (extension, _) in COMPRESSION_EXTENSIONS {
+        if let Some(stem) = file_name.strip_suffix(extension) {
+            return (stem, extension);
+        }
+    }
+    (file_name, "")
+}
+
+/// 根据文件扩展名获取压缩类型
+/// Get the compression type from the file extension
+pub fn get_compression_type(file_name: &str) -> FileCompressionType {
+    let file_name = file_name.trim_end_matches('\'');
+    COMPRESSION_EXTENSIONS
+        .iter()
+        .find(|(extension, _)| file_name.ends_with(extension))
+        .map(|(_, compression_type)| *compression_type)
+        .unwrap_or(FileCompressionType::UNCOMPRESSED)
+}
+
+/// 解析 `compression` 选项，支持 `gzip`、`zstd`、`bzip2`、`xz`、`uncompressed`
+/// Parse the `compression` option: `gzip`, `zstd`, `bzip2`, `xz` or `uncompressed`
+pub fn parse_compression_type(name: &str) -> Result<FileCompressionType, Exception> {
+    match name.to_lowercase().as_str() {
+        "uncompressed" | "none" => Ok(FileCompressionType::UNCOMPRESSED),
+        name => FileCompressionType::from_str(name).map_err(|_| {
+            Exception::bad_request_error(format!("Unsupported compression '{}'", name))
+        }),
+    }
+}
+
+/// 根据文件头的魔数识别压缩类型
+/// Identify the compression type from the magic bytes at the start of a file
+pub fn detect_compression_type(buffer: &[u8]) -> Option<(FileCompressionType, &'static str)> {
+    if buffer.starts_with(b"\x1F\x8B") {
+        Some((FileCompressionType::GZIP, "gzip"))
+    } else if buffer.starts_with(b"\x28\xB5\x2F\xFD") {
+        Some((FileCompressionType::ZSTD, "zstd"))
+    } else if buffer.starts_with(b"BZh") {
+        Some((FileCompressionType::BZIP2, "bzip2"))
+    } else if buffer.starts_with(b"\xFD7zXZ\x00") {
+        Some((FileCompressionType::XZ, "xz"))
+    } else {
+        None
+    }
+}
+
+/// 打开文件（或对象存储中的对象）并按压缩类型透明解压
+/// Open a file (or an object in object storage) and transparently decompress it according to the compression type
+pub fn open_decompressed(
+    file_path: &str,
+    compression_type: FileCompressionType,
+) -> Result<Box<dyn Read>, Exception> {
+    let file: Box<dyn BufRead> = if is_object_store_path(file_path) {
+        Box::new(BufReader::new(ObjectReader::open(file_path)?))
+    } else {
+        Box::new(BufReader::new(File::open(file_path)?))
+    };
+    let reader: Box<dyn Read> = if compression_type == FileCompressionType::GZIP {
+        Box::new(MultiGzDecoder::new(file))
+    } else if compression_type == FileCompressionType::ZSTD {
+        Box::new(zstd::Decoder::with_buffer(file)?)
+    } else if compression_type == FileCompressionType::BZIP2 {
+        Box::new(MultiBzDecoder::new(file))
+    } else if compression_type == FileCompressionType::XZ {
+        Box::new(XzDecoder::new_multi_decoder(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(reader)
+}
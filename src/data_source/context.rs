@@ -1,21 +1,87 @@
-use crate::data_source::excel::from_file_to_record_batch;
-use crate::data_source::schema::DataSourceFormat;
-use crate::data_source::utils::get_format;
+use crate::config::get_config;
+use crate::data_source::avro;
+use crate::data_source::avro::AvroOptions;
+use crate::data_source::compression::{get_compression_type, parse_compression_type};
+use crate::data_source::deadline::with_deadline;
+use crate::data_source::encoding::{get_source_path, transcode_files};
+use crate::data_source::excel::{ExcelOptions, ExcelTable};
+use crate::data_source::fixed_width;
+use crate::data_source::fixed_width::FixedWidthOptions;
+use crate::data_source::flatten::flatten_table;
+use crate::data_source::lake::{detect_lake_format, register_lake_table};
+use crate::data_source::log;
+use crate::data_source::log::LogOptions;
+use crate::data_source::merge::{align_data_frame, is_same_schema, merge_schemas};
+use crate::data_source::metadata::{with_metadata_fields, MetadataTable};
+use crate::data_source::partition::{find_table_files, get_partition_columns, is_partitioned};
+use crate::data_source::remote::{with_credentials, RemoteKind, RemoteTable};
+use crate::data_source::schema::{DataSourceFormat, TableOptions};
+use crate::data_source::sniff::sniff_format;
+use crate::data_source::sqlite_file::SqliteTable;
+use crate::data_source::store::{
+    cache_objects, is_object_store_path, parse_listing_table_url, register_object_store,
+    ObjectReader,
+};
+use crate::data_source::utils::{
+    find_files, get_file_extension, get_format, get_format_by_name, is_ambiguous_format, is_dir,
+    is_file_path, run_blocking,
+};
+use crate::data_source::xml;
+use crate::data_source::xml::XmlOptions;
 use crate::response::http_error::Exception;
-use crate::server::schema::TableCatalog;
-use crate::sql::parse::get_table_names;
+use crate::server::schema::{TableCatalog, TableFieldSchema};
+use crate::sql::json::register_json_functions;
+use crate::sql::parse::{get_table_names, rewrite_table_functions, temp_table_name};
 use crate::sql::sql_error::SQLError;
-use crate::utils::get_os;
+use crate::sqlite::from_json_column;
 use crate::{sqlite, utils};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::reader::FileReader;
 use arrow_array::RecordBatch;
-use chrono::Utc;
 use datafusion::dataframe::DataFrame;
-use datafusion::prelude::{CsvReadOptions, NdJsonReadOptions, SessionContext};
-use rusqlite::{params, params_from_iter};
-use std::env;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use datafusion::datasource::file_format::options::{ArrowReadOptions, ReadOptions};
+use datafusion::datasource::listing::{ListingOptions, ListingTable, ListingTableConfig};
+use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+use datafusion::physical_plan::collect;
+use datafusion::prelude::{
+    CsvReadOptions, NdJsonReadOptions, ParquetReadOptions, SessionConfig, SessionContext,
+};
+use rusqlite::{params, params_from_iter, OptionalExtension};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+/// 纯文本按整行读取，使用不会出现在文本中的控制字符作为分隔符与引号
+/// Plain text is read line by line, using control characters that never appear in text as delimiter and quote
+const TEXT_DELIMITER: u8 = 0x1F;
+const TEXT_QUOTE: u8 = 0x1E;
+/// 检查类型冲突时最多逐个读取的文件数
+/// The maximum number of files read one by one when checking for type conflicts
+const MAX_CONFLICT_CHECK_FILES: usize = 100;
+/// glob 匹配的文件 schema 不一致时最多分别读取的 schema 分组数
+/// The maximum number of schema groups read separately when the files matched by a glob differ
+const MAX_SCHEMA_GROUPS: usize = 100;
 
 pub fn session() -> SessionContext {
-    let ctx = SessionContext::new();
+    let mut ctx = match get_config().query.memory_limit_mb {
+        // 超出内存限制的查询失败，排序等算子不会溢出到磁盘
+        // Queries beyond the memory limit fail, operators such as sorts do not spill to disk
+        Some(memory_limit) => match RuntimeEnvBuilder::new()
+            .with_memory_limit(memory_limit * 1024 * 1024, 1.0)
+            .build_arc()
+        {
+            Ok(runtime) => SessionContext::new_with_config_rt(SessionConfig::new(), runtime),
+            Err(e) => {
+                eprintln!("Error: failed to apply the query memory limit: {:?}", e);
+                SessionContext::new()
+            }
+        },
+        None => SessionContext::new(),
+    };
+    if let Err(e) = register_json_functions(&mut ctx) {
+        eprintln!("Error: failed to register the JSON functions: {:?}", e);
+    }
     // ctx.copied_config()
     //     .options_mut()
     //     .execution
@@ -23,50 +89,297 @@ pub fn session() -> SessionContext {
     ctx
 }
 
-pub async fn get_data_frame(ctx: &SessionContext, sql: &String) -> Result<DataFrame, Exception> {
+pub async fn get_data_frame(ctx: &SessionContext, sql: &str) -> Result<DataFrame, Exception> {
     ctx.sql(sql).await.map_err(Exception::from)
 }
 
 pub fn get_data_dir() -> String {
-    env::var("DATA_DIR").unwrap_or(get_os().default_data_dir().to_string())
+    get_config().storage.data_dir.display().to_string()
 }
 
+/// 在阻塞线程池中注册表：注册时会同步地列出与读取本地或对象存储中的文件，不能占用 actix 的工作线程，
+/// 其中的异步部分仍由当前运行时驱动
+/// Register a table on the blocking thread pool, because registering lists and reads local or
+/// object store files synchronously and must not hold an actix worker thread, while its async
+/// parts are still driven by the current runtime
+async fn register_table_blocking(
+    table: &TableCatalog,
+    ctx: &SessionContext,
+) -> Result<Option<String>, Exception> {
+    let (table, ctx) = (table.clone(), ctx.clone());
+    let handle = Handle::current();
+    run_blocking(move || handle.block_on(register_table(&table, &ctx))).await
+}
+
+/// 注册表，当格式由文件内容推断得出时返回推断结果的说明
+/// Register a table, returning a description of the format when it was inferred from the file content
 pub async fn register_table(
-    table_ref: &String,
-    table_path: &String,
+    table: &TableCatalog,
     ctx: &SessionContext,
-) -> Result<(), Exception> {
-    let data_source_format = get_format(table_path);
+) -> Result<Option<String>, Exception> {
+    let table_ref = table.table_name.as_str();
+    let table_path = table.table_path.as_str();
+    let table_options = &table.table_options;
+    // 外部数据库的表不是文件，使用保存的凭据连接
+    // Tables in external databases are not files and connect with the saved credentials
+    if let Some(kind) = table_options.get("format").and_then(RemoteKind::from_name) {
+        let remote_table = table_options.get("table").ok_or_else(|| {
+            Exception::bad_request_error(format!("{} tables require a 'table' option", kind.name()))
+        })?;
+        let credentials = sqlite::get_credentials(table_ref)?;
+        let url = with_credentials(table_path, credentials.as_deref());
+        ctx.register_table(
+            table_ref,
+            Arc::new(RemoteTable::try_new(kind, &url, remote_table, &table.table_schema).await?),
+        )?;
+        return Ok(None);
+    }
     let table_path = if utils::is_relative_path(table_path) {
         format!("{}/{}", get_data_dir(), table_path)
     } else {
         table_path.to_string()
     };
+    // 设置 `cache` 选项时远程文件下载到临时目录后按本地文件读取
+    // With the `cache` option remote files are downloaded into the tmp dir and read as local files
+    let table_path = if !is_object_store_path(&table_path) {
+        table_path
+    } else if table_options.get_bool("cache")?.unwrap_or(false) {
+        cache_objects(&table_path, is_partitioned(&table_path, table_options)?)?
+    } else {
+        register_object_store(ctx, &table_path)?;
+        table_path
+    };
+    // 目录使用其中第一个文件确定扩展名与格式，避免读取 `_SUCCESS` 等标记文件
+    // A directory uses its first file to decide the extension and format, so marker files such as `_SUCCESS` are skipped
+    let sample_path = if is_dir(&table_path) {
+        find_table_files(&table_path, table_options)?
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| table_path.clone())
+    } else {
+        table_path.clone()
+    };
+    let file_extension = table_options
+        .get("file_extension")
+        .map(|extension| extension.to_string())
+        .unwrap_or_else(|| get_file_extension(&sample_path));
+
+    let mut table_options = table_options.clone();
+    let mut detected_format: Option<String> = None;
+    let data_source_format = match table_options.get("format") {
+        Some(name) => Some(get_format_by_name(name, &table_path).ok_or_else(|| {
+            Exception::bad_request_error(format!("Unsupported format '{}'", name))
+        })?),
+        None if table_options.get("pattern").is_some() || table_options.get("preset").is_some() => {
+            Some(DataSourceFormat::Log)
+        }
+        None if table_options.get("widths").is_some() => Some(DataSourceFormat::FixedWidth),
+        // Delta Lake 与 Iceberg 表是包含事务日志或元数据的目录
+        // Delta Lake and Iceberg tables are directories holding a transaction log or metadata
+        None => match detect_lake_format(&table_path) {
+            Some(lake_format) => Some(lake_format),
+            None => {
+                let data_source_format = get_format(&sample_path);
+                if is_ambiguous_format(&data_source_format) {
+                    match sniff_format(&sample_path)? {
+                        Some(sniff) => {
+                            detected_format = Some(format!(
+                                "Detected format of '{}': {}",
+                                table_path, sniff.description
+                            ));
+                            table_options.merge_defaults(&sniff.options);
+                            Some(sniff.format)
+                        }
+                        None => data_source_format,
+                    }
+                } else {
+                    data_source_format
+                }
+            }
+        },
+    };
+    // 非 UTF-8 的文本文件先转码为 UTF-8 副本
+    // Text files in another encoding than UTF-8 are transcoded into UTF-8 copies first
+    let table_path = match &data_source_format {
+        Some(
+            DataSourceFormat::CSV
+            | DataSourceFormat::TSV
+            | DataSourceFormat::NdJson { .. }
+            | DataSourceFormat::FixedWidth,
+        ) => match transcode_files(&table_path, &table_options)? {
+            Some((transcoded_path, description)) => {
+                table_options.insert("compression", "uncompressed");
+                if let Some(description) = description {
+                    detected_format = Some(match detected_format {
+                        Some(detected_format) => {
+                            format!("{}; {}", detected_format, description)
+                        }
+                        None => description,
+                    });
+                }
+                transcoded_path
+            }
+            None => table_path,
+        },
+        _ => table_path,
+    };
+    let table_options = &table_options;
 
     match data_source_format {
         Some(format) => match format {
-            DataSourceFormat::CSV => {
-                ctx.register_csv(table_ref, &table_path, CsvReadOptions::default())
-                    .await?;
-            }
-            DataSourceFormat::TSV => {
-                let mut options = CsvReadOptions::default();
-                options.delimiter = b'\t';
-                options.file_extension = ".tsv";
-                ctx.register_csv(table_ref, &table_path, options).await?;
+            DataSourceFormat::CSV | DataSourceFormat::TSV => {
+                let delimiter = match format {
+                    DataSourceFormat::TSV => b'\t',
+                    _ => b',',
+                };
+                register_files(
+                    ctx,
+                    table_ref,
+                    &table_path,
+                    &file_extension,
+                    table_options,
+                    None,
+                    |compression_type, file_extension| {
+                        Ok(get_csv_options(table_options, delimiter, file_extension)?
+                            .file_compression_type(compression_type)
+                            .to_listing_options(&ctx.copied_config(), ctx.copied_table_options()))
+                    },
+                )
+                .await?;
             }
             DataSourceFormat::JSON => {
                 return Err(Exception::bad_request_error(
                     "JSON files are currently not supported.",
                 ))
             }
-            DataSourceFormat::NdJson { file_extension } => {
-                let mut options = NdJsonReadOptions::default();
-                options.file_extension = &file_extension;
-                ctx.register_json(table_ref, &table_path, options).await?;
+            DataSourceFormat::NdJson { .. } => {
+                let max_records = table_options.get_usize("schema_infer_max_records")?;
+                register_files(
+                    ctx,
+                    table_ref,
+                    &table_path,
+                    &file_extension,
+                    table_options,
+                    None,
+                    |compression_type, file_extension| {
+                        let mut options = NdJsonReadOptions::default()
+                            .file_extension(file_extension)
+                            .file_compression_type(compression_type);
+                        if let Some(max_records) = max_records {
+                            options.schema_infer_max_records = max_records;
+                        }
+                        Ok(options
+                            .to_listing_options(&ctx.copied_config(), ctx.copied_table_options()))
+                    },
+                )
+                .await?;
             }
             DataSourceFormat::XLSX => {
-                ctx.register_batch(table_ref, from_file_to_record_batch(&table_path)?)?;
+                let options = ExcelOptions {
+                    sheet: table_options.get("sheet").map(|sheet| sheet.to_string()),
+                    range: table_options.get("range").map(|range| range.to_string()),
+                    metadata_columns: table_options.get_bool("metadata_columns")?.unwrap_or(false),
+                    strict_schema: table_options.get_bool("strict_schema")?.unwrap_or(false),
+                    infer_rows: table_options.get_usize("schema_infer_max_records")?,
+                    header: table_options.get_bool("header")?.unwrap_or(true),
+                    header_row: table_options.get_usize("header_row")?,
+                };
+                ctx.register_table(
+                    table_ref,
+                    Arc::new(ExcelTable::try_new(&table_path, &options)?),
+                )?;
+            }
+            DataSourceFormat::Parquet => {
+                register_files(
+                    ctx,
+                    table_ref,
+                    &table_path,
+                    &file_extension,
+                    table_options,
+                    None,
+                    |_, file_extension| {
+                        Ok(ParquetReadOptions::default()
+                            .file_extension(file_extension)
+                            .to_listing_options(&ctx.copied_config(), ctx.copied_table_options()))
+                    },
+                )
+                .await?;
+            }
+            DataSourceFormat::Arrow if is_object_store_path(&table_path) => {
+                register_object_arrow_files(ctx, table_ref, &table_path, table_options).await?;
+            }
+            DataSourceFormat::Arrow => {
+                register_files(
+                    ctx,
+                    table_ref,
+                    &table_path,
+                    &file_extension,
+                    table_options,
+                    None,
+                    |_, file_extension| Ok(get_arrow_listing_options(ctx, file_extension)),
+                )
+                .await?;
+            }
+            DataSourceFormat::Avro => {
+                let options = AvroOptions::try_new(table_options)?;
+                ctx.register_batch(
+                    table_ref,
+                    avro::from_file_to_record_batch(&table_path, &options)?,
+                )?;
+            }
+            DataSourceFormat::FixedWidth => {
+                let options = FixedWidthOptions::try_new(table_options, &table.table_schema)?;
+                ctx.register_batch(
+                    table_ref,
+                    fixed_width::from_file_to_record_batch(&table_path, &options)?,
+                )?;
+            }
+            DataSourceFormat::SQLite => {
+                ctx.register_table(
+                    table_ref,
+                    Arc::new(SqliteTable::try_new(
+                        &table_path,
+                        table_options.get("table"),
+                    )?),
+                )?;
+            }
+            DataSourceFormat::XML => {
+                let options = XmlOptions::try_new(table_options)?;
+                ctx.register_batch(
+                    table_ref,
+                    xml::from_file_to_record_batch(&table_path, &options)?,
+                )?;
+            }
+            DataSourceFormat::Delta | DataSourceFormat::Iceberg => {
+                register_lake_table(ctx, table_ref, &table_path, &format, table_options)?;
+            }
+            DataSourceFormat::Log => {
+                let options = LogOptions::try_new(table_options, &table.table_schema)?;
+                ctx.register_batch(
+                    table_ref,
+                    log::from_file_to_record_batch(&table_path, &options)?,
+                )?;
+            }
+            DataSourceFormat::Text => {
+                let schema = Arc::new(Schema::new(vec![Field::new("line", DataType::Utf8, true)]));
+                register_files(
+                    ctx,
+                    table_ref,
+                    &table_path,
+                    &file_extension,
+                    table_options,
+                    Some(schema),
+                    |compression_type, file_extension| {
+                        Ok(CsvReadOptions::default()
+                            .has_header(false)
+                            .delimiter(TEXT_DELIMITER)
+                            .quote(TEXT_QUOTE)
+                            .file_extension(file_extension)
+                            .file_compression_type(compression_type)
+                            .to_listing_options(&ctx.copied_config(), ctx.copied_table_options()))
+                    },
+                )
+                .await?;
             }
         },
         None => {
@@ -76,37 +389,602 @@ pub async fn register_table(
             )));
         }
     }
+    // 设置 `flatten` 选项时结构体列展开为 `a.b.c` 形式的列，便于直接过滤嵌套字段
+    // With the `flatten` option struct columns are expanded into `a.b.c` columns so nested fields can be filtered directly
+    if table_options.get_bool("flatten")?.unwrap_or(false) {
+        flatten_table(ctx, table_ref).await?;
+    }
+
+    Ok(detected_format)
+}
+
+/// 注册表并读取其 Arrow schema，转换为 `catalog.table_schema` 的存储格式
+/// Register a table and read its Arrow schema, converted into the format stored in `catalog.table_schema`
+pub async fn infer_table_schema(table: &TableCatalog) -> Result<Vec<TableFieldSchema>, Exception> {
+    let ctx = session();
+    register_table_blocking(table, &ctx).await?;
+    let data_frame = ctx.table(table.table_name.as_str()).await?;
+    Ok(data_frame
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| TableFieldSchema {
+            field: field.name().to_string(),
+            field_type: field.data_type().to_string(),
+            comment: None,
+        })
+        .collect())
+}
+
+/// 推断表的 schema，并逐个读取 glob 匹配的文件，报告同名列在不同文件中的类型冲突
+/// Infer the schema of a table and read the files matched by its glob one by one, reporting
+/// columns whose type differs between files
+pub async fn infer_table_schema_with_conflicts(
+    table: &TableCatalog,
+) -> Result<(Vec<TableFieldSchema>, Vec<String>), Exception> {
+    let table_schema = infer_table_schema(table).await;
+    let conflicts = find_type_conflicts(table).await?;
+    match table_schema {
+        Ok(table_schema) => Ok((table_schema, conflicts)),
+        Err(e) if conflicts.is_empty() => Err(e),
+        Err(_) => Err(Exception::bad_request_error(format!(
+            "Type conflicts across files: {}",
+            conflicts.join("; ")
+        ))),
+    }
+}
+
+async fn find_type_conflicts(table: &TableCatalog) -> Result<Vec<String>, Exception> {
+    let (table_path, fragment) = match table.table_path.find('#') {
+        Some(index) => table.table_path.split_at(index),
+        None => (table.table_path.as_str(), ""),
+    };
+    let table_path = if utils::is_relative_path(table_path) {
+        format!("{}/{}", get_data_dir(), table_path)
+    } else {
+        table_path.to_string()
+    };
+    let table_options = table.table_options.clone();
+    let files = run_blocking(move || {
+        // Delta Lake 与 Iceberg 表的 schema 来自表的元数据，不逐个读取数据文件
+        // The schema of a Delta Lake or Iceberg table comes from its metadata, not from each data file
+        let is_lake_table = match table_options.get("format") {
+            Some(format) => matches!(
+                get_format_by_name(format, &table_path),
+                Some(DataSourceFormat::Delta) | Some(DataSourceFormat::Iceberg)
+            ),
+            None => detect_lake_format(&table_path).is_some(),
+        };
+        if is_lake_table {
+            return Ok(Vec::new());
+        }
+        find_table_files(&table_path, &table_options)
+    })
+    .await?;
+    if files.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    // 单个文件没有分区路径，逐个读取时不使用分区列
+    // A single file has no partition path, so partition columns are not used when reading files one by one
+    let mut table_options = table.table_options.clone();
+    table_options.remove("partition_columns");
+    let mut field_types: Vec<(String, String, String)> = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut conflict_fields: Vec<String> = Vec::new();
+    for file in files.iter().take(MAX_CONFLICT_CHECK_FILES) {
+        let file_table = TableCatalog {
+            table_name: table.table_name.clone(),
+            table_path: format!("{}{}", file, fragment),
+            table_options: table_options.clone(),
+            table_schema: table.table_schema.clone(),
+        };
+        let file_schema = match infer_table_schema(&file_table).await {
+            Ok(file_schema) => file_schema,
+            Err(e) => {
+                conflicts.push(format!("Cannot read '{}': {}", file, e));
+                continue;
+            }
+        };
+        for field in file_schema {
+            match field_types.iter().find(|(name, _, _)| name == &field.field) {
+                Some((name, field_type, first_file))
+                    if field_type != &field.field_type && !conflict_fields.contains(name) =>
+                {
+                    conflicts.push(format!(
+                        "Column '{}' is {} in '{}' but {} in '{}'",
+                        name, field_type, first_file, field.field_type, file
+                    ));
+                    conflict_fields.push(name.clone());
+                }
+                Some(_) => {}
+                None => field_types.push((field.field, field.field_type, file.clone())),
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+/// 按压缩类型对文件分组并注册为 ListingTable，压缩与未压缩的文件可以在同一个 glob 下一起查询；
+/// glob 匹配多个文件时先逐个推断 schema 并合并，schema 一致时注册为一个 ListingTable，
+/// 不一致时按 schema 分组读取，并按合并后的 schema 对齐
+/// Group files by compression type and register them as listing tables, so compressed and
+/// uncompressed files under one glob can be queried together; when a glob matches several files
+/// their schemas are inferred and merged up front, files that all share it are registered as one
+/// listing table and otherwise they are read in groups of the same schema and aligned to the merged one
+async fn register_files<F>(
+    ctx: &SessionContext,
+    table_ref: &str,
+    table_path: &str,
+    file_extension: &str,
+    table_options: &TableOptions,
+    schema: Option<SchemaRef>,
+    listing_options: F,
+) -> Result<(), Exception>
+where
+    F: Fn(FileCompressionType, &str) -> Result<ListingOptions, Exception>,
+{
+    let metadata_columns = table_options.get_bool("metadata_columns")?.unwrap_or(false);
+    let strict_schema = table_options.get_bool("strict_schema")?.unwrap_or(false);
+    let partition_columns = get_partition_columns(table_path, table_options)?;
+    // 分区值从相对于表目录的路径中解析，因此不能逐个读取文件
+    // Partition values are parsed from the path relative to the table directory, so files cannot be read one by one
+    let mut schema = schema;
+    let per_file = partition_columns.is_empty()
+        && (metadata_columns || (schema.is_none() && find_files(table_path)?.len() > 1));
+    if per_file {
+        let file_groups = get_file_groups(table_path, table_options)?;
+        let schemas = match &schema {
+            Some(schema) => file_groups
+                .iter()
+                .map(|(_, table_paths)| (table_paths[0].clone(), schema.clone()))
+                .collect::<Vec<_>>(),
+            None => infer_file_schemas(ctx, &file_groups, &listing_options).await?,
+        };
+        let merged_schema = merge_schemas(&schemas, strict_schema)?;
+
+        if metadata_columns {
+            // 每个文件单独读取并在读取时为 `_row` 编号，合并时不再改变各文件内的行号
+            // Every file is read on its own and numbers `_row` while reading, so the union
+            // leaves the row numbers within each file unchanged
+            let merged_schema = with_metadata_fields(&merged_schema);
+            let mut data_frame: Option<DataFrame> = None;
+            for ((compression_type, table_paths), (file, file_schema)) in
+                file_groups.into_iter().zip(schemas)
+            {
+                let table = create_listing_table(
+                    ctx,
+                    compression_type,
+                    table_paths,
+                    "",
+                    &partition_columns,
+                    Some(file_schema),
+                    &listing_options,
+                )
+                .await?;
+                let table = MetadataTable::new(table, &get_source_path(&file));
+                let table = align_data_frame(ctx.read_table(Arc::new(table))?, &merged_schema)?;
+                data_frame = Some(match data_frame {
+                    Some(data_frame) => data_frame.union(table)?,
+                    None => table,
+                });
+            }
+            if let Some(data_frame) = data_frame {
+                ctx.register_table(table_ref, data_frame.into_view())?;
+            }
+            return Ok(());
+        }
+
+        if schemas
+            .iter()
+            .any(|(_, file_schema)| !is_same_schema(file_schema, &merged_schema))
+        {
+            return register_schema_groups(
+                ctx,
+                table_ref,
+                group_by_schema(file_groups, schemas)?,
+                &merged_schema,
+                &listing_options,
+            )
+            .await;
+        }
+        schema = Some(merged_schema);
+    }
 
+    let groups = get_compression_groups(table_path, table_options)?;
+    if !partition_columns.is_empty() && (metadata_columns || groups.len() > 1) {
+        return Err(Exception::bad_request_error(
+            "Partitioned tables support neither metadata columns nor mixed compression types",
+        ));
+    }
+    let mut tables = Vec::new();
+    for (compression_type, table_paths) in groups {
+        // 多个分组时逐个列出文件，不再按扩展名过滤
+        // With several groups the files are listed explicitly, so no extension filter is applied
+        let is_table_path = table_paths.len() == 1 && table_paths[0] == table_path;
+        let file_extension = if is_table_path { file_extension } else { "" };
+        tables.push(
+            create_listing_table(
+                ctx,
+                compression_type,
+                table_paths,
+                file_extension,
+                &partition_columns,
+                schema.clone(),
+                &listing_options,
+            )
+            .await?,
+        );
+    }
+
+    if tables.len() == 1 {
+        ctx.register_table(table_ref, tables.remove(0))?;
+        return Ok(());
+    }
+
+    let mut data_frame: Option<DataFrame> = None;
+    for table in tables {
+        let table = ctx.read_table(table)?;
+        data_frame = Some(match data_frame {
+            Some(data_frame) => data_frame.union_by_name(table)?,
+            None => table,
+        });
+    }
+    if let Some(data_frame) = data_frame {
+        ctx.register_table(table_ref, data_frame.into_view())?;
+    }
     Ok(())
 }
 
-pub async fn register_listing_table(sql: &String) -> Result<(SessionContext, String), Exception> {
-    let mut sql = sql.clone();
+/// 逐个推断文件的 schema，只读取用于推断的数据，不为每个文件创建 ListingTable
+/// Infer the schema of every file, reading only the data needed for inference without creating
+/// a listing table per file
+async fn infer_file_schemas<F>(
+    ctx: &SessionContext,
+    file_groups: &[(FileCompressionType, Vec<String>)],
+    listing_options: &F,
+) -> Result<Vec<(String, SchemaRef)>, Exception>
+where
+    F: Fn(FileCompressionType, &str) -> Result<ListingOptions, Exception>,
+{
+    let state = ctx.state();
+    let mut schemas = Vec::new();
+    for (compression_type, table_paths) in file_groups {
+        let options = listing_options(*compression_type, "")?;
+        for file in table_paths {
+            let schema = options
+                .infer_schema(&state, &parse_listing_table_url(file)?)
+                .await
+                .map_err(|e| {
+                    Exception::bad_request_error(format!("Cannot read '{}': {}", file, e))
+                })?;
+            schemas.push((file.clone(), schema));
+        }
+    }
+    Ok(schemas)
+}
+
+/// 将压缩类型与 schema 都相同的文件放在同一分组，分组过多时报错而不是为每个文件创建 ListingTable
+/// Put files with the same compression type and schema in one group, failing when there are too
+/// many groups instead of creating a listing table per file
+fn group_by_schema(
+    file_groups: Vec<(FileCompressionType, Vec<String>)>,
+    schemas: Vec<(String, SchemaRef)>,
+) -> Result<Vec<(FileCompressionType, SchemaRef, Vec<String>)>, Exception> {
+    let mut groups: Vec<(FileCompressionType, SchemaRef, Vec<String>)> = Vec::new();
+    for ((compression_type, table_paths), (_, schema)) in file_groups.into_iter().zip(schemas) {
+        match groups.iter_mut().find(|(group_type, group_schema, _)| {
+            *group_type == compression_type && is_same_schema(group_schema, &schema)
+        }) {
+            Some((_, _, files)) => files.extend(table_paths),
+            None => groups.push((compression_type, schema, table_paths)),
+        }
+    }
+    if groups.len() > MAX_SCHEMA_GROUPS {
+        return Err(Exception::bad_request_error(format!(
+            "The matched files have {} different schemas, at most {} can be merged; \
+             match fewer files or declare the schema",
+            groups.len(),
+            MAX_SCHEMA_GROUPS
+        )));
+    }
+    Ok(groups)
+}
+
+/// 每个 schema 分组注册为一个 ListingTable，按合并后的 schema 对齐后合并
+/// Register every schema group as one listing table and union them aligned to the merged schema
+async fn register_schema_groups<F>(
+    ctx: &SessionContext,
+    table_ref: &str,
+    groups: Vec<(FileCompressionType, SchemaRef, Vec<String>)>,
+    merged_schema: &SchemaRef,
+    listing_options: &F,
+) -> Result<(), Exception>
+where
+    F: Fn(FileCompressionType, &str) -> Result<ListingOptions, Exception>,
+{
+    let mut data_frame: Option<DataFrame> = None;
+    for (compression_type, schema, table_paths) in groups {
+        let table = create_listing_table(
+            ctx,
+            compression_type,
+            table_paths,
+            "",
+            &[],
+            Some(schema),
+            listing_options,
+        )
+        .await?;
+        let table = align_data_frame(ctx.read_table(table)?, merged_schema)?;
+        data_frame = Some(match data_frame {
+            Some(data_frame) => data_frame.union(table)?,
+            None => table,
+        });
+    }
+    if let Some(data_frame) = data_frame {
+        ctx.register_table(table_ref, data_frame.into_view())?;
+    }
+    Ok(())
+}
+
+/// 为一组文件创建 ListingTable，未给出 schema 时推断，读取单个文件失败时在错误中指出文件名
+/// Create a listing table for a group of files, inferring the schema unless one is given and
+/// naming the file when a single file cannot be read
+async fn create_listing_table<F>(
+    ctx: &SessionContext,
+    compression_type: FileCompressionType,
+    table_paths: Vec<String>,
+    file_extension: &str,
+    partition_columns: &[(String, DataType)],
+    schema: Option<SchemaRef>,
+    listing_options: &F,
+) -> Result<Arc<ListingTable>, Exception>
+where
+    F: Fn(FileCompressionType, &str) -> Result<ListingOptions, Exception>,
+{
+    let table_urls = table_paths
+        .iter()
+        .map(|table_path| parse_listing_table_url(table_path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let config = ListingTableConfig::new_with_multi_paths(table_urls).with_listing_options(
+        listing_options(compression_type, file_extension)?
+            .with_table_partition_cols(partition_columns.to_vec()),
+    );
+    let config = match schema {
+        Some(schema) => config.with_schema(schema),
+        None => match config.infer_schema(&ctx.state()).await {
+            Ok(config) => config,
+            Err(e) if file_extension.is_empty() && table_paths.len() == 1 => {
+                return Err(Exception::bad_request_error(format!(
+                    "Cannot read '{}': {}",
+                    table_paths[0], e
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok(Arc::new(ListingTable::try_new(config)?))
+}
+
+/// 将匹配的每个文件作为单独的分组，用于合并 schema 或添加文件级的虚拟列
+/// Put every matching file in its own group, used to merge schemas or add file-level virtual columns
+fn get_file_groups(
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<Vec<(FileCompressionType, Vec<String>)>, Exception> {
+    let compression_type = match table_options.get("compression") {
+        Some(compression) => Some(parse_compression_type(compression)?),
+        None => None,
+    };
+    let groups = find_files(table_path)?
+        .into_iter()
+        .map(|file| {
+            let file_compression_type =
+                compression_type.unwrap_or_else(|| get_compression_type(&file));
+            (file_compression_type, vec![file])
+        })
+        .collect::<Vec<_>>();
+
+    if groups.is_empty() {
+        return Err(Exception::file_not_found_error(table_path));
+    }
+    Ok(groups)
+}
+
+/// 根据 `compression` 选项或文件扩展名确定压缩类型，混合压缩的 glob 按文件拆分为多个分组
+/// Determine the compression type from the `compression` option or the file extensions,
+/// splitting a glob with mixed compression into one group per compression type
+fn get_compression_groups(
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<Vec<(FileCompressionType, Vec<String>)>, Exception> {
+    if let Some(compression) = table_options.get("compression") {
+        return Ok(vec![(
+            parse_compression_type(compression)?,
+            vec![table_path.to_string()],
+        )]);
+    }
+
+    let mut groups: Vec<(FileCompressionType, Vec<String>)> = Vec::new();
+    for file in find_table_files(table_path, table_options)? {
+        let compression_type = get_compression_type(&file);
+        match groups
+            .iter_mut()
+            .find(|(group, _)| *group == compression_type)
+        {
+            Some((_, files)) => files.push(file),
+            None => groups.push((compression_type, vec![file])),
+        }
+    }
+
+    if groups.len() > 1 {
+        Ok(groups)
+    } else {
+        let compression_type = groups
+            .first()
+            .map(|(compression_type, _)| *compression_type)
+            .unwrap_or_else(|| get_compression_type(table_path));
+        Ok(vec![(compression_type, vec![table_path.to_string()])])
+    }
+}
+
+fn get_csv_options<'a>(
+    table_options: &TableOptions,
+    default_delimiter: u8,
+    file_extension: &'a str,
+) -> Result<CsvReadOptions<'a>, Exception> {
+    let mut options = CsvReadOptions::default()
+        .delimiter(
+            table_options
+                .get_byte("delimiter")?
+                .unwrap_or(default_delimiter),
+        )
+        .file_extension(file_extension);
+    if let Some(header) = table_options.get_bool("header")? {
+        options = options.has_header(header);
+    }
+    if let Some(quote) = table_options.get_byte("quote")? {
+        options = options.quote(quote);
+    }
+    if let Some(escape) = table_options.get_byte("escape")? {
+        options = options.escape(escape);
+    }
+    if let Some(comment) = table_options.get_byte("comment")? {
+        options = options.comment(comment);
+    }
+    if let Some(max_records) = table_options.get_usize("schema_infer_max_records")? {
+        options = options.schema_infer_max_records(max_records);
+    }
+    Ok(options)
+}
+
+fn get_arrow_listing_options(ctx: &SessionContext, file_extension: &str) -> ListingOptions {
+    ArrowReadOptions {
+        file_extension,
+        ..Default::default()
+    }
+    .to_listing_options(&ctx.copied_config(), ctx.copied_table_options())
+}
+
+/// 对象存储中的 Arrow IPC 文件从文件尾读取 schema：DataFusion 按流推断 schema 时假定 schema 紧跟在
+/// 8 字节的文件头之后，而 arrow-rs 写出的文件头按 64 字节对齐；各文件 schema 不一致时逐个注册后按合并后的 schema 对齐
+/// Arrow IPC files in object storage take their schema from the footer: DataFusion infers the
+/// schema from a stream assuming it follows an 8-byte header, while arrow-rs pads the header to 64
+/// bytes; files with different schemas are registered one by one and aligned to the merged schema
+async fn register_object_arrow_files(
+    ctx: &SessionContext,
+    table_ref: &str,
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<(), Exception> {
+    let schemas = find_table_files(table_path, table_options)?
+        .into_iter()
+        .map(|file| {
+            let reader = FileReader::try_new(ObjectReader::open(&file)?, None)?;
+            Ok((file, reader.schema()))
+        })
+        .collect::<Result<Vec<_>, Exception>>()?;
+    let merged_schema = merge_schemas(
+        &schemas,
+        table_options.get_bool("strict_schema")?.unwrap_or(false),
+    )?;
+    if schemas
+        .iter()
+        .all(|(_, schema)| is_same_schema(schema, &merged_schema))
+    {
+        return register_files(
+            ctx,
+            table_ref,
+            table_path,
+            "",
+            table_options,
+            Some(merged_schema),
+            |_, file_extension| Ok(get_arrow_listing_options(ctx, file_extension)),
+        )
+        .await;
+    }
+
+    let metadata_columns = table_options.get_bool("metadata_columns")?.unwrap_or(false);
+    let merged_schema = match metadata_columns {
+        true => Arc::new(with_metadata_fields(&merged_schema)),
+        false => merged_schema,
+    };
+    let mut file_options = table_options.clone();
+    file_options.remove("metadata_columns");
+    let mut data_frame: Option<DataFrame> = None;
+    for (i, (file, schema)) in schemas.into_iter().enumerate() {
+        let file_table_ref = format!("{}_{}", table_ref, i);
+        register_files(
+            ctx,
+            &file_table_ref,
+            &file,
+            "",
+            &file_options,
+            Some(schema),
+            |_, file_extension| Ok(get_arrow_listing_options(ctx, file_extension)),
+        )
+        .await?;
+        let mut table = ctx.table_provider(file_table_ref.as_str()).await?;
+        if metadata_columns {
+            table = Arc::new(MetadataTable::new(table, &file));
+        }
+        let table = align_data_frame(ctx.read_table(table)?, &merged_schema)?;
+        data_frame = Some(match data_frame {
+            Some(data_frame) => data_frame.union(table)?,
+            None => table,
+        });
+    }
+    if let Some(data_frame) = data_frame {
+        ctx.register_table(table_ref, data_frame.into_view())?;
+    }
+    Ok(())
+}
+
+/// 注册 SQL 中引用的所有表，返回会话、改写后的 SQL 以及格式推断等提示信息
+/// Register every table referenced by the SQL, returning the session, the rewritten SQL and notices such as detected formats
+pub async fn register_listing_table(
+    sql: &str,
+) -> Result<(SessionContext, String, Vec<String>), Exception> {
+    let ctx = session();
+    let mut messages: Vec<String> = Vec::new();
+    let (mut sql, table_functions, table_references) = rewrite_table_functions(sql)?;
+    for table in &table_functions {
+        messages.extend(register_table_blocking(table, &ctx).await?);
+    }
+    for reference in &table_references {
+        let table = resolve_table_reference(reference)?;
+        messages.extend(register_table_blocking(&table, &ctx).await?);
+    }
+
     let table_names = get_table_names(&sql)?;
     if table_names.is_empty() {
         return Err(SQLError::sql_syntax_error("Table name is empty", &sql))?;
     }
 
-    let ctx = session();
     let conn = sqlite::conn();
     let mut tables: Vec<String> = Vec::new();
 
     let temp_tables = table_names
         .iter()
-        .filter(|name| match { get_format(name) } {
-            Some(_) => true,
-            None => {
+        .filter(|name| {
+            table_functions
+                .iter()
+                .chain(table_references.iter())
+                .all(|table| &table.table_name != *name)
+        })
+        .filter(|name| {
+            if is_file_path(name) {
+                true
+            } else {
                 tables.push(name.to_string());
                 false
             }
         })
         .map(|name| TableCatalog {
-            table_name: format!(
-                "temp_{}_{}",
-                Utc::now().timestamp(),
-                utils::generate_random_string(4)
-            ),
+            table_name: temp_table_name(),
             table_path: name.to_string(),
+            table_options: TableOptions::default(),
+            table_schema: Vec::new(),
         })
         .collect::<Vec<TableCatalog>>();
 
@@ -130,32 +1008,115 @@ pub async fn register_listing_table(sql: &String) -> Result<(SessionContext, Str
         }
     }
 
-    let placeholders = table_names
-        .iter()
-        .map(|_| "?")
-        .collect::<Vec<_>>()
-        .join(", ");
+    let placeholders = tables.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
     let catalog_sql = format!(
-        "SELECT table_ref, table_path FROM catalog WHERE table_ref IN ({})",
+        "SELECT table_ref, table_path, table_options, table_schema FROM catalog WHERE table_ref IN ({})",
         placeholders
     );
 
+    // 先读出全部行再注册，注册期间不持有 catalog 的读锁，其他请求可以写入
+    // Read every row before registering, so no read lock on the catalog is held while registering
+    // and other requests can write to it
     let mut stmt = conn.prepare(&catalog_sql)?;
-    let results = stmt.query_map(params_from_iter(tables.iter().map(|s| s.as_str())), |row| {
-        Ok(TableCatalog {
-            table_name: row.get(0)?,
-            table_path: row.get(1)?,
-        })
-    })?;
+    let results = stmt
+        .query_map(params_from_iter(tables.iter().map(|s| s.as_str())), |row| {
+            Ok(TableCatalog {
+                table_name: row.get(0)?,
+                table_path: row.get(1)?,
+                table_options: from_json_column(row, 2)?,
+                table_schema: from_json_column(row, 3)?,
+            })
+        })?
+        .collect::<Result<Vec<TableCatalog>, _>>()?;
+    drop(stmt);
+
+    for table in &results {
+        messages.extend(register_table_blocking(table, &ctx).await?);
+    }
+    Ok((ctx, sql, messages))
+}
+
+/// 时间旅行与 `table_history` 引用的表：`table_path` 是 catalog 中的表名时沿用其路径与选项，否则视为表的路径
+/// Tables referenced by time travel or `table_history`: a `table_path` naming a catalog table
+/// reuses its path and options, otherwise it is the path of the table
+fn resolve_table_reference(reference: &TableCatalog) -> Result<TableCatalog, Exception> {
+    let conn = sqlite::conn();
+    let catalog = conn
+        .query_row(
+            "SELECT table_path, table_options FROM catalog WHERE table_ref = ?1 AND type != 'TEMP'",
+            params![&reference.table_path],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    from_json_column::<TableOptions>(row, 1)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let mut table_options = reference.table_options.clone();
+    let table_path = match catalog {
+        Some((table_path, catalog_options)) => {
+            table_options.merge_defaults(&catalog_options);
+            table_path
+        }
+        None => reference.table_path.clone(),
+    };
+    let format = match table_options.get("format") {
+        Some(format) => get_format_by_name(format, &table_path),
+        None if utils::is_relative_path(&table_path) => {
+            detect_lake_format(&format!("{}/{}", get_data_dir(), table_path))
+        }
+        None => detect_lake_format(&table_path),
+    };
+    if !matches!(
+        format,
+        Some(DataSourceFormat::Delta) | Some(DataSourceFormat::Iceberg)
+    ) {
+        return Err(Exception::bad_request_error(format!(
+            "Time travel and table history need a Delta Lake or Iceberg table: {}",
+            table_path
+        )));
+    }
+    Ok(TableCatalog {
+        table_name: reference.table_name.clone(),
+        table_path,
+        table_options,
+        table_schema: Vec::new(),
+    })
+}
 
-    for item in results {
-        let item = item?;
-        register_table(&item.table_name, &item.table_path, &ctx).await?;
+pub async fn execute(ctx: &SessionContext, sql: &str) -> Result<Vec<RecordBatch>, Exception> {
+    let data_frame = get_data_frame(ctx, sql).await?;
+    let Some(timeout_secs) = get_config().query.timeout_secs else {
+        return Ok(data_frame.collect().await?);
+    };
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    let task_ctx = Arc::new(data_frame.task_ctx());
+    let plan = with_deadline(data_frame.create_physical_plan().await?, deadline)?;
+    match with_query_timeout(collect(plan, task_ctx)).await {
+        Err(_) if Instant::now() >= deadline => Err(query_timeout_error(timeout_secs)),
+        result => result,
+    }
+}
+
+/// 按配置的查询超时时间执行，超时后取消查询
+/// Run a query within the configured query timeout, cancelling it once the timeout elapses
+pub async fn with_query_timeout<T, E>(
+    query: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, Exception>
+where
+    Exception: From<E>,
+{
+    let Some(timeout_secs) = get_config().query.timeout_secs else {
+        return Ok(query.await?);
+    };
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), query).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(query_timeout_error(timeout_secs)),
     }
-    Ok((ctx, sql))
 }
 
-pub async fn execute(ctx: &SessionContext, sql: &String) -> Result<Vec<RecordBatch>, Exception> {
-    let data_frame = get_data_frame(&ctx, sql).await?;
-    data_frame.collect().await.map_err(Exception::from)
+fn query_timeout_error(timeout_secs: u64) -> Exception {
+    Exception::timeout_error(format!("The query did not finish within {}s", timeout_secs))
 }
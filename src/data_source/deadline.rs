@@ -0,0 +1,98 @@
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{DataFusionError, Result};
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{DisplayAs, DisplayFormatType, ExecutionPlan, PlanProperties};
+use futures::StreamExt;
+use std::any::Any;
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 为执行计划的每个算子加上截止时间，算子每输出一批数据时检查，超过后查询失败；
+/// DataFusion 读取本地文件时不会让出执行权，仅靠异步超时无法中断查询
+/// Add a deadline to every operator of an execution plan, checked whenever an operator outputs a
+/// batch so the query fails once it has passed; DataFusion does not yield while reading local
+/// files, so an async timeout alone cannot interrupt a query
+pub fn with_deadline(
+    plan: Arc<dyn ExecutionPlan>,
+    deadline: Instant,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    plan.transform_up(|input| {
+        let plan: Arc<dyn ExecutionPlan> = Arc::new(DeadlineExec::new(input, deadline));
+        Ok(Transformed::yes(plan))
+    })
+    .map(|plan| plan.data)
+}
+
+#[derive(Debug)]
+struct DeadlineExec {
+    input: Arc<dyn ExecutionPlan>,
+    deadline: Instant,
+    properties: PlanProperties,
+}
+
+impl DeadlineExec {
+    fn new(input: Arc<dyn ExecutionPlan>, deadline: Instant) -> Self {
+        DeadlineExec {
+            properties: input.properties().clone(),
+            input,
+            deadline,
+        }
+    }
+}
+
+impl DisplayAs for DeadlineExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "DeadlineExec")
+    }
+}
+
+impl ExecutionPlan for DeadlineExec {
+    fn name(&self) -> &str {
+        "DeadlineExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match <[_; 1]>::try_from(children) {
+            Ok([input]) => Ok(Arc::new(DeadlineExec::new(input, self.deadline))),
+            Err(_) => Err(DataFusionError::Internal(
+                "DeadlineExec expects one child".to_string(),
+            )),
+        }
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let deadline = self.deadline;
+        let stream = self.input.execute(partition, context)?;
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            stream.schema(),
+            stream.map(move |batch| match Instant::now() < deadline {
+                true => batch,
+                false => Err(DataFusionError::Execution(
+                    "The query deadline has passed".to_string(),
+                )),
+            }),
+        )))
+    }
+}
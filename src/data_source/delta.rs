@@ -0,0 +1,484 @@
+use crate::data_source::compression::open_decompressed;
+use crate::data_source::lake::{
+    join_path, read_text, strip_file_scheme, LakeFile, LakeSnapshot, TimeTravel,
+};
+use crate::data_source::utils::{find_files, get_modified_time};
+use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, Field, Fields, TimeUnit};
+use arrow::json::LineDelimitedWriter;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use bytes::Bytes;
+use datafusion::common::ScalarValue;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+const LOG_DIR: &str = "_delta_log";
+
+/// `_delta_log` 中的提交（`<version>.json`）与检查点（`<version>.checkpoint[.<part>.<parts>].parquet`）
+/// The commits (`<version>.json`) and checkpoints (`<version>.checkpoint[.<part>.<parts>].parquet`)
+/// in `_delta_log`
+struct DeltaLog {
+    commits: BTreeMap<i64, String>,
+    checkpoints: BTreeMap<i64, Vec<String>>,
+}
+
+impl DeltaLog {
+    fn list(table_path: &str) -> Result<Self, Exception> {
+        let log_dir = join_path(table_path, LOG_DIR);
+        let files = find_files(&format!("{}/", log_dir))?;
+        if files.is_empty() {
+            return Err(Exception::file_not_found_error(log_dir));
+        }
+
+        let mut commits = BTreeMap::new();
+        let mut checkpoint_parts: BTreeMap<(i64, usize), Vec<String>> = BTreeMap::new();
+        for file in files {
+            let name = file.rsplit('/').next().unwrap_or(&file);
+            let Some((version, kind)) = name.split_once('.') else {
+                continue;
+            };
+            let Ok(version) = version.parse::<i64>() else {
+                continue;
+            };
+            if kind == "json" {
+                commits.insert(version, file.clone());
+                continue;
+            }
+            let Some(part) = kind
+                .strip_prefix("checkpoint")
+                .and_then(|part| part.strip_suffix("parquet"))
+            else {
+                continue;
+            };
+            // 多文件检查点的文件名中包含分片序号与分片数，v2 检查点（文件名含 UUID）需要 sidecar 文件，不使用
+            // Multi-part checkpoints name the part and the number of parts, v2 checkpoints (with a
+            // UUID in the name) need sidecar files and are not used
+            let parts = match part.trim_matches('.').split_once('.') {
+                None if part == "." => 1,
+                Some((_, parts)) => match parts.parse::<usize>() {
+                    Ok(parts) => parts,
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            checkpoint_parts
+                .entry((version, parts))
+                .or_default()
+                .push(file.clone());
+        }
+
+        let mut checkpoints = BTreeMap::new();
+        for ((version, parts), files) in checkpoint_parts {
+            if files.len() == parts {
+                checkpoints.insert(version, files);
+            }
+        }
+        Ok(Self {
+            commits,
+            checkpoints,
+        })
+    }
+
+    fn latest_version(&self) -> Option<i64> {
+        self.commits
+            .keys()
+            .chain(self.checkpoints.keys())
+            .max()
+            .copied()
+    }
+
+    /// 提交的时间：优先使用提交信息中的时间戳，其次使用文件的修改时间
+    /// The time of a commit: the timestamp in the commit info, or the modification time of the file
+    fn commit_timestamp(&self, version: i64) -> Result<Option<i64>, Exception> {
+        let Some(file) = self.commits.get(&version) else {
+            return Ok(None);
+        };
+        let timestamp = read_actions(file)?
+            .iter()
+            .filter_map(|action| action.get("commitInfo"))
+            .find_map(|commit_info| {
+                commit_info
+                    .get("inCommitTimestamp")
+                    .or_else(|| commit_info.get("timestamp"))
+                    .and_then(|timestamp| timestamp.as_i64())
+            });
+        Ok(timestamp.or_else(|| {
+            get_modified_time(file)
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as i64)
+        }))
+    }
+
+    /// 确定要读取的版本，按时间旅行时为不晚于该时间的最新提交
+    /// Decide the version to read, the latest commit not after the time when travelling to a timestamp
+    fn resolve_version(&self, table_path: &str, time_travel: TimeTravel) -> Result<i64, Exception> {
+        let latest = self.latest_version().ok_or_else(|| {
+            Exception::bad_request_error(format!("'{}' has no Delta commits", table_path))
+        })?;
+        match time_travel {
+            TimeTravel::Latest => Ok(latest),
+            TimeTravel::Version(version) if (0..=latest).contains(&version) => Ok(version),
+            TimeTravel::Version(version) => Err(Exception::bad_request_error(format!(
+                "Version {} of '{}' does not exist, the latest version is {}",
+                version, table_path, latest
+            ))),
+            TimeTravel::Timestamp(timestamp) => {
+                let mut found = None;
+                for version in self.commits.keys() {
+                    match self.commit_timestamp(*version)? {
+                        Some(commit_timestamp) if commit_timestamp <= timestamp => {
+                            found = Some(*version)
+                        }
+                        Some(_) => break,
+                        None => {}
+                    }
+                }
+                found.ok_or_else(|| {
+                    Exception::bad_request_error(format!(
+                        "'{}' has no version at or before the timestamp {}",
+                        table_path, timestamp
+                    ))
+                })
+            }
+        }
+    }
+}
+
+/// 回放事务日志得到的表状态
+/// The table state obtained by replaying the transaction log
+#[derive(Default)]
+struct DeltaState {
+    metadata: Option<JsonValue>,
+    files: BTreeMap<String, JsonValue>,
+}
+
+impl DeltaState {
+    fn apply(&mut self, action: JsonValue) {
+        if let Some(add) = action.get("add") {
+            if let Some(path) = add.get("path").and_then(|path| path.as_str()) {
+                self.files.insert(path.to_string(), add.clone());
+            }
+        } else if let Some(remove) = action.get("remove") {
+            if let Some(path) = remove.get("path").and_then(|path| path.as_str()) {
+                self.files.remove(path);
+            }
+        } else if let Some(metadata) = action.get("metaData") {
+            self.metadata = Some(metadata.clone());
+        }
+    }
+}
+
+/// 读取 Delta Lake 表在某个版本的 schema 与数据文件：从不晚于该版本的最近检查点开始回放之后的提交
+/// Read the schema and data files of a Delta Lake table at one version, replaying the commits after
+/// the latest checkpoint not beyond that version
+pub fn read_snapshot(table_path: &str, time_travel: TimeTravel) -> Result<LakeSnapshot, Exception> {
+    let log = DeltaLog::list(table_path)?;
+    let version = log.resolve_version(table_path, time_travel)?;
+
+    let mut state = DeltaState::default();
+    let checkpoint = log.checkpoints.range(..=version).next_back();
+    let first_commit = match checkpoint {
+        Some((checkpoint_version, files)) => {
+            for action in read_checkpoint(files)? {
+                state.apply(action);
+            }
+            checkpoint_version + 1
+        }
+        None => 0,
+    };
+    for commit in first_commit..=version {
+        let file = log.commits.get(&commit).ok_or_else(|| {
+            Exception::bad_request_error(format!(
+                "Version {} of '{}' cannot be read, the commit {} is missing from the transaction log",
+                version, table_path, commit
+            ))
+        })?;
+        for action in read_actions(file)? {
+            state.apply(action);
+        }
+    }
+
+    let metadata = state.metadata.ok_or_else(|| {
+        Exception::bad_request_error(format!("'{}' has no Delta table metadata", table_path))
+    })?;
+    if let Some(mode) = metadata
+        .pointer("/configuration/delta.columnMapping.mode")
+        .and_then(|mode| mode.as_str())
+        .filter(|mode| *mode != "none")
+    {
+        return Err(Exception::bad_request_error(format!(
+            "Delta tables with column mapping mode '{}' are not supported",
+            mode
+        )));
+    }
+    let schema: JsonValue = serde_json::from_str(
+        metadata
+            .get("schemaString")
+            .and_then(|schema| schema.as_str())
+            .unwrap_or("{}"),
+    )?;
+    let partition_columns = metadata
+        .get("partitionColumns")
+        .and_then(|columns| columns.as_array())
+        .map(|columns| {
+            columns
+                .iter()
+                .filter_map(|column| column.as_str().map(|column| column.to_string()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let (partition_fields, fields): (Vec<Field>, Vec<Field>) = to_fields(&schema)?
+        .into_iter()
+        .partition(|field| partition_columns.contains(field.name()));
+
+    let mut files = Vec::new();
+    for (path, add) in state.files {
+        if add
+            .get("deletionVector")
+            .is_some_and(|vector| !vector.is_null())
+        {
+            return Err(Exception::bad_request_error(format!(
+                "Delta tables with deletion vectors are not supported, found one for '{}'",
+                path
+            )));
+        }
+        let partition_values = partition_fields
+            .iter()
+            .map(|field| {
+                let value = add
+                    .get("partitionValues")
+                    .and_then(|values| values.get(field.name()))
+                    .and_then(|value| value.as_str())
+                    .filter(|value| !value.is_empty());
+                match value {
+                    Some(value) => {
+                        ScalarValue::try_from_string(value.to_string(), field.data_type())
+                    }
+                    None => ScalarValue::try_new_null(field.data_type()),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        files.push(LakeFile {
+            path: resolve_path(table_path, &path),
+            size: add.get("size").and_then(|size| size.as_u64()).unwrap_or(0),
+            partition_values,
+        });
+    }
+    Ok(LakeSnapshot {
+        fields,
+        partition_fields,
+        files,
+    })
+}
+
+/// 读取 Delta Lake 表的提交历史，最新的提交在前
+/// Read the commit history of a Delta Lake table, the latest commit first
+pub fn read_history(table_path: &str) -> Result<RecordBatch, Exception> {
+    let log = DeltaLog::list(table_path)?;
+    let mut versions = Vec::new();
+    let mut timestamps = Vec::new();
+    let mut operations = Vec::new();
+    let mut parameters = Vec::new();
+    let mut metrics = Vec::new();
+    let mut engines = Vec::new();
+    for (version, file) in log.commits.iter().rev() {
+        let commit_info = read_actions(file)?
+            .into_iter()
+            .find_map(|mut action| action.get_mut("commitInfo").map(JsonValue::take))
+            .unwrap_or_default();
+        let to_json = |key: &str| commit_info.get(key).map(|value| value.to_string());
+        versions.push(*version);
+        timestamps.push(log.commit_timestamp(*version)?);
+        operations.push(
+            commit_info
+                .get("operation")
+                .and_then(|operation| operation.as_str())
+                .map(|operation| operation.to_string()),
+        );
+        parameters.push(to_json("operationParameters"));
+        metrics.push(to_json("operationMetrics"));
+        engines.push(
+            commit_info
+                .get("engineInfo")
+                .or_else(|| commit_info.get("clientVersion"))
+                .and_then(|engine| engine.as_str())
+                .map(|engine| engine.to_string()),
+        );
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(versions)),
+        Arc::new(TimestampMillisecondArray::from(timestamps).with_timezone("UTC")),
+        Arc::new(StringArray::from(operations)),
+        Arc::new(StringArray::from(parameters)),
+        Arc::new(StringArray::from(metrics)),
+        Arc::new(StringArray::from(engines)),
+    ];
+    let schema = arrow::datatypes::Schema::new(vec![
+        Field::new("version", DataType::Int64, false),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("operation", DataType::Utf8, true),
+        Field::new("operation_parameters", DataType::Utf8, true),
+        Field::new("operation_metrics", DataType::Utf8, true),
+        Field::new("engine_info", DataType::Utf8, true),
+    ]);
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// 提交文件每行一个 JSON 动作
+/// A commit file holds one JSON action per line
+fn read_actions(file: &str) -> Result<Vec<JsonValue>, Exception> {
+    read_text(file)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(Exception::from))
+        .collect()
+}
+
+/// 检查点是 Parquet 文件，每行转换为与提交文件相同的 JSON 动作
+/// Checkpoints are Parquet files, every row is turned into the same JSON action as in commit files
+fn read_checkpoint(files: &[String]) -> Result<Vec<JsonValue>, Exception> {
+    let mut actions = Vec::new();
+    for file in files {
+        let mut data = Vec::new();
+        open_decompressed(file, FileCompressionType::UNCOMPRESSED)?.read_to_end(&mut data)?;
+        for batch in ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))?.build()? {
+            let mut writer = LineDelimitedWriter::new(Vec::new());
+            writer.write(&batch?)?;
+            writer.finish()?;
+            for line in writer.into_inner().split(|byte| *byte == b'\n') {
+                if !line.is_empty() {
+                    actions.push(serde_json::from_slice(line)?);
+                }
+            }
+        }
+    }
+    Ok(actions)
+}
+
+/// 数据文件的路径是 URL 编码的相对路径，或者是完整的 URI
+/// The path of a data file is a URL-encoded relative path, or a full URI
+fn resolve_path(table_path: &str, path: &str) -> String {
+    let path = urlencoding::decode(path)
+        .map(|path| path.into_owned())
+        .unwrap_or_else(|_| path.to_string());
+    if path.starts_with("file:") {
+        strip_file_scheme(&path).to_string()
+    } else if path.contains("://") {
+        path
+    } else {
+        join_path(table_path, &path)
+    }
+}
+
+/// 将 Delta 的 schema（`schemaString`）转换为 Arrow 字段
+/// Convert a Delta schema (the `schemaString`) into Arrow fields
+fn to_fields(schema: &JsonValue) -> Result<Vec<Field>, Exception> {
+    schema
+        .get("fields")
+        .and_then(|fields| fields.as_array())
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|field| {
+            let name = field
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            let nullable = field
+                .get("nullable")
+                .and_then(|nullable| nullable.as_bool())
+                .unwrap_or(true);
+            Ok(Field::new(
+                name,
+                to_data_type(field.get("type").unwrap_or(&JsonValue::Null))?,
+                nullable,
+            ))
+        })
+        .collect()
+}
+
+fn to_data_type(data_type: &JsonValue) -> Result<DataType, Exception> {
+    if let Some(name) = data_type.as_str() {
+        return Ok(match name {
+            "string" => DataType::Utf8,
+            "long" => DataType::Int64,
+            "integer" => DataType::Int32,
+            "short" => DataType::Int16,
+            "byte" => DataType::Int8,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "boolean" => DataType::Boolean,
+            "binary" => DataType::Binary,
+            "date" => DataType::Date32,
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            name => match name
+                .strip_prefix("decimal(")
+                .and_then(|name| name.strip_suffix(')'))
+                .and_then(|name| name.split_once(','))
+            {
+                Some((precision, scale)) => DataType::Decimal128(
+                    precision.trim().parse().unwrap_or(38),
+                    scale.trim().parse().unwrap_or(0),
+                ),
+                None => {
+                    return Err(Exception::bad_request_error(format!(
+                        "Unsupported Delta type '{}'",
+                        name
+                    )))
+                }
+            },
+        });
+    }
+    match data_type.get("type").and_then(|kind| kind.as_str()) {
+        Some("struct") => Ok(DataType::Struct(Fields::from(to_fields(data_type)?))),
+        Some("array") => {
+            let contains_null = data_type
+                .get("containsNull")
+                .and_then(|contains_null| contains_null.as_bool())
+                .unwrap_or(true);
+            Ok(DataType::List(Arc::new(Field::new(
+                "element",
+                to_data_type(data_type.get("elementType").unwrap_or(&JsonValue::Null))?,
+                contains_null,
+            ))))
+        }
+        Some("map") => {
+            let value_contains_null = data_type
+                .get("valueContainsNull")
+                .and_then(|contains_null| contains_null.as_bool())
+                .unwrap_or(true);
+            let entries = Field::new(
+                "key_value",
+                DataType::Struct(Fields::from(vec![
+                    Field::new(
+                        "key",
+                        to_data_type(data_type.get("keyType").unwrap_or(&JsonValue::Null))?,
+                        false,
+                    ),
+                    Field::new(
+                        "value",
+                        to_data_type(data_type.get("valueType").unwrap_or(&JsonValue::Null))?,
+                        value_contains_null,
+                    ),
+                ])),
+                false,
+            );
+            Ok(DataType::Map(Arc::new(entries), false))
+        }
+        _ => Err(Exception::bad_request_error(format!(
+            "Unsupported Delta type {}",
+            data_type
+        ))),
+    }
+}
@@ -0,0 +1,441 @@
+use crate::data_source::compression::split_compression_extension;
+use crate::data_source::context::{get_data_dir, infer_table_schema};
+use crate::data_source::lake::detect_lake_format;
+use crate::data_source::schema::{DataSourceFormat, TableOptions};
+use crate::data_source::sniff::sniff_format;
+use crate::data_source::utils::{find_files, get_file_extension, get_format, is_ambiguous_format};
+use crate::response::http_error::Exception;
+use crate::server::schema::TableCatalog;
+use crate::{sqlite, utils};
+use actix_web::rt::Runtime;
+use glob::Pattern;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::JoinHandle;
+
+#[derive(Serialize)]
+pub struct DiscoveredTable {
+    pub table_ref: String,
+    pub table_path: String,
+    pub status: String,
+}
+
+struct Candidate {
+    table_ref: String,
+    table_path: String,
+    files: Vec<String>,
+}
+
+/// 在独立线程中扫描 DATA_DIR，启动时不必等待推断所有文件的 schema 即可处理请求，返回扫描线程供监听等待
+/// Scan DATA_DIR on a thread of its own, so requests are served at startup without waiting for
+/// the schema of every file to be inferred, returning the scanning thread so the watcher can wait for it
+pub fn scan_data_dir_in_background() -> JoinHandle<()> {
+    thread::spawn(|| {
+        let result = Runtime::new()
+            .map_err(Exception::from)
+            .and_then(|runtime| runtime.block_on(scan_directory(None)));
+        match result {
+            Ok(tables) => println!("Discovered {} tables in the data directory", tables.len()),
+            Err(e) => eprintln!("Error: failed to scan the data directory: {:?}", e),
+        }
+    })
+}
+
+/// 扫描目录（默认为 DATA_DIR），按目录与文件名模式将文件归为表，推断 schema 后以 `AUTO` 类型写入 catalog，
+/// 不会覆盖用户创建的表
+/// Scan a directory (DATA_DIR by default), group files into tables by directory and file name pattern,
+/// infer their schemas and upsert them into the catalog as `AUTO` tables without touching user tables
+pub async fn scan_directory(path: Option<&str>) -> Result<Vec<DiscoveredTable>, Exception> {
+    let data_dir = PathBuf::from(get_data_dir());
+    let root = match path {
+        Some(path) if utils::is_relative_path(path) => data_dir.join(path),
+        Some(path) => PathBuf::from(path),
+        None => data_dir.clone(),
+    };
+    // 规范化路径，使 glob 匹配结果与扫描到的文件路径一致
+    // Canonicalize the paths so glob matches compare equal to the scanned file paths
+    let root = match fs::canonicalize(&root) {
+        Ok(root) if root.is_dir() => root,
+        _ => return Err(Exception::file_not_found_error(root.display().to_string())),
+    };
+    let data_dir = fs::canonicalize(&data_dir).unwrap_or(data_dir);
+    // 只允许扫描 DATA_DIR 之内的目录
+    // Only directories inside DATA_DIR may be scanned
+    if !root.starts_with(&data_dir) {
+        return Err(Exception::bad_request_error(format!(
+            "Cannot scan '{}', only directories inside the data directory '{}' can be scanned",
+            root.display(),
+            data_dir.display()
+        )));
+    }
+
+    let mut candidates = Vec::new();
+    if is_partitioned_dir(&root)? {
+        let table_path = format!("{}/", relative_location(&data_dir, &root));
+        candidates.push(Candidate {
+            table_ref: table_name(&table_path),
+            files: vec![root.display().to_string()],
+            table_path,
+        });
+    } else {
+        collect_candidates(&data_dir, &root, &mut candidates)?;
+    }
+    register_candidates(&data_dir, candidates).await
+}
+
+/// 为不属于任何表的新文件注册表，不重新扫描其所在目录：分区目录或 Delta Lake、Iceberg 表中的文件注册整个表目录，
+/// 其他文件单独注册，表名已被占用时使用文件名
+/// Register a table for a new file that belongs to no table without scanning its directory again:
+/// a file inside partition directories or a Delta Lake or Iceberg table registers the whole table
+/// directory, other files are registered on their own, named after the file when the name is taken
+pub async fn discover_file(path: &Path) -> Result<Vec<DiscoveredTable>, Exception> {
+    let data_dir = PathBuf::from(get_data_dir());
+    let data_dir = fs::canonicalize(&data_dir).unwrap_or(data_dir);
+    let path = fs::canonicalize(path)?;
+    if !path.starts_with(&data_dir) {
+        return Ok(Vec::new());
+    }
+    let names = sqlite::conn()
+        .prepare("select table_ref from catalog")?
+        .query_map([], |row| row.get::<usize, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let candidate = match get_table_dir(&data_dir, &path) {
+        Some(table_dir) => {
+            let table_path = format!("{}/", relative_location(&data_dir, &table_dir));
+            Candidate {
+                table_ref: unique_name(table_name(&table_path), &names),
+                files: vec![table_dir.display().to_string()],
+                table_path,
+            }
+        }
+        None => {
+            let file = path.display().to_string();
+            if !is_table_file(&file)? {
+                return Ok(Vec::new());
+            }
+            let parent = relative_location(&data_dir, path.parent().unwrap_or(&data_dir));
+            let name = table_name(&format!("{}/{}", parent, file_prefix(&file)));
+            let table_ref = if names.contains(&name) {
+                let name = table_name(&format!("{}/{}", parent, file_stem(&file)));
+                unique_name(name, &names)
+            } else {
+                name
+            };
+            Candidate {
+                table_ref,
+                table_path: relative_location(&data_dir, &path),
+                files: vec![file],
+            }
+        }
+    };
+    register_candidates(&data_dir, vec![candidate]).await
+}
+
+/// 文件所在的分区表或 Delta Lake、Iceberg 表的目录；分区目录直接位于 DATA_DIR 下时没有表目录
+/// The directory of the partitioned, Delta Lake or Iceberg table holding a file; partition
+/// directories right under DATA_DIR have no table directory
+fn get_table_dir(data_dir: &Path, path: &Path) -> Option<PathBuf> {
+    if let Some(table_dir) = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| *dir != data_dir)
+        .find(|dir| detect_lake_format(&dir.display().to_string()).is_some())
+    {
+        return Some(table_dir.to_path_buf());
+    }
+    let mut dir = path.parent()?;
+    let mut partitioned = false;
+    while dir != data_dir
+        && dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.contains('='))
+    {
+        partitioned = true;
+        dir = dir.parent()?;
+    }
+    Some(dir.to_path_buf()).filter(|dir| partitioned && dir != data_dir)
+}
+
+/// 将候选表推断 schema 后以 `AUTO` 类型写入 catalog，无法合并为一张表的文件逐个注册
+/// Infer the schema of the candidate tables and upsert them into the catalog as `AUTO` tables,
+/// registering files one by one when they cannot be read as one table
+async fn register_candidates(
+    data_dir: &Path,
+    candidates: Vec<Candidate>,
+) -> Result<Vec<DiscoveredTable>, Exception> {
+    let conn = sqlite::conn();
+    let mut queue = VecDeque::from(candidates);
+    let mut tables = Vec::new();
+    while let Some(candidate) = queue.pop_front() {
+        let table_type: Option<String> = conn
+            .query_row(
+                "select type from catalog where table_ref = ?1",
+                params![&candidate.table_ref],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if matches!(&table_type, Some(table_type) if table_type != "AUTO") {
+            tables.push(DiscoveredTable {
+                table_ref: candidate.table_ref,
+                table_path: candidate.table_path,
+                status: "skipped: a user table with the same name exists".to_string(),
+            });
+            continue;
+        }
+
+        let table = TableCatalog {
+            table_name: candidate.table_ref.clone(),
+            table_path: candidate.table_path.clone(),
+            table_options: TableOptions::default(),
+            table_schema: Vec::new(),
+        };
+        match infer_table_schema(&table).await {
+            Ok(table_schema) => {
+                conn.execute(
+                    r#"
+                        insert into catalog ( table_ref, table_path, table_schema, type )
+                        values
+                        (?1, ?2, ?3, 'AUTO')
+                        on conflict ( table_ref ) do update
+                        set table_path = excluded.table_path, table_schema = excluded.table_schema,
+                            status = 'OK'
+                        where catalog.type = 'AUTO'
+                        "#,
+                    params![
+                        &candidate.table_ref,
+                        &candidate.table_path,
+                        serde_json::to_string(&table_schema)?
+                    ],
+                )?;
+                tables.push(DiscoveredTable {
+                    table_ref: candidate.table_ref,
+                    table_path: candidate.table_path,
+                    status: match table_type {
+                        Some(_) => "updated".to_string(),
+                        None => "created".to_string(),
+                    },
+                });
+            }
+            // 同一模式下的文件无法合并为一张表时，逐个文件注册
+            // Fall back to one table per file when the files under a pattern cannot be read as one table
+            Err(_) if candidate.files.len() > 1 => {
+                let mut names = queue
+                    .iter()
+                    .map(|candidate| candidate.table_ref.clone())
+                    .chain(tables.iter().map(|table| table.table_ref.clone()))
+                    .collect::<Vec<_>>();
+                for file in candidate.files {
+                    let table_ref = unique_name(
+                        format!("{}_{}", candidate.table_ref, table_name(file_stem(&file))),
+                        &names,
+                    );
+                    names.push(table_ref.clone());
+                    queue.push_back(Candidate {
+                        table_ref,
+                        table_path: relative_location(data_dir, Path::new(&file)),
+                        files: vec![file],
+                    });
+                }
+            }
+            Err(e) => tables.push(DiscoveredTable {
+                table_ref: candidate.table_ref,
+                table_path: candidate.table_path,
+                status: format!("failed: {}", e),
+            }),
+        }
+    }
+    Ok(tables)
+}
+
+/// 递归收集候选表：含 `key=value` 子目录的目录作为分区表，其余文件按去掉编号后的文件名与扩展名分组
+/// Recursively collect candidate tables: directories with `key=value` subdirectories become
+/// partitioned tables, other files are grouped by their file name without numbering and extension
+fn collect_candidates(
+    data_dir: &Path,
+    dir: &Path,
+    candidates: &mut Vec<Candidate>,
+) -> Result<(), Exception> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| !name.starts_with(['_', '.']))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let mut groups: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for path in entries {
+        if path.is_dir() {
+            // Delta Lake 与 Iceberg 表的目录整体作为一张表
+            // The directory of a Delta Lake or Iceberg table is a single table
+            if is_partitioned_dir(&path)?
+                || detect_lake_format(&path.display().to_string()).is_some()
+            {
+                let table_path = format!("{}/", relative_location(data_dir, &path));
+                candidates.push(Candidate {
+                    table_ref: unique_candidate_name(candidates, &table_path, ""),
+                    files: vec![path.display().to_string()],
+                    table_path,
+                });
+            } else {
+                collect_candidates(data_dir, &path, candidates)?;
+            }
+            continue;
+        }
+
+        let file = path.display().to_string();
+        if !is_table_file(&file)? {
+            continue;
+        }
+        let file_extension = get_file_extension(&file);
+        let (extension, _) = split_compression_extension(&file_extension);
+        let prefix = file_prefix(&file).to_string();
+        groups
+            .entry((prefix, extension.to_string()))
+            .or_default()
+            .push(file);
+    }
+
+    for ((prefix, extension), files) in groups {
+        let location = if files.len() == 1 {
+            relative_location(data_dir, Path::new(&files[0]))
+        } else {
+            let compressed = files
+                .iter()
+                .any(|file| !split_compression_extension(file).1.is_empty());
+            let pattern = format!(
+                "{}*{}{}",
+                Pattern::escape(&prefix),
+                extension,
+                if compressed { "*" } else { "" }
+            );
+            relative_location(data_dir, &dir.join(pattern))
+        };
+
+        // glob 还匹配到其他文件时（如 `order*.csv` 匹配 `order_summary.csv`），逐个文件注册
+        // When the glob would match other files too (e.g. `order*.csv` matching `order_summary.csv`),
+        // register every file on its own
+        let location_path = if utils::is_relative_path(&location) {
+            data_dir.join(&location).display().to_string()
+        } else {
+            location.clone()
+        };
+        let mut matched_files = find_files(&location_path)?;
+        matched_files.sort();
+        if files.len() > 1 && matched_files != files {
+            for file in files {
+                let table_path = relative_location(data_dir, Path::new(&file));
+                let name = format!("{}/{}", relative_location(data_dir, dir), file_stem(&file));
+                candidates.push(Candidate {
+                    table_ref: unique_candidate_name(candidates, &name, &extension),
+                    table_path,
+                    files: vec![file],
+                });
+            }
+            continue;
+        }
+
+        let parent = relative_location(data_dir, dir);
+        let name = format!("{}/{}", parent, prefix);
+        candidates.push(Candidate {
+            table_ref: unique_candidate_name(candidates, &name, &extension),
+            table_path: location,
+            files,
+        });
+    }
+    Ok(())
+}
+
+/// 目录下存在 `key=value` 形式的子目录时视为分区表
+/// A directory with `key=value` subdirectories is treated as a partitioned table
+fn is_partitioned_dir(dir: &Path) -> Result<bool, Exception> {
+    Ok(fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.path().is_dir() && entry.file_name().to_string_lossy().contains('=')))
+}
+
+/// 扩展名可以确定格式，或文件内容能识别为结构化数据时才作为表，纯文本文件不会自动注册
+/// Only files whose extension or content identifies a structured format become tables,
+/// plain text files are not registered automatically
+fn is_table_file(file: &str) -> Result<bool, Exception> {
+    let format = get_format(file);
+    if !is_ambiguous_format(&format) {
+        return Ok(true);
+    }
+    Ok(sniff_format(file)
+        .ok()
+        .flatten()
+        .is_some_and(|sniff| sniff.format != DataSourceFormat::Text))
+}
+
+/// 位于 DATA_DIR 下的路径保存为相对路径，以便 DATA_DIR 迁移后仍然可用
+/// Paths under DATA_DIR are stored relative to it so they keep working if DATA_DIR moves
+fn relative_location(data_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(data_dir)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+fn file_stem(file: &str) -> &str {
+    let file_name = Path::new(file)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(file);
+    let extension = get_file_extension(file_name);
+    file_name.strip_suffix(&extension).unwrap_or(file_name)
+}
+
+/// 去掉编号后的文件名，同一目录下前缀与扩展名相同的文件归为一张表，例如 `order_1.csv` 的前缀为 `order`
+/// The file name without numbering, files in one directory with the same prefix and extension
+/// belong to one table, e.g. the prefix of `order_1.csv` is `order`
+fn file_prefix(file: &str) -> &str {
+    file_stem(file).trim_end_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '_' | '.'))
+}
+
+/// 将路径转换为合法的表名，例如 `sales/order_*` 转换为 `sales_order`
+/// Turn a path into a valid table name, e.g. `sales/order_*` into `sales_order`
+fn table_name(path: &str) -> String {
+    let name = path
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("t_{}", name)
+    } else {
+        name
+    }
+}
+
+fn unique_candidate_name(candidates: &[Candidate], path: &str, extension: &str) -> String {
+    let names = candidates
+        .iter()
+        .map(|candidate| candidate.table_ref.clone())
+        .collect::<Vec<_>>();
+    let name = table_name(path);
+    if names.contains(&name) && !extension.is_empty() {
+        unique_name(format!("{}_{}", name, table_name(extension)), &names)
+    } else {
+        unique_name(name, &names)
+    }
+}
+
+fn unique_name(name: String, names: &[String]) -> String {
+    if !names.contains(&name) {
+        return name;
+    }
+    (2..)
+        .map(|index| format!("{}_{}", name, index))
+        .find(|candidate| !names.contains(candidate))
+        .unwrap_or(name)
+}
@@ -0,0 +1,245 @@
+use crate::config::get_config;
+use crate::data_source::compression::{get_compression_type, open_decompressed};
+use crate::data_source::partition::find_table_files;
+use crate::data_source::schema::TableOptions;
+use crate::data_source::store::CACHE_DIR;
+use crate::data_source::utils::{find_files_recursive, get_local_copy_path, get_modified_time};
+use crate::response::http_error::Exception;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const DETECT_SIZE: usize = 64 * 1024;
+const BUFFER_SIZE: usize = 64 * 1024;
+/// 转码后的文件保存在临时目录下的该子目录中
+/// Transcoded files are kept in this subdirectory of the tmp dir
+const ENCODING_DIR: &str = "encoding";
+
+/// 解析 `encoding` 选项，支持 WHATWG 编码标签，例如 `gbk`、`gb18030`、`latin1`、`utf-16le`
+/// Parse the `encoding` option, which accepts WHATWG encoding labels such as `gbk`, `gb18030`,
+/// `latin1` or `utf-16le`
+pub fn parse_encoding(name: &str) -> Result<&'static Encoding, Exception> {
+    let label = match name.trim().to_lowercase().as_str() {
+        "utf-16" | "utf16" => "utf-16le".to_string(),
+        "utf16le" => "utf-16le".to_string(),
+        "utf16be" => "utf-16be".to_string(),
+        "latin-1" => "latin1".to_string(),
+        label => label.to_string(),
+    };
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| Exception::bad_request_error(format!("Unsupported encoding '{}'", name)))
+}
+
+/// 根据 BOM 与内容推断编码；没有 BOM 时先判断是否为 UTF-16，再检查 UTF-8，最后使用 chardetng 推测
+/// Detect the encoding from the BOM and content: without a BOM, check for UTF-16 and valid UTF-8
+/// before letting chardetng guess
+pub fn detect_encoding(buffer: &[u8]) -> (&'static Encoding, bool) {
+    if let Some((encoding, _)) = Encoding::for_bom(buffer) {
+        return (encoding, true);
+    }
+    if let Some(encoding) = detect_utf16(buffer) {
+        return (encoding, false);
+    }
+    if is_utf8(buffer) {
+        return (UTF_8, false);
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(buffer, buffer.len() < DETECT_SIZE);
+    (detector.guess(None, true), false)
+}
+
+/// 将文件开头的字节转换为 UTF-8，用于格式推断
+/// Convert the head of a file into UTF-8 for format sniffing
+pub fn decode_head(buffer: &[u8]) -> Vec<u8> {
+    let (encoding, has_bom) = detect_encoding(buffer);
+    if encoding == UTF_8 && !has_bom {
+        return buffer.to_vec();
+    }
+    let (text, _, _) = encoding.decode(buffer);
+    text.into_owned().into_bytes()
+}
+
+/// 文件不是 UTF-8 时（由 `encoding` 选项指定或自动识别），将匹配的文件解压并转码为 UTF-8 保存到临时目录，
+/// 返回转码后的表路径与识别结果的说明；文件修改前已转码的结果会被复用
+/// When the files are not UTF-8 (set by the `encoding` option or detected), decompress and transcode
+/// the matching files into UTF-8 copies in the tmp dir, returning the transcoded table path and a
+/// description of the detected encoding; copies are reused until the source file changes
+pub fn transcode_files(
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<Option<(String, Option<String>)>, Exception> {
+    let option = match table_options.get("encoding") {
+        Some(name) if name.eq_ignore_ascii_case("auto") => None,
+        Some(name) => Some(parse_encoding(name)?),
+        None => None,
+    };
+    if option == Some(UTF_8) {
+        return Ok(None);
+    }
+
+    let files = find_table_files(table_path, table_options)?;
+    let mut encodings = Vec::new();
+    let mut has_bom = false;
+    for file in &files {
+        let mut buffer = Vec::with_capacity(DETECT_SIZE);
+        open_decompressed(file, get_compression_type(file))?
+            .take(DETECT_SIZE as u64)
+            .read_to_end(&mut buffer)?;
+        let (encoding, bom) = detect_encoding(&buffer);
+        has_bom |= bom;
+        encodings.push(option.unwrap_or(encoding));
+    }
+    // 全部为不带 BOM 的 UTF-8 时不需要转码
+    // No transcoding is needed when every file is UTF-8 without a BOM
+    if !has_bom && encodings.iter().all(|encoding| *encoding == UTF_8) {
+        return Ok(None);
+    }
+
+    let root = get_transcode_root(table_path);
+    let mut targets = Vec::new();
+    for (file, encoding) in files.iter().zip(encodings.iter()) {
+        let target = get_local_copy_path(&root, file);
+        if !is_up_to_date(file, &target) {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            transcode_file(file, &target, encoding)?;
+        }
+        targets.push(target);
+    }
+    // 删除源文件已不存在的转码结果，避免 glob 匹配到过期的文件
+    // Remove copies whose source file is gone, so the glob does not match stale files
+    for file in find_files_recursive(&root.display().to_string())? {
+        if !targets.iter().any(|target| Path::new(&file) == target) {
+            fs::remove_file(&file)?;
+        }
+    }
+
+    let mut detected = encodings
+        .iter()
+        .map(|encoding| encoding.name())
+        .collect::<Vec<_>>();
+    detected.sort_unstable();
+    detected.dedup();
+    let description = match option {
+        Some(_) => None,
+        None => Some(format!(
+            "Detected encoding of '{}': {}",
+            table_path,
+            detected.join(", ")
+        )),
+    };
+    Ok(Some((
+        get_local_copy_path(&root, table_path).display().to_string(),
+        description,
+    )))
+}
+
+/// 转码后的文件或缓存的远程文件路径还原为源文件路径（或 URL），用于 `_file` 等虚拟列
+/// Map a transcoded file or a cached remote file back to its source file (or URL), used for
+/// virtual columns such as `_file`
+pub fn get_source_path(file: &str) -> String {
+    let tmp_dir = &get_config().storage.tmp_dir;
+    let path = match [ENCODING_DIR, CACHE_DIR]
+        .iter()
+        .find_map(|dir| Path::new(file).strip_prefix(tmp_dir.join(dir)).ok())
+    {
+        Some(path) => path,
+        None => return file.to_string(),
+    };
+    // 跳过表目录的哈希值，剩余部分为源文件的绝对路径或 URL；缓存的文件也可能再被转码
+    // Skip the hash of the table directory, the rest is the absolute path or URL of the source
+    // file; a cached file may have been transcoded as well
+    let source = path.components().skip(1).collect::<PathBuf>();
+    let source = source.display().to_string();
+    let source = match source.split_once(":/") {
+        Some((scheme, rest)) if !scheme.contains('/') => format!("{}://{}", scheme, rest),
+        _ => format!("/{}", source),
+    };
+    get_source_path(&source)
+}
+
+fn get_transcode_root(table_path: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    table_path.hash(&mut hasher);
+    get_config()
+        .storage
+        .tmp_dir
+        .join(ENCODING_DIR)
+        .join(format!("{:016x}", hasher.finish()))
+}
+
+fn is_up_to_date(file: &str, target: &Path) -> bool {
+    let target = fs::metadata(target).and_then(|metadata| metadata.modified());
+    match (get_modified_time(file), target) {
+        (Some(source), Ok(target)) => target >= source,
+        _ => false,
+    }
+}
+
+fn transcode_file(file: &str, target: &Path, encoding: &'static Encoding) -> Result<(), Exception> {
+    let mut reader = open_decompressed(file, get_compression_type(file))?;
+    let mut writer = BufWriter::new(File::create(target)?);
+    // BOM 决定实际的编码并被去除
+    // A BOM decides the actual encoding and is stripped
+    let mut decoder = encoding.new_decoder();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut output = String::with_capacity(BUFFER_SIZE * 3);
+    loop {
+        let read = reader.read(&mut buffer)?;
+        let last = read == 0;
+        let mut input = &buffer[..read];
+        loop {
+            output.clear();
+            let (result, consumed, _) = decoder.decode_to_string(input, &mut output, last);
+            writer.write_all(output.as_bytes())?;
+            input = &input[consumed..];
+            if result == encoding_rs::CoderResult::InputEmpty {
+                break;
+            }
+        }
+        if last {
+            break;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// 缓冲区末尾可能截断一个多字节字符，不视为无效
+/// A multi-byte character may be cut off at the end of the buffer, which is not treated as invalid
+fn is_utf8(buffer: &[u8]) -> bool {
+    match std::str::from_utf8(buffer) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// 没有 BOM 的 UTF-16 文本中，ASCII 字符的高字节为 0，零字节集中在偶数或奇数位置
+/// In UTF-16 text without a BOM the high byte of ASCII characters is 0, so the zero bytes
+/// cluster at even or odd positions
+fn detect_utf16(buffer: &[u8]) -> Option<&'static Encoding> {
+    let pairs = buffer.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let even = buffer.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd = buffer
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|byte| **byte == 0)
+        .count();
+    if odd * 2 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 2 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
@@ -1,126 +1,929 @@
-use crate::data_source::utils::find_files;
+use crate::data_source::compression::{
+    get_compression_type, open_decompressed, split_compression_extension,
+};
+use crate::data_source::merge::{merge_schemas, widen_data_type};
+use crate::data_source::metadata::{
+    get_modified, FILE_COLUMN, MODIFIED_COLUMN, MODIFIED_DATA_TYPE, ROW_COLUMN,
+};
+use crate::data_source::utils::{find_files, get_file_extension, get_modified_time};
 use crate::response::http_error::Exception;
-use arrow::datatypes::{DataType as ArrowDataType, Field, Schema};
+use arrow::array::new_null_array;
+use arrow::compute::cast as cast_array;
+use arrow::datatypes::{DataType as ArrowDataType, Field, Schema, SchemaRef, TimeUnit};
 use arrow_array::{
-    ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray,
+    ArrayRef, BooleanArray, Date32Array, DurationNanosecondArray, Float64Array, Int64Array,
+    RecordBatch, RecordBatchOptions, StringArray, TimestampNanosecondArray,
 };
-use calamine::{open_workbook, Data, DataType, HeaderRow, Range, Reader, Xlsx};
-use chrono::NaiveDateTime;
-use std::sync::Arc;
-
-pub fn from_file_to_record_batch(file_path: &str) -> Result<RecordBatch, Exception> {
-    let mut excel: (&str, Option<&str>) = (file_path, None);
-    if let Some(index) = file_path.find('#') {
-        excel = (&file_path[..index], Some(&file_path[index..]));
-    }
-
-    let files = find_files(file_path)?;
-    let mut schemas: Option<Vec<Field>> = None;
-    let mut records: Vec<Vec<Data>> = Vec::new();
-
-    for file in files {
-        let mut xlsx: Xlsx<_> = open_workbook(file)?;
-        let sheet = match excel.1 {
-            Some(s) => s,
-            None => {
-                let sheets = xlsx.sheet_names();
-                if sheets.is_empty() {
-                    return Err(Exception::unprocessable_entity_error("Sheets not found"));
-                }
-                match sheets.get(0) {
-                    Some(s) => &s.clone(),
-                    None => return Err(Exception::unprocessable_entity_error("Sheets not found")),
-                }
+use async_trait::async_trait;
+use calamine::{
+    open_workbook_auto_from_rs, open_workbook_from_rs, Data, Error, HeaderRow, Range, Reader,
+    Sheets,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::runtime::SpawnedTask;
+use datafusion::common::DataFusionError;
+use datafusion::datasource::TableType;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::{stream, SinkExt, StreamExt};
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// 默认用于推断列类型的数据行数
+/// The default number of data rows used to infer the column types
+const DEFAULT_INFER_ROWS: usize = 1000;
+/// 读取全部工作表时标识数据来源工作表的列
+/// The column naming the source sheet when all sheets are read
+const SHEET_COLUMN: &str = "_sheet";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const TIMESTAMP_DATA_TYPE: ArrowDataType = ArrowDataType::Timestamp(TimeUnit::Nanosecond, None);
+const DURATION_DATA_TYPE: ArrowDataType = ArrowDataType::Duration(TimeUnit::Nanosecond);
+/// 工作表的最大列数（XFD）与最大行数，超出的单元格引用无效
+/// The maximum number of columns (XFD) and rows of a sheet, cell references beyond them are invalid
+const MAX_COLUMNS: u32 = 16384;
+const MAX_ROWS: u32 = 1048576;
+/// 缓存的工作簿 schema 数量上限，超出时淘汰最久未使用的
+/// The maximum number of cached workbook schemas, the least recently used one is evicted beyond it
+const MAX_CACHED_SCHEMAS: usize = 1024;
+/// 缓存的工作簿数据的总字节数上限，超出时淘汰最久未使用的工作簿，更大的工作簿不缓存
+/// The maximum total size in bytes of the cached workbook data, the least recently used workbooks
+/// are evicted beyond it and larger workbooks are not cached
+const MAX_CACHED_BYTES: usize = 256 * 1024 * 1024;
+
+/// 工作簿中选中工作表的 schema 缓存，键为文件与读取选项，文件修改时间变化后失效
+/// Cache of the schemas of the selected sheets of workbooks keyed by the file and read options,
+/// invalidated when the file's modification time changes
+static SCHEMA_CACHE: LazyLock<Mutex<SchemaCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 缓存的工作簿 schema 及其最近一次使用的时间
+/// The cached workbook schemas with the time each was last used
+type SchemaCache = HashMap<String, (Arc<WorkbookSchema>, Instant)>;
+
+/// 已转换为 Arrow 批次的工作簿数据缓存，键为文件、读取选项与读取的列，不保留原始单元格，按总字节数限制大小
+/// Cache of workbook data converted into Arrow batches keyed by the file, read options and the
+/// columns read, without the raw cells and limited by its total size in bytes
+static WORKBOOK_CACHE: LazyLock<Mutex<HashMap<String, CachedWorkbook>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone)]
+pub struct ExcelOptions {
+    pub sheet: Option<String>,
+    pub range: Option<String>,
+    pub metadata_columns: bool,
+    pub strict_schema: bool,
+    pub infer_rows: Option<usize>,
+    pub header: bool,
+    pub header_row: Option<usize>,
+}
+
+/// 电子表格表：注册时只推断 schema（并按文件缓存），查询时每个文件在自己的分区中解析，
+/// 按需转换并分批输出
+/// A spreadsheet table: only the schema is inferred at registration (and cached per file), queries
+/// parse every file in its own partition and produce the rows in batches as they are converted
+#[derive(Debug)]
+pub struct ExcelTable {
+    workbooks: Vec<(Arc<WorkbookSource>, Arc<WorkbookSchema>)>,
+    schema: SchemaRef,
+    all_sheets: bool,
+    metadata_columns: bool,
+}
+
+impl ExcelTable {
+    pub fn try_new(file_path: &str, options: &ExcelOptions) -> Result<Self, Exception> {
+        // `file.xlsx#Sheet1!B3:H500` 中的片段指定工作表与单元格区域，优先于选项
+        // A fragment such as `file.xlsx#Sheet1!B3:H500` selects the sheet and cell range, overriding the options
+        let (file_path, sheet, range) = match file_path.split_once('#') {
+            Some((file_path, fragment)) => {
+                let (sheet, range) = match fragment.rsplit_once('!') {
+                    Some((sheet, range)) => (sheet, Some(range)),
+                    None => (fragment, None),
+                };
+                let sheet = sheet.trim().trim_matches('\'');
+                (
+                    file_path,
+                    Some(sheet).filter(|sheet| !sheet.is_empty()),
+                    range.or(options.range.as_deref()),
+                )
             }
+            None => (
+                file_path,
+                options.sheet.as_deref(),
+                options.range.as_deref(),
+            ),
+        };
+        let cell_range = match range {
+            Some(range) => Some(parse_cell_range(range)?),
+            None => None,
         };
 
-        let r = xlsx
-            .with_header_row(HeaderRow::Row(0))
-            .worksheet_range(&sheet)?;
+        let files = find_files(file_path)?;
+        if files.is_empty() {
+            return Err(Exception::file_not_found_error(file_path));
+        }
+        let workbooks = files
+            .into_iter()
+            .map(|file| {
+                let source = Arc::new(WorkbookSource {
+                    file,
+                    sheet: sheet.map(|sheet| sheet.to_string()),
+                    cell_range,
+                    options: options.clone(),
+                });
+                let schema = load_workbook_schema(&source)?;
+                Ok((source, schema))
+            })
+            .collect::<Result<Vec<_>, Exception>>()?;
 
-        if schemas.is_none() {
-            schemas = Some(get_header_schema(&r)?);
-            let schema = schemas.as_ref().unwrap();
-            for _ in schema {
-                records.push(Vec::new());
-            }
+        // 各个工作簿（工作表）的列可能不同，按合并后的 schema 对齐，`_sheet` 与元数据列放在最后
+        // The workbooks (and sheets) may have different columns, so they are aligned to the merged
+        // schema with `_sheet` and the metadata columns at the end
+        let schemas = workbooks
+            .iter()
+            .flat_map(|(source, workbook)| {
+                workbook.sheets.iter().map(move |sheet| {
+                    (
+                        format!("{}#{}", source.file, sheet.name),
+                        sheet.schema.clone(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let schema = merge_schemas(&schemas, options.strict_schema)?;
+        let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+        let all_sheets = sheet == Some("*");
+        if all_sheets {
+            fields.push(Arc::new(Field::new(
+                SHEET_COLUMN,
+                ArrowDataType::Utf8,
+                false,
+            )));
+        }
+        if options.metadata_columns {
+            fields.push(Arc::new(Field::new(
+                FILE_COLUMN,
+                ArrowDataType::Utf8,
+                false,
+            )));
+            fields.push(Arc::new(Field::new(
+                ROW_COLUMN,
+                ArrowDataType::Int64,
+                false,
+            )));
+            fields.push(Arc::new(Field::new(
+                MODIFIED_COLUMN,
+                MODIFIED_DATA_TYPE,
+                true,
+            )));
         }
 
-        for row in r.rows().skip(1) {
-            row.iter().enumerate().for_each(|(i, cell)| {
-                records.get_mut(i).unwrap().push(cell.clone());
+        Ok(Self {
+            workbooks,
+            schema: Arc::new(Schema::new(fields)),
+            all_sheets,
+            metadata_columns: options.metadata_columns,
+        })
+    }
+}
+
+#[async_trait]
+impl TableProvider for ExcelTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion::common::Result<Arc<dyn ExecutionPlan>> {
+        let projection = match projection {
+            Some(projection) => projection.clone(),
+            None => (0..self.schema.fields().len()).collect(),
+        };
+        let schema = Arc::new(self.schema.project(&projection)?);
+        // 每个文件一个分区，由 DataFusion 并行读取
+        // One partition per file, which DataFusion reads in parallel
+        let partitions = self
+            .workbooks
+            .iter()
+            .map(|(source, workbook)| {
+                Arc::new(ExcelPartition {
+                    source: source.clone(),
+                    workbook: workbook.clone(),
+                    schema: schema.clone(),
+                    all_sheets: self.all_sheets,
+                    metadata_columns: self.metadata_columns,
+                }) as Arc<dyn PartitionStream>
             })
+            .collect::<Vec<_>>();
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            partitions,
+            None,
+            Vec::new(),
+            false,
+            limit,
+        )?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ExcelPartition {
+    source: Arc<WorkbookSource>,
+    workbook: Arc<WorkbookSchema>,
+    schema: SchemaRef,
+    all_sheets: bool,
+    metadata_columns: bool,
+}
+
+type BatchSender = mpsc::Sender<datafusion::common::Result<RecordBatch>>;
+
+impl PartitionStream for ExcelPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let batch_size = ctx.session_config().batch_size().max(1);
+        let partition = self.clone();
+        // 解析与转换单元格是 CPU 密集的操作，在阻塞线程池中执行，每转换 batch_size 行发送一个批次，
+        // 查询结束（如达到 limit）后停止转换
+        // Parsing and converting cells is CPU bound, so it runs on the blocking thread pool and
+        // sends a batch every batch_size rows, stopping as soon as the consumer is done (e.g. the
+        // limit is reached)
+        let (mut sender, receiver) = mpsc::channel(2);
+        let task = SpawnedTask::spawn_blocking(move || {
+            if let Err(e) = partition.read(batch_size, &mut sender) {
+                let _ = block_on(sender.send(Err(DataFusionError::External(Box::new(e)))));
+            }
+        });
+        let batches = receiver.chain(stream::once(async move { task.join().await }).filter_map(
+            |result| async move {
+                result
+                    .err()
+                    .map(|e| Err(DataFusionError::External(Box::new(e))))
+            },
+        ));
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+impl ExcelPartition {
+    fn read(&self, batch_size: usize, sender: &mut BatchSender) -> Result<(), Exception> {
+        // 只转换查询用到的列，缓存按读取的列区分
+        // Only the columns the query uses are converted, so the cache is kept per set of columns
+        let names = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect::<Vec<_>>();
+        let key = format!("{}#{:?}", self.source.key(), names);
+        let modified = get_modified_time(&self.source.file);
+        if let Some(sheets) = get_cached_workbook(&key, modified) {
+            for (sheet, data) in self.workbook.sheets.iter().zip(sheets.iter()) {
+                let mut first_row = data.first_row;
+                for batch in &data.batches {
+                    if !self.send(sheet, batch, first_row, sender)? {
+                        return Ok(());
+                    }
+                    first_row += batch.num_rows() as i64;
+                }
+            }
+            return Ok(());
+        }
+
+        // 转换后的批次在不超过缓存上限时保留下来，供之后的查询使用
+        // The converted batches are kept for later queries while they fit in the cache
+        let mut workbook = open_workbook(&self.source.file)?;
+        let mut cached = Some((Vec::new(), 0));
+        for sheet in &self.workbook.sheets {
+            // 每个工作表的单元格只在转换期间保留
+            // The cells of each sheet are only kept while they are converted
+            let range = read_range(&mut workbook, &sheet.name, self.source.cell_range)?;
+            let columns = sheet
+                .schema
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| names.contains(&field.name().as_str()))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let schema = Arc::new(sheet.schema.project(&columns)?);
+            let data_start = get_data_start(&self.source.options);
+            let first_row = get_first_row(&range, data_start);
+            let rows = range.rows().skip(data_start).collect::<Vec<_>>();
+            let mut batches = Vec::new();
+            for (i, chunk) in rows.chunks(batch_size).enumerate() {
+                let batch = to_batch(chunk, &schema, &columns)?;
+                let batch_first_row = first_row + (i * batch_size) as i64;
+                if !self.send(sheet, &batch, batch_first_row, sender)? {
+                    return Ok(());
+                }
+                if let Some((_, bytes)) = &mut cached {
+                    *bytes += batch.get_array_memory_size();
+                    if *bytes > MAX_CACHED_BYTES {
+                        cached = None;
+                        batches.clear();
+                    } else {
+                        batches.push(batch);
+                    }
+                }
+            }
+            if let Some((sheets, _)) = &mut cached {
+                sheets.push(SheetBatches { first_row, batches });
+            }
+        }
+        if let Some((sheets, bytes)) = cached {
+            cache_workbook(key, modified, sheets, bytes);
         }
+        Ok(())
     }
 
-    let schemas = schemas.unwrap();
-    let mut arrays: Vec<ArrayRef> = vec![];
-
-    for (i, schema) in schemas.iter().enumerate() {
-        let column_data = &records[i];
-
-        let array: ArrayRef = match schema.data_type() {
-            ArrowDataType::Utf8 => Arc::new(StringArray::from(
-                column_data
-                    .iter()
-                    .map(|cell| cell.to_string())
-                    .collect::<Vec<_>>(),
-            )) as ArrayRef,
-            ArrowDataType::Int64 => Arc::new(Int64Array::from(
-                column_data
-                    .iter()
-                    .map(|cell| cell.as_i64())
-                    .collect::<Vec<_>>(),
-            )) as ArrayRef,
-            ArrowDataType::Float64 => Arc::new(Float64Array::from(
-                column_data
-                    .iter()
-                    .map(|cell| cell.as_f64())
-                    .collect::<Vec<_>>(),
-            )) as ArrayRef,
-            ArrowDataType::Timestamp(arrow::datatypes::TimeUnit::Nanosecond, None) => {
-                Arc::new(TimestampNanosecondArray::from(
-                    column_data
-                        .iter()
-                        .map(|cell| {
-                            let date_str = cell.to_string();
-                            NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%d %H:%M:%S")
-                                .map(|dt| dt.and_utc().timestamp_nanos_opt())
-                                .unwrap_or_default()
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef
+    /// 将工作表的批次对齐到表的 schema 并发送，查询已结束时返回 false
+    /// Align a batch of a sheet to the table's schema and send it, returning false once the
+    /// query is done
+    fn send(
+        &self,
+        sheet: &SheetSchema,
+        batch: &RecordBatch,
+        first_row: i64,
+        sender: &mut BatchSender,
+    ) -> Result<bool, Exception> {
+        let file = self.source.file.as_str();
+        let rows = batch.num_rows();
+        let mut arrays: Vec<ArrayRef> = Vec::new();
+        for field in self.schema.fields() {
+            let name = field.name().as_str();
+            let array: ArrayRef = match name {
+                SHEET_COLUMN if self.all_sheets => {
+                    Arc::new(StringArray::from(vec![sheet.name.as_str(); rows]))
+                }
+                FILE_COLUMN if self.metadata_columns => {
+                    Arc::new(StringArray::from(vec![file; rows]))
+                }
+                // `_row` 为工作表中的行号（从 1 开始）
+                // `_row` is the row number in the sheet (1-based)
+                ROW_COLUMN if self.metadata_columns => Arc::new(Int64Array::from_iter_values(
+                    first_row..first_row + rows as i64,
+                )),
+                MODIFIED_COLUMN if self.metadata_columns => {
+                    Arc::new(TimestampNanosecondArray::from(vec![
+                        get_modified(file);
+                        rows
+                    ]))
+                }
+                name => match batch.column_by_name(name) {
+                    Some(array) if array.data_type() == field.data_type() => array.clone(),
+                    Some(array) => cast_array(array, field.data_type())?,
+                    None => new_null_array(field.data_type(), rows),
+                },
+            };
+            arrays.push(array);
+        }
+        let options = RecordBatchOptions::new().with_row_count(Some(rows));
+        let batch = RecordBatch::try_new_with_options(self.schema.clone(), arrays, &options)?;
+        Ok(block_on(sender.send(Ok(batch))).is_ok())
+    }
+}
+
+/// 要读取的工作簿文件、工作表与读取选项
+/// The workbook file to read with the selected sheet and the read options
+#[derive(Debug)]
+struct WorkbookSource {
+    file: String,
+    sheet: Option<String>,
+    cell_range: Option<CellRange>,
+    options: ExcelOptions,
+}
+
+impl WorkbookSource {
+    fn key(&self) -> String {
+        format!(
+            "{}#{:?}#{:?}#{}#{:?}#{:?}",
+            self.file,
+            self.sheet,
+            self.cell_range,
+            self.options.header,
+            self.options.header_row,
+            self.options.infer_rows
+        )
+    }
+}
+
+/// 工作簿中选中的工作表及其推断出的 schema
+/// The selected sheets of a workbook with their inferred schemas
+#[derive(Debug)]
+struct WorkbookSchema {
+    modified: Option<SystemTime>,
+    sheets: Vec<SheetSchema>,
+}
+
+#[derive(Debug)]
+struct SheetSchema {
+    name: String,
+    schema: SchemaRef,
+}
+
+/// 缓存的工作簿数据：每个工作表转换后的批次，批次只包含查询读取的列
+/// Cached workbook data: the converted batches of every sheet, holding only the columns the query read
+#[derive(Debug)]
+struct CachedWorkbook {
+    modified: Option<SystemTime>,
+    sheets: Arc<Vec<SheetBatches>>,
+    bytes: usize,
+    used: Instant,
+}
+
+#[derive(Debug)]
+struct SheetBatches {
+    first_row: i64,
+    batches: Vec<RecordBatch>,
+}
+
+/// 读取工作簿的 schema，缓存中没有或文件已修改时解析工作簿推断，推断后不保留单元格
+/// Read the schema of a workbook, parsing the workbook to infer it when it is not cached or the
+/// file has changed; no cells are kept after inference
+fn load_workbook_schema(source: &WorkbookSource) -> Result<Arc<WorkbookSchema>, Exception> {
+    let key = source.key();
+    let modified = get_modified_time(&source.file);
+    {
+        let mut cache = SCHEMA_CACHE.lock().unwrap();
+        if let Some((schema, used)) = cache.get_mut(&key) {
+            if schema.modified.is_some() && schema.modified == modified {
+                *used = Instant::now();
+                return Ok(schema.clone());
             }
-            _ => unimplemented!(),
+        }
+    }
+
+    let schema = Arc::new(infer_workbook_schema(source, modified)?);
+    let mut cache = SCHEMA_CACHE.lock().unwrap();
+    cache.insert(key, (schema.clone(), Instant::now()));
+    while cache.len() > MAX_CACHED_SCHEMAS {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.remove(&key),
+            None => break,
         };
+    }
+    Ok(schema)
+}
 
-        arrays.push(array);
+fn get_cached_workbook(key: &str, modified: Option<SystemTime>) -> Option<Arc<Vec<SheetBatches>>> {
+    let mut cache = WORKBOOK_CACHE.lock().unwrap();
+    let workbook = cache.get_mut(key)?;
+    if workbook.modified.is_none() || workbook.modified != modified {
+        return None;
     }
+    workbook.used = Instant::now();
+    Some(workbook.sheets.clone())
+}
 
-    RecordBatch::try_new(Arc::new(Schema::new(schemas)), arrays).map_err(Exception::from)
+fn cache_workbook(
+    key: String,
+    modified: Option<SystemTime>,
+    sheets: Vec<SheetBatches>,
+    bytes: usize,
+) {
+    if modified.is_none() {
+        return;
+    }
+    let mut cache = WORKBOOK_CACHE.lock().unwrap();
+    cache.insert(
+        key,
+        CachedWorkbook {
+            modified,
+            sheets: Arc::new(sheets),
+            bytes,
+            used: Instant::now(),
+        },
+    );
+    while cache.values().map(|workbook| workbook.bytes).sum::<usize>() > MAX_CACHED_BYTES {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, workbook)| workbook.used)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.remove(&key),
+            None => break,
+        };
+    }
 }
 
-pub fn get_header_schema(r: &Range<Data>) -> Result<Vec<Field>, Exception> {
-    let schema = r.rows().next().map(|row| {
-        row.iter()
-            .map(|cell| {
-                let data_type: ArrowDataType = determine_data_type(cell);
-                Field::new(cell.to_string(), data_type, true)
-            })
-            .collect()
-    });
+/// 推断工作簿中选中工作表的 schema：`sheet` 可以是工作表名称、从 1 开始的序号，或 `*` 表示全部工作表
+/// Infer the schemas of the selected sheets of a workbook: `sheet` is a sheet name, a 1-based
+/// sheet index, or `*` for all sheets
+fn infer_workbook_schema(
+    source: &WorkbookSource,
+    modified: Option<SystemTime>,
+) -> Result<WorkbookSchema, Exception> {
+    let file = source.file.as_str();
+    let mut workbook = open_workbook(file)?;
+    let sheet_names = workbook.sheet_names();
+    if sheet_names.is_empty() {
+        return Err(Exception::unprocessable_entity_error("Sheets not found"));
+    }
+    let sheets = match source.sheet.as_deref() {
+        None => vec![sheet_names[0].clone()],
+        Some("*") => sheet_names.clone(),
+        Some(sheet) if sheet_names.iter().any(|name| name == sheet) => vec![sheet.to_string()],
+        Some(sheet) => match sheet.parse::<usize>() {
+            Ok(index) if index >= 1 && index <= sheet_names.len() => {
+                vec![sheet_names[index - 1].clone()]
+            }
+            _ => {
+                return Err(Exception::bad_request_error(format!(
+                    "Sheet '{}' not found in '{}', available sheets: {}",
+                    sheet,
+                    file,
+                    sheet_names.join(", ")
+                )))
+            }
+        },
+    };
+
+    let mut sheet_schemas = Vec::new();
+    for sheet in sheets {
+        let r = read_range(&mut workbook, &sheet, source.cell_range)?;
+        sheet_schemas.push(infer_sheet_schema(sheet, &r, &source.options)?);
+    }
+    Ok(WorkbookSchema {
+        modified,
+        sheets: sheet_schemas,
+    })
+}
 
-    schema.ok_or(Exception::unprocessable_entity_error("Header not found"))
+/// 读取压缩或未压缩的工作簿文件，按扩展名选择 xlsx、xls、xlsb 或 ods 读取器，扩展名未知时根据内容自动识别
+/// Read a compressed or uncompressed workbook file, picking the xlsx, xls, xlsb or ods reader from
+/// the extension and detecting it from the content when the extension is unknown
+fn open_workbook(file: &str) -> Result<Sheets<Cursor<Vec<u8>>>, Exception> {
+    // 压缩的工作簿（如 `.xlsx.gz`、`.xls.gz`）先解压到内存
+    // Compressed workbooks (e.g. `.xlsx.gz` or `.xls.gz`) are decompressed into memory first
+    let mut buffer = Vec::new();
+    open_decompressed(file, get_compression_type(file))?.read_to_end(&mut buffer)?;
+    let file_extension = get_file_extension(file);
+    let (extension, _) = split_compression_extension(&file_extension);
+    let cursor = Cursor::new(buffer);
+    let workbook = match extension {
+        ".xlsx" | ".xlsm" => Sheets::Xlsx(open_workbook_from_rs(cursor).map_err(Error::Xlsx)?),
+        ".xls" => Sheets::Xls(open_workbook_from_rs(cursor).map_err(Error::Xls)?),
+        ".xlsb" => Sheets::Xlsb(open_workbook_from_rs(cursor).map_err(Error::Xlsb)?),
+        ".ods" => Sheets::Ods(open_workbook_from_rs(cursor).map_err(Error::Ods)?),
+        _ => open_workbook_auto_from_rs(cursor)?,
+    };
+    Ok(workbook)
+}
+
+/// 读取工作表的单元格，给出单元格区域时只保留该区域
+/// Read the cells of a sheet, keeping only the cell range when one is given
+fn read_range(
+    workbook: &mut Sheets<Cursor<Vec<u8>>>,
+    sheet: &str,
+    cell_range: Option<CellRange>,
+) -> Result<Range<Data>, Exception> {
+    let r = workbook
+        .with_header_row(HeaderRow::Row(0))
+        .worksheet_range(sheet)?;
+    Ok(match cell_range {
+        Some((start, end)) => r.range(start, end),
+        None => r,
+    })
+}
+
+/// 数据行在区域中的起始位置：`header_row` 之前的行与表头被跳过
+/// The position of the first data row in the range: rows above `header_row` and the header are skipped
+fn get_data_start(options: &ExcelOptions) -> usize {
+    options.header_row.unwrap_or(1).saturating_sub(1) + options.header as usize
+}
+
+/// 第一个数据行在工作表中的行号（从 1 开始）
+/// The row number of the first data row in the sheet (1-based)
+fn get_first_row(r: &Range<Data>, data_start: usize) -> i64 {
+    r.start().map(|(row, _)| row as i64).unwrap_or(0) + data_start as i64 + 1
+}
+
+fn infer_sheet_schema(
+    name: String,
+    r: &Range<Data>,
+    options: &ExcelOptions,
+) -> Result<SheetSchema, Exception> {
+    // `header_row` 之前的行被跳过；没有表头时从该行开始都是数据，列名为 `column_N`
+    // Rows above `header_row` are skipped; without a header the data starts at that row and
+    // the columns are named `column_N`
+    let skip = options.header_row.unwrap_or(1).saturating_sub(1);
+    let names = if options.header {
+        let header = r
+            .rows()
+            .nth(skip)
+            .ok_or_else(|| Exception::unprocessable_entity_error("Header not found"))?;
+        get_column_names(header)
+    } else {
+        get_column_names(&vec![Data::Empty; r.width()])
+    };
+    let data_start = get_data_start(options);
+    let infer_rows = options.infer_rows.unwrap_or(DEFAULT_INFER_ROWS);
+
+    let fields = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let cells = r
+                .rows()
+                .skip(data_start)
+                .take(infer_rows)
+                .map(|row| row.get(i).unwrap_or(&Data::Empty));
+            Field::new(name, infer_data_type(cells), true)
+        })
+        .collect::<Vec<_>>();
+    Ok(SheetSchema {
+        name,
+        schema: Arc::new(Schema::new(fields)),
+    })
+}
+
+/// 将一批数据行中的指定列（工作表中的列序号）转换为 `schema` 的批次
+/// Convert the given columns (indices in the sheet) of a chunk of data rows into a batch of `schema`
+fn to_batch(
+    rows: &[&[Data]],
+    schema: &SchemaRef,
+    columns: &[usize],
+) -> Result<RecordBatch, Exception> {
+    let arrays = schema
+        .fields()
+        .iter()
+        .zip(columns)
+        .map(|(field, &i)| {
+            let cells = rows
+                .iter()
+                .map(|row| row.get(i).unwrap_or(&Data::Empty))
+                .collect::<Vec<_>>();
+            to_array(&cells, field.data_type())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    Ok(RecordBatch::try_new_with_options(
+        schema.clone(),
+        arrays,
+        &options,
+    )?)
+}
+
+type CellRange = ((u32, u32), (u32, u32));
+
+/// 解析 `A3:F200` 形式的单元格区域，返回从 0 开始的 (行, 列) 坐标
+/// Parse a cell range such as `A3:F200` into zero-based (row, column) positions
+fn parse_cell_range(range: &str) -> Result<CellRange, Exception> {
+    let invalid = || Exception::bad_request_error(format!("Invalid cell range '{}'", range));
+    let (start, end) = range.split_once(':').ok_or_else(invalid)?;
+    let start = parse_cell_reference(start).ok_or_else(invalid)?;
+    let end = parse_cell_reference(end).ok_or_else(invalid)?;
+    if start.0 > end.0 || start.1 > end.1 {
+        return Err(invalid());
+    }
+    Ok((start, end))
 }
 
-fn determine_data_type(cell: &Data) -> ArrowDataType {
+fn parse_cell_reference(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.trim().to_uppercase();
+    let index = cell.find(|c: char| c.is_ascii_digit())?;
+    let (column, row) = cell.split_at(index);
+    if column.is_empty() || !column.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let column = column.chars().try_fold(0u32, |acc, c| {
+        acc.checked_mul(26)?.checked_add(c as u32 - 'A' as u32 + 1)
+    })?;
+    let row = row.parse::<u32>().ok()?;
+    if column > MAX_COLUMNS || row > MAX_ROWS {
+        return None;
+    }
+    Some((row.checked_sub(1)?, column.checked_sub(1)?))
+}
+
+/// 表头为空的列命名为 `column_N`，重复的列名追加序号
+/// Columns with an empty header are named `column_N`, and duplicated names get a numeric suffix
+fn get_column_names(header: &[Data]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (i, cell) in header.iter().enumerate() {
+        let name = match cell_to_string(cell) {
+            Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+            _ => format!("column_{}", i + 1),
+        };
+        let name = if names.contains(&name) {
+            (2..)
+                .map(|index| format!("{}_{}", name, index))
+                .find(|candidate| !names.contains(candidate))
+                .unwrap_or(name)
+        } else {
+            name
+        };
+        names.push(name);
+    }
+    names
+}
+
+/// 根据数据行推断列类型，空单元格不参与推断，类型不一致时按 Int64 → Float64 → Utf8 放宽
+/// Infer a column type from its data rows, ignoring empty cells and widening differing types
+/// along Int64 → Float64 → Utf8
+fn infer_data_type<'a>(cells: impl Iterator<Item = &'a Data>) -> ArrowDataType {
+    let mut data_type: Option<ArrowDataType> = None;
+    for cell in cells {
+        let cell_type = match cell {
+            Data::Empty | Data::Error(_) => continue,
+            Data::Int(_) => ArrowDataType::Int64,
+            Data::Float(value) if is_integral(*value) => ArrowDataType::Int64,
+            Data::Float(_) => ArrowDataType::Float64,
+            Data::Bool(_) => ArrowDataType::Boolean,
+            Data::DateTime(value) if value.is_duration() => DURATION_DATA_TYPE,
+            Data::DurationIso(_) => DURATION_DATA_TYPE,
+            Data::DateTime(_) | Data::DateTimeIso(_) => match cell_to_datetime(cell) {
+                Some(datetime) if datetime.time() == NaiveTime::MIN => ArrowDataType::Date32,
+                Some(_) => TIMESTAMP_DATA_TYPE,
+                None => ArrowDataType::Utf8,
+            },
+            Data::String(_) => ArrowDataType::Utf8,
+        };
+        data_type = Some(match data_type {
+            None => cell_type,
+            Some(ArrowDataType::Date32) if cell_type == TIMESTAMP_DATA_TYPE => cell_type,
+            Some(TIMESTAMP_DATA_TYPE) if cell_type == ArrowDataType::Date32 => TIMESTAMP_DATA_TYPE,
+            Some(data_type) => widen_data_type(&data_type, &cell_type),
+        });
+    }
+    data_type.unwrap_or(ArrowDataType::Utf8)
+}
+
+/// 按列类型转换单元格，空单元格与无法转换的值为 null
+/// Convert the cells to the column type, with nulls for empty cells and values that cannot be converted
+fn to_array(cells: &[&Data], data_type: &ArrowDataType) -> Result<ArrayRef, Exception> {
+    let array: ArrayRef = match data_type {
+        ArrowDataType::Int64 => Arc::new(
+            cells
+                .iter()
+                .map(|cell| match cell {
+                    Data::Int(value) => Some(*value),
+                    Data::Float(value) if is_integral(*value) => Some(*value as i64),
+                    Data::String(value) => value.trim().parse().ok(),
+                    _ => None,
+                })
+                .collect::<Int64Array>(),
+        ),
+        ArrowDataType::Float64 => Arc::new(
+            cells
+                .iter()
+                .map(|cell| match cell {
+                    Data::Int(value) => Some(*value as f64),
+                    Data::Float(value) => Some(*value),
+                    Data::String(value) => value.trim().parse().ok(),
+                    _ => None,
+                })
+                .collect::<Float64Array>(),
+        ),
+        ArrowDataType::Boolean => Arc::new(
+            cells
+                .iter()
+                .map(|cell| match cell {
+                    Data::Bool(value) => Some(*value),
+                    Data::String(value) => value.trim().to_lowercase().parse().ok(),
+                    _ => None,
+                })
+                .collect::<BooleanArray>(),
+        ),
+        ArrowDataType::Date32 => Arc::new(
+            cells
+                .iter()
+                .map(|cell| {
+                    cell_to_datetime(cell)
+                        .map(|datetime| (datetime.date() - NaiveDate::default()).num_days() as i32)
+                })
+                .collect::<Date32Array>(),
+        ),
+        &TIMESTAMP_DATA_TYPE => Arc::new(
+            cells
+                .iter()
+                .map(|cell| {
+                    cell_to_datetime(cell)
+                        .and_then(|datetime| datetime.and_utc().timestamp_nanos_opt())
+                })
+                .collect::<TimestampNanosecondArray>(),
+        ),
+        &DURATION_DATA_TYPE => Arc::new(
+            cells
+                .iter()
+                .map(|cell| cell_to_duration(cell).and_then(|duration| duration.num_nanoseconds()))
+                .collect::<DurationNanosecondArray>(),
+        ),
+        ArrowDataType::Utf8 => Arc::new(
+            cells
+                .iter()
+                .map(|cell| cell_to_string(cell))
+                .collect::<StringArray>(),
+        ),
+        data_type => {
+            return Err(Exception::unprocessable_entity_error(format!(
+                "Unsupported spreadsheet column type {}",
+                data_type
+            )))
+        }
+    };
+    Ok(array)
+}
+
+fn is_integral(value: f64) -> bool {
+    value.fract() == 0.0 && value.abs() < i64::MAX as f64
+}
+
+fn cell_to_string(cell: &Data) -> Option<String> {
+    match cell {
+        Data::Empty | Data::Error(_) => None,
+        Data::String(value) => Some(value.clone()),
+        Data::DateTime(value) if value.is_duration() => Some(value.as_f64().to_string()),
+        Data::DateTime(_) => {
+            cell_to_datetime(cell).map(|datetime| datetime.format(DATETIME_FORMAT).to_string())
+        }
+        cell => Some(cell.to_string()),
+    }
+}
+
+fn cell_to_datetime(cell: &Data) -> Option<NaiveDateTime> {
+    match cell {
+        Data::DateTime(value) if value.is_datetime() => value.as_datetime(),
+        Data::DateTimeIso(value) | Data::String(value) => parse_datetime(value.trim()),
+        _ => None,
+    }
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        DATETIME_FORMAT,
+        "%Y-%m-%d %H:%M:%S%.f",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_time(NaiveTime::MIN))
+    })
+}
+
+fn cell_to_duration(cell: &Data) -> Option<Duration> {
     match cell {
-        calamine::Data::String(_) => ArrowDataType::Utf8,
-        calamine::Data::Float(_) => ArrowDataType::Float64,
-        calamine::Data::Int(_) => ArrowDataType::Int32,
-        calamine::Data::DateTime(_) => {
-            ArrowDataType::Timestamp(arrow::datatypes::TimeUnit::Nanosecond, None)
+        Data::DateTime(value) if value.is_duration() => value.as_duration(),
+        Data::DurationIso(value) => parse_iso_duration(value),
+        _ => None,
+    }
+}
+
+/// 解析 ISO 8601 时长，例如 `PT12H30M15S`、`P1DT2H`
+/// Parse an ISO 8601 duration such as `PT12H30M15S` or `P1DT2H`
+fn parse_iso_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().strip_prefix('P')?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            unit => {
+                let amount = number.parse::<f64>().ok()?;
+                number.clear();
+                let seconds = match (unit, in_time) {
+                    ('D', false) => 86_400.0,
+                    ('W', false) => 604_800.0,
+                    ('H', true) => 3_600.0,
+                    ('M', true) => 60.0,
+                    ('S', true) => 1.0,
+                    _ => return None,
+                };
+                duration += Duration::nanoseconds((amount * seconds * 1e9) as i64);
+            }
         }
-        _ => ArrowDataType::Utf8,
     }
+    Some(duration)
 }
@@ -0,0 +1,270 @@
+use crate::data_source::compression::{get_compression_type, open_decompressed};
+use crate::data_source::encoding::get_source_path;
+use crate::data_source::log::convert_column;
+use crate::data_source::metadata::{
+    get_modified, FILE_COLUMN, MODIFIED_COLUMN, MODIFIED_DATA_TYPE, ROW_COLUMN,
+};
+use crate::data_source::schema::TableOptions;
+use crate::data_source::utils::{find_files, parse_data_type};
+use crate::response::http_error::Exception;
+use crate::server::schema::TableFieldSchema;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray};
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+pub struct FixedWidthColumn {
+    pub name: Option<String>,
+    pub data_type: Option<DataType>,
+    /// 从 0 开始的字符位置
+    /// Zero-based character position
+    pub start: usize,
+    pub width: usize,
+}
+
+pub struct FixedWidthOptions {
+    pub columns: Vec<FixedWidthColumn>,
+    pub header: bool,
+    pub trim: bool,
+    pub timestamp_format: Option<String>,
+    pub metadata_columns: bool,
+}
+
+impl FixedWidthOptions {
+    /// 列位置来自 `CREATE TABLE` 中的列注释（如 `COMMENT 'start=1 width=10'`，位置从 1 开始），
+    /// 或按顺序排列的 `widths` 选项（如 `'10,20,8'`）
+    /// Column positions come from the column comments in `CREATE TABLE` (e.g.
+    /// `COMMENT 'start=1 width=10'`, 1-based), or from the `widths` option listing consecutive
+    /// column widths (e.g. `'10,20,8'`)
+    pub fn try_new(
+        table_options: &TableOptions,
+        table_schema: &[TableFieldSchema],
+    ) -> Result<Self, Exception> {
+        let declared = table_schema
+            .iter()
+            .map(|field| {
+                let data_type = parse_data_type(&field.field_type).ok_or_else(|| {
+                    Exception::bad_request_error(format!(
+                        "Unsupported type '{}' for column {}",
+                        field.field_type, field.field
+                    ))
+                })?;
+                let position = match &field.comment {
+                    Some(comment) => parse_position(comment)?,
+                    None => None,
+                };
+                Ok((field.field.clone(), data_type, position))
+            })
+            .collect::<Result<Vec<_>, Exception>>()?;
+
+        let columns = match table_options.get("widths") {
+            Some(widths) => {
+                let widths = widths
+                    .split(',')
+                    .map(|width| match width.trim().parse::<usize>() {
+                        Ok(width) if width > 0 => Ok(width),
+                        _ => Err(Exception::bad_request_error(format!(
+                            "Invalid width '{}' in the widths option",
+                            width.trim()
+                        ))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !declared.is_empty() && declared.len() != widths.len() {
+                    return Err(Exception::bad_request_error(format!(
+                        "The widths option lists {} columns but the table declares {}",
+                        widths.len(),
+                        declared.len()
+                    )));
+                }
+                let mut start = 0;
+                widths
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, width)| {
+                        let column = FixedWidthColumn {
+                            name: declared.get(i).map(|(name, _, _)| name.clone()),
+                            data_type: declared.get(i).map(|(_, data_type, _)| data_type.clone()),
+                            start,
+                            width,
+                        };
+                        start += width;
+                        column
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None if !declared.is_empty() => declared
+                .into_iter()
+                .map(|(name, data_type, position)| match position {
+                    Some((start, width)) => Ok(FixedWidthColumn {
+                        name: Some(name),
+                        data_type: Some(data_type),
+                        start,
+                        width,
+                    }),
+                    None => Err(Exception::bad_request_error(format!(
+                        "Column {} has no position, declare it with COMMENT 'start=1 width=10'",
+                        name
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => {
+                return Err(Exception::bad_request_error(
+                    "Fixed-width tables require column positions (COMMENT 'start=1 width=10') or a 'widths' option",
+                ))
+            }
+        };
+
+        Ok(FixedWidthOptions {
+            columns,
+            header: table_options.get_bool("header")?.unwrap_or(false),
+            trim: table_options.get_bool("trim")?.unwrap_or(true),
+            timestamp_format: table_options
+                .get("timestamp_format")
+                .map(|format| format.to_string()),
+            metadata_columns: table_options.get_bool("metadata_columns")?.unwrap_or(false),
+        })
+    }
+}
+
+/// 解析 `start=1 width=10` 形式的列位置，返回从 0 开始的位置与宽度；其他注释返回 `None`
+/// Parse a column position such as `start=1 width=10` into a zero-based start and width,
+/// returning `None` for other comments
+fn parse_position(comment: &str) -> Result<Option<(usize, usize)>, Exception> {
+    let mut start: Option<usize> = None;
+    let mut width: Option<usize> = None;
+    for part in comment
+        .split([' ', ',', ';'])
+        .filter(|part| !part.is_empty())
+    {
+        let (key, value) = match part.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        let value = value.parse::<usize>().ok();
+        match key.as_str() {
+            "start" => start = value,
+            "width" => width = value,
+            _ => {}
+        }
+    }
+    match (start, width) {
+        (None, None) => Ok(None),
+        (Some(start), Some(width)) if start > 0 && width > 0 => Ok(Some((start - 1, width))),
+        _ => Err(Exception::bad_request_error(format!(
+            "Invalid column position '{}', expected 'start=1 width=10'",
+            comment
+        ))),
+    }
+}
+
+/// 按字符位置切分每一行，未声明类型的列根据内容推断为 Int64、Float64 或 Utf8
+/// Split every line at the character positions, inferring Int64, Float64 or Utf8 for columns
+/// without a declared type
+pub fn from_file_to_record_batch(
+    file_path: &str,
+    options: &FixedWidthOptions,
+) -> Result<RecordBatch, Exception> {
+    let files = find_files(file_path)?;
+    if files.is_empty() {
+        return Err(Exception::file_not_found_error(file_path));
+    }
+
+    let mut header: Option<Vec<String>> = None;
+    let mut values: Vec<Vec<Option<String>>> = vec![Vec::new(); options.columns.len()];
+    let mut row_files: Vec<String> = Vec::new();
+    let mut row_numbers: Vec<i64> = Vec::new();
+    for file in &files {
+        let reader = BufReader::new(open_decompressed(file, get_compression_type(file))?);
+        let source = get_source_path(file);
+        for (line_number, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches('\r').chars().collect::<Vec<_>>();
+            if options.header && line_number == 0 {
+                if header.is_none() {
+                    header = Some(
+                        options
+                            .columns
+                            .iter()
+                            .map(|column| slice(&line, column, true).unwrap_or_default())
+                            .collect(),
+                    );
+                }
+                continue;
+            }
+            if line.iter().all(|c| c.is_whitespace()) {
+                continue;
+            }
+            for (column, values) in options.columns.iter().zip(values.iter_mut()) {
+                values.push(slice(&line, column, options.trim));
+            }
+            row_files.push(source.clone());
+            row_numbers.push(line_number as i64 + 1);
+        }
+    }
+
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for (i, (column, values)) in options.columns.iter().zip(values).enumerate() {
+        let name = match (&column.name, &header) {
+            (Some(name), _) => name.clone(),
+            (None, Some(header)) if !header[i].is_empty() => header[i].clone(),
+            _ => format!("column_{}", i + 1),
+        };
+        let data_type = column
+            .data_type
+            .clone()
+            .unwrap_or_else(|| infer_data_type(&values));
+        let array = StringArray::from(values);
+        arrays.push(convert_column(
+            &array,
+            &data_type,
+            options.timestamp_format.as_deref(),
+        )?);
+        fields.push(Field::new(name, data_type, true));
+    }
+
+    if options.metadata_columns {
+        let modified = row_files
+            .iter()
+            .map(|file| get_modified(file))
+            .collect::<Vec<_>>();
+        fields.push(Field::new(FILE_COLUMN, DataType::Utf8, false));
+        arrays.push(Arc::new(StringArray::from(row_files)));
+        fields.push(Field::new(ROW_COLUMN, DataType::Int64, false));
+        arrays.push(Arc::new(Int64Array::from(row_numbers)));
+        fields.push(Field::new(MODIFIED_COLUMN, MODIFIED_DATA_TYPE, true));
+        arrays.push(Arc::new(TimestampNanosecondArray::from(modified)));
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(Exception::from)
+}
+
+/// 截取列所在的字符，超出行尾的部分为空；去除空白后为空的值视为 null
+/// Take the characters of a column, with nothing past the end of the line; values that are
+/// empty after trimming are null
+fn slice(line: &[char], column: &FixedWidthColumn, trim: bool) -> Option<String> {
+    let start = column.start.min(line.len());
+    let end = (column.start + column.width).min(line.len());
+    let value = line[start..end].iter().collect::<String>();
+    if value.trim().is_empty() {
+        None
+    } else if trim {
+        Some(value.trim().to_string())
+    } else {
+        Some(value)
+    }
+}
+
+fn infer_data_type(values: &[Option<String>]) -> DataType {
+    let mut values = values.iter().flatten().map(|value| value.trim()).peekable();
+    if values.peek().is_none() {
+        DataType::Utf8
+    } else if values.clone().all(|value| value.parse::<i64>().is_ok()) {
+        DataType::Int64
+    } else if values.clone().all(|value| value.parse::<f64>().is_ok()) {
+        DataType::Float64
+    } else {
+        DataType::Utf8
+    }
+}
@@ -0,0 +1,42 @@
+use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, Fields};
+use datafusion::functions::core::expr_fn::get_field;
+use datafusion::logical_expr::Expr;
+use datafusion::prelude::{ident, SessionContext};
+
+/// 将表中的结构体列展开为 `a.b.c` 形式的列，列表等其他嵌套列保持不变
+/// Expand the struct columns of a table into columns named like `a.b.c`, other nested columns such as lists are kept
+pub async fn flatten_table(ctx: &SessionContext, table_ref: &str) -> Result<(), Exception> {
+    let data_frame = ctx.table(table_ref).await?;
+    let mut exprs = Vec::new();
+    for field in data_frame.schema().fields() {
+        flatten_column(
+            ident(field.name()),
+            field.name(),
+            field.data_type(),
+            &mut exprs,
+        );
+    }
+    let data_frame = data_frame.select(exprs)?;
+    ctx.deregister_table(table_ref)?;
+    ctx.register_table(table_ref, data_frame.into_view())?;
+    Ok(())
+}
+
+fn flatten_column(expr: Expr, name: &str, data_type: &DataType, exprs: &mut Vec<Expr>) {
+    match data_type {
+        DataType::Struct(fields) if !fields.is_empty() => flatten_struct(expr, name, fields, exprs),
+        _ => exprs.push(expr.alias(name)),
+    }
+}
+
+fn flatten_struct(expr: Expr, name: &str, fields: &Fields, exprs: &mut Vec<Expr>) {
+    for field in fields {
+        flatten_column(
+            get_field(expr.clone(), field.name().as_str()),
+            &format!("{}.{}", name, field.name()),
+            field.data_type(),
+            exprs,
+        );
+    }
+}
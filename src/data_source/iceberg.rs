@@ -0,0 +1,515 @@
+use crate::data_source::avro::{read_avro_file, AvroValue};
+use crate::data_source::lake::{
+    join_path, read_text, strip_file_scheme, LakeFile, LakeSnapshot, TimeTravel,
+};
+use crate::data_source::store::is_object_store_path;
+use crate::data_source::utils::find_files;
+use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, Field, Fields, TimeUnit};
+use arrow_array::{
+    ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray,
+};
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::sync::Arc;
+
+const METADATA_DIR: &str = "metadata";
+const METADATA_SUFFIX: &str = ".metadata.json";
+const VERSION_HINT: &str = "version-hint.text";
+
+/// Iceberg 表的元数据文件以及表目录，元数据中的路径以 `location` 为前缀，表被移动后按实际目录重新定位
+/// The metadata file of an Iceberg table and the table directory; paths in the metadata start with
+/// its `location`, so they are relocated to the actual directory when the table has been moved
+struct IcebergMetadata {
+    root: String,
+    location: String,
+    metadata: JsonValue,
+}
+
+impl IcebergMetadata {
+    /// 表路径可以是表目录或某个 `*.metadata.json` 文件；表目录优先使用 `version-hint.text`，否则使用版本号最大的元数据文件
+    /// The table path is either the table directory or a `*.metadata.json` file; a table directory
+    /// uses `version-hint.text`, or the metadata file with the highest version
+    fn read(table_path: &str) -> Result<Self, Exception> {
+        let (root, metadata_file) = if table_path.ends_with(METADATA_SUFFIX) {
+            let metadata_dir = parent(table_path);
+            (parent(metadata_dir).to_string(), table_path.to_string())
+        } else {
+            let root = table_path.trim_end_matches('/').to_string();
+            let metadata_file = find_metadata_file(&root)?;
+            (root, metadata_file)
+        };
+        let metadata: JsonValue = serde_json::from_str(&read_text(&metadata_file)?)?;
+        let location = metadata
+            .get("location")
+            .and_then(|location| location.as_str())
+            .map(|location| {
+                strip_file_scheme(location)
+                    .trim_end_matches('/')
+                    .to_string()
+            })
+            .unwrap_or_else(|| root.clone());
+        Ok(Self {
+            root,
+            location,
+            metadata,
+        })
+    }
+
+    fn resolve_path(&self, path: &str) -> String {
+        let path = strip_file_scheme(path);
+        match path.strip_prefix(self.location.as_str()) {
+            Some(relative) if relative.is_empty() || relative.starts_with('/') => {
+                join_path(&self.root, relative)
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    fn snapshots(&self) -> &[JsonValue] {
+        self.metadata
+            .get("snapshots")
+            .and_then(|snapshots| snapshots.as_array())
+            .map(|snapshots| snapshots.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn current_snapshot_id(&self) -> Option<i64> {
+        self.metadata
+            .get("current-snapshot-id")
+            .and_then(|id| id.as_i64())
+            .filter(|id| *id != -1)
+    }
+
+    /// 按时间旅行选择快照，表还没有快照时为 `None`
+    /// Select the snapshot to read for the time travel, `None` when the table has no snapshot yet
+    fn select_snapshot(
+        &self,
+        table_path: &str,
+        time_travel: TimeTravel,
+    ) -> Result<Option<&JsonValue>, Exception> {
+        let snapshot_id = match time_travel {
+            TimeTravel::Latest => self.current_snapshot_id(),
+            TimeTravel::Version(snapshot_id) => Some(snapshot_id),
+            TimeTravel::Timestamp(timestamp) => {
+                // 快照日志记录了每个快照成为当前快照的时间，没有日志时使用快照的创建时间
+                // The snapshot log records when every snapshot became current, without it the
+                // creation time of the snapshots is used
+                let mut log = match self
+                    .metadata
+                    .get("snapshot-log")
+                    .and_then(|log| log.as_array())
+                    .filter(|log| !log.is_empty())
+                {
+                    Some(log) => log
+                        .iter()
+                        .map(|entry| (entry.get("timestamp-ms"), entry.get("snapshot-id")))
+                        .collect::<Vec<_>>(),
+                    None => self
+                        .snapshots()
+                        .iter()
+                        .map(|snapshot| (snapshot.get("timestamp-ms"), snapshot.get("snapshot-id")))
+                        .collect(),
+                }
+                .into_iter()
+                .filter_map(|(timestamp, snapshot_id)| {
+                    Some((timestamp?.as_i64()?, snapshot_id?.as_i64()?))
+                })
+                .collect::<Vec<_>>();
+                log.sort();
+                let snapshot_id = log
+                    .into_iter()
+                    .take_while(|(snapshot_timestamp, _)| *snapshot_timestamp <= timestamp)
+                    .last()
+                    .map(|(_, snapshot_id)| snapshot_id);
+                Some(snapshot_id.ok_or_else(|| {
+                    Exception::bad_request_error(format!(
+                        "'{}' has no snapshot at or before the timestamp {}",
+                        table_path, timestamp
+                    ))
+                })?)
+            }
+        };
+        let Some(snapshot_id) = snapshot_id else {
+            return Ok(None);
+        };
+        self.snapshots()
+            .iter()
+            .find(|snapshot| {
+                snapshot.get("snapshot-id").and_then(|id| id.as_i64()) == Some(snapshot_id)
+            })
+            .map(Some)
+            .ok_or_else(|| {
+                Exception::bad_request_error(format!(
+                    "Snapshot {} of '{}' does not exist, available snapshots: {}",
+                    snapshot_id,
+                    table_path,
+                    self.snapshots()
+                        .iter()
+                        .filter_map(|snapshot| snapshot.get("snapshot-id"))
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+
+    /// 快照使用的 schema，格式版本 1 的元数据只有一个 `schema`
+    /// The schema used by a snapshot, metadata of format version 1 only has a single `schema`
+    fn schema(&self, snapshot: Option<&JsonValue>) -> Option<&JsonValue> {
+        let schema_id = snapshot
+            .and_then(|snapshot| snapshot.get("schema-id"))
+            .or_else(|| self.metadata.get("current-schema-id"))
+            .and_then(|id| id.as_i64());
+        self.metadata
+            .get("schemas")
+            .and_then(|schemas| schemas.as_array())
+            .and_then(|schemas| {
+                schemas
+                    .iter()
+                    .find(|schema| schema.get("schema-id").and_then(|id| id.as_i64()) == schema_id)
+            })
+            .or_else(|| self.metadata.get("schema"))
+    }
+
+    /// 快照的清单文件：格式版本 2 从清单列表（Avro）读取，格式版本 1 也可以直接列在快照中
+    /// The manifests of a snapshot, read from the manifest list (Avro) in format version 2 or
+    /// listed in the snapshot itself in format version 1
+    fn manifests(&self, snapshot: &JsonValue) -> Result<Vec<String>, Exception> {
+        if let Some(manifest_list) = snapshot.get("manifest-list").and_then(|list| list.as_str()) {
+            let (_, manifests) = read_avro_file(&self.resolve_path(manifest_list))?;
+            return Ok(manifests
+                .iter()
+                .filter_map(|manifest| manifest.get("manifest_path").and_then(AvroValue::as_str))
+                .map(|path| self.resolve_path(path))
+                .collect());
+        }
+        Ok(snapshot
+            .get("manifests")
+            .and_then(|manifests| manifests.as_array())
+            .map(|manifests| manifests.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|manifest| manifest.as_str())
+            .map(|path| self.resolve_path(path))
+            .collect())
+    }
+}
+
+/// 读取 Iceberg 表在某个快照的 schema 与数据文件
+/// Read the schema and data files of an Iceberg table at one snapshot
+pub fn read_snapshot(table_path: &str, time_travel: TimeTravel) -> Result<LakeSnapshot, Exception> {
+    let metadata = IcebergMetadata::read(table_path)?;
+    let snapshot = metadata.select_snapshot(table_path, time_travel)?;
+    let schema = metadata.schema(snapshot).ok_or_else(|| {
+        Exception::bad_request_error(format!("'{}' has no Iceberg schema", table_path))
+    })?;
+    let fields = to_fields(schema)?;
+
+    let mut files = Vec::new();
+    if let Some(snapshot) = snapshot {
+        for manifest in metadata.manifests(snapshot)? {
+            let (_, entries) = read_avro_file(&manifest)?;
+            for entry in entries {
+                // 状态 2 表示该文件已在此快照中删除
+                // Status 2 means the file was deleted in this snapshot
+                if entry.get("status").and_then(AvroValue::as_i64) == Some(2) {
+                    continue;
+                }
+                let Some(data_file) = entry.get("data_file") else {
+                    continue;
+                };
+                let path = data_file
+                    .get("file_path")
+                    .and_then(AvroValue::as_str)
+                    .unwrap_or_default();
+                if data_file
+                    .get("content")
+                    .and_then(AvroValue::as_i64)
+                    .unwrap_or(0)
+                    != 0
+                {
+                    return Err(Exception::bad_request_error(format!(
+                        "Iceberg tables with row-level deletes are not supported, found the delete file '{}'",
+                        path
+                    )));
+                }
+                let file_format = data_file
+                    .get("file_format")
+                    .and_then(AvroValue::as_str)
+                    .unwrap_or("PARQUET");
+                if !file_format.eq_ignore_ascii_case("parquet") {
+                    return Err(Exception::bad_request_error(format!(
+                        "Iceberg data files in the {} format are not supported: '{}'",
+                        file_format, path
+                    )));
+                }
+                files.push(LakeFile {
+                    path: metadata.resolve_path(path),
+                    size: data_file
+                        .get("file_size_in_bytes")
+                        .and_then(AvroValue::as_i64)
+                        .unwrap_or(0) as u64,
+                    partition_values: Vec::new(),
+                });
+            }
+        }
+    }
+    // Iceberg 的数据文件包含分区源列，因此不需要额外的分区列
+    // Iceberg data files contain the partition source columns, so no extra partition columns are needed
+    Ok(LakeSnapshot {
+        fields,
+        partition_fields: Vec::new(),
+        files,
+    })
+}
+
+/// 读取 Iceberg 表的快照历史，最新的快照在前
+/// Read the snapshot history of an Iceberg table, the latest snapshot first
+pub fn read_history(table_path: &str) -> Result<RecordBatch, Exception> {
+    let metadata = IcebergMetadata::read(table_path)?;
+    let mut snapshots = metadata.snapshots().iter().collect::<Vec<_>>();
+    snapshots.sort_by_key(|snapshot| {
+        std::cmp::Reverse(
+            snapshot
+                .get("timestamp-ms")
+                .and_then(|timestamp| timestamp.as_i64()),
+        )
+    });
+
+    // 从当前快照沿父快照回溯，得到当前状态的祖先快照
+    // Walk from the current snapshot through the parents to find the ancestors of the current state
+    let mut ancestors = Vec::new();
+    let mut snapshot_id = metadata.current_snapshot_id();
+    while let Some(id) = snapshot_id.filter(|id| !ancestors.contains(id)) {
+        ancestors.push(id);
+        snapshot_id = metadata
+            .snapshots()
+            .iter()
+            .find(|snapshot| snapshot.get("snapshot-id").and_then(|id| id.as_i64()) == Some(id))
+            .and_then(|snapshot| snapshot.get("parent-snapshot-id"))
+            .and_then(|id| id.as_i64());
+    }
+
+    let get_i64 =
+        |snapshot: &JsonValue, key: &str| snapshot.get(key).and_then(|value| value.as_i64());
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(
+            snapshots
+                .iter()
+                .map(|snapshot| get_i64(snapshot, "snapshot-id"))
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(Int64Array::from(
+            snapshots
+                .iter()
+                .map(|snapshot| get_i64(snapshot, "parent-snapshot-id"))
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(
+            TimestampMillisecondArray::from(
+                snapshots
+                    .iter()
+                    .map(|snapshot| get_i64(snapshot, "timestamp-ms"))
+                    .collect::<Vec<_>>(),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(StringArray::from(
+            snapshots
+                .iter()
+                .map(|snapshot| {
+                    snapshot
+                        .pointer("/summary/operation")
+                        .and_then(|operation| operation.as_str())
+                })
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            snapshots
+                .iter()
+                .map(|snapshot| snapshot.get("summary").map(|summary| summary.to_string()))
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            snapshots
+                .iter()
+                .map(|snapshot| {
+                    snapshot
+                        .get("manifest-list")
+                        .and_then(|manifest_list| manifest_list.as_str())
+                })
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(BooleanArray::from(
+            snapshots
+                .iter()
+                .map(|snapshot| {
+                    get_i64(snapshot, "snapshot-id").is_some_and(|id| ancestors.contains(&id))
+                })
+                .collect::<Vec<_>>(),
+        )),
+    ];
+    let schema = arrow::datatypes::Schema::new(vec![
+        Field::new("snapshot_id", DataType::Int64, true),
+        Field::new("parent_id", DataType::Int64, true),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("operation", DataType::Utf8, true),
+        Field::new("summary", DataType::Utf8, true),
+        Field::new("manifest_list", DataType::Utf8, true),
+        Field::new("is_current_ancestor", DataType::Boolean, false),
+    ]);
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// 优先使用 `version-hint.text` 指向的元数据文件，否则使用文件名中版本号最大的（`v3.metadata.json` 或 `00003-<uuid>.metadata.json`）
+/// Use the metadata file `version-hint.text` points to, or the one with the highest version in its
+/// name (`v3.metadata.json` or `00003-<uuid>.metadata.json`)
+fn find_metadata_file(root: &str) -> Result<String, Exception> {
+    let metadata_dir = join_path(root, METADATA_DIR);
+    if let Ok(hint) = read_text(&join_path(&metadata_dir, VERSION_HINT)) {
+        let file = join_path(
+            &metadata_dir,
+            &format!("v{}{}", hint.trim(), METADATA_SUFFIX),
+        );
+        if is_object_store_path(&file) || Path::new(&file).is_file() {
+            return Ok(file);
+        }
+    }
+    find_files(&join_path(&metadata_dir, &format!("*{}", METADATA_SUFFIX)))?
+        .into_iter()
+        .max_by_key(|file| {
+            let name = file.rsplit('/').next().unwrap_or(file);
+            let version = name
+                .trim_start_matches('v')
+                .split(['-', '.'])
+                .next()
+                .and_then(|version| version.parse::<i64>().ok());
+            (version, name.to_string())
+        })
+        .ok_or_else(|| {
+            Exception::file_not_found_error(format!("{}/*{}", metadata_dir, METADATA_SUFFIX))
+        })
+}
+
+fn parent(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+/// 将 Iceberg 的 schema 转换为 Arrow 字段
+/// Convert an Iceberg schema into Arrow fields
+fn to_fields(schema: &JsonValue) -> Result<Vec<Field>, Exception> {
+    schema
+        .get("fields")
+        .and_then(|fields| fields.as_array())
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|field| {
+            let name = field
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            let required = field
+                .get("required")
+                .and_then(|required| required.as_bool())
+                .unwrap_or(false);
+            Ok(Field::new(
+                name,
+                to_data_type(field.get("type").unwrap_or(&JsonValue::Null))?,
+                !required,
+            ))
+        })
+        .collect()
+}
+
+fn to_data_type(data_type: &JsonValue) -> Result<DataType, Exception> {
+    if let Some(name) = data_type.as_str() {
+        return Ok(match name {
+            "boolean" => DataType::Boolean,
+            "int" => DataType::Int32,
+            "long" => DataType::Int64,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "date" => DataType::Date32,
+            "time" => DataType::Time64(TimeUnit::Microsecond),
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            "timestamptz" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "timestamp_ns" => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            "timestamptz_ns" => DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            "string" => DataType::Utf8,
+            "uuid" => DataType::FixedSizeBinary(16),
+            "binary" => DataType::Binary,
+            name => {
+                if let Some(length) = name
+                    .strip_prefix("fixed[")
+                    .and_then(|name| name.strip_suffix(']'))
+                {
+                    DataType::FixedSizeBinary(length.trim().parse().unwrap_or(0))
+                } else if let Some((precision, scale)) = name
+                    .strip_prefix("decimal(")
+                    .and_then(|name| name.strip_suffix(')'))
+                    .and_then(|name| name.split_once(','))
+                {
+                    DataType::Decimal128(
+                        precision.trim().parse().unwrap_or(38),
+                        scale.trim().parse().unwrap_or(0),
+                    )
+                } else {
+                    return Err(Exception::bad_request_error(format!(
+                        "Unsupported Iceberg type '{}'",
+                        name
+                    )));
+                }
+            }
+        });
+    }
+    match data_type.get("type").and_then(|kind| kind.as_str()) {
+        Some("struct") => Ok(DataType::Struct(Fields::from(to_fields(data_type)?))),
+        Some("list") => {
+            let required = data_type
+                .get("element-required")
+                .and_then(|required| required.as_bool())
+                .unwrap_or(false);
+            Ok(DataType::List(Arc::new(Field::new(
+                "element",
+                to_data_type(data_type.get("element").unwrap_or(&JsonValue::Null))?,
+                !required,
+            ))))
+        }
+        Some("map") => {
+            let required = data_type
+                .get("value-required")
+                .and_then(|required| required.as_bool())
+                .unwrap_or(false);
+            let entries = Field::new(
+                "key_value",
+                DataType::Struct(Fields::from(vec![
+                    Field::new(
+                        "key",
+                        to_data_type(data_type.get("key").unwrap_or(&JsonValue::Null))?,
+                        false,
+                    ),
+                    Field::new(
+                        "value",
+                        to_data_type(data_type.get("value").unwrap_or(&JsonValue::Null))?,
+                        !required,
+                    ),
+                ])),
+                false,
+            );
+            Ok(DataType::Map(Arc::new(entries), false))
+        }
+        _ => Err(Exception::bad_request_error(format!(
+            "Unsupported Iceberg type {}",
+            data_type
+        ))),
+    }
+}
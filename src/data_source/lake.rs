@@ -0,0 +1,400 @@
+use crate::data_source::compression::open_decompressed;
+use crate::data_source::schema::{DataSourceFormat, TableOptions};
+use crate::data_source::store::{get_object_store_location, is_object_store_path};
+use crate::data_source::utils::find_files;
+use crate::data_source::{delta, iceberg};
+use crate::response::http_error::Exception;
+use arrow::array::AsArray;
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow_array::{Array, RecordBatch};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{Column, DFSchema, ScalarValue};
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use datafusion::datasource::listing::PartitionedFile;
+use datafusion::datasource::physical_plan::{FileGroup, FileScanConfigBuilder, ParquetSource};
+use datafusion::datasource::source::DataSourceExec;
+use datafusion::datasource::TableType;
+use datafusion::execution::object_store::ObjectStoreUrl;
+use datafusion::logical_expr::utils::conjunction;
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::empty::EmptyExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::SessionContext;
+use std::any::Any;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 时间旅行：读取最新版本、指定版本（Delta 的版本号或 Iceberg 的快照 ID），或某个时间点（毫秒）时的版本
+/// Time travel: read the latest version, a given version (a Delta version or an Iceberg snapshot
+/// ID), or the version current at a point in time (in milliseconds)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeTravel {
+    Latest,
+    Version(i64),
+    Timestamp(i64),
+}
+
+impl TimeTravel {
+    /// 读取 `version`（或 `snapshot_id`）与 `timestamp` 选项，对应 `VERSION AS OF` 与 `TIMESTAMP AS OF`
+    /// Read the `version` (or `snapshot_id`) and `timestamp` options, which `VERSION AS OF` and
+    /// `TIMESTAMP AS OF` turn into
+    pub fn try_new(table_options: &TableOptions) -> Result<Self, Exception> {
+        let version = table_options
+            .get("version")
+            .or_else(|| table_options.get("snapshot_id"));
+        match (version, table_options.get("timestamp")) {
+            (Some(_), Some(_)) => Err(Exception::bad_request_error(
+                "Specify either a version or a timestamp to travel to, not both",
+            )),
+            (Some(version), None) => {
+                version
+                    .trim()
+                    .parse()
+                    .map(TimeTravel::Version)
+                    .map_err(|_| {
+                        Exception::bad_request_error(format!(
+                            "Option 'version' expects an integer, got '{}'",
+                            version
+                        ))
+                    })
+            }
+            (None, Some(timestamp)) => {
+                Ok(TimeTravel::Timestamp(parse_timestamp_millis(timestamp)?))
+            }
+            (None, None) => Ok(TimeTravel::Latest),
+        }
+    }
+}
+
+/// 解析时间点为毫秒时间戳：整数为毫秒，不带时区的时间按 UTC 处理
+/// Parse a point in time into milliseconds: integers are milliseconds, and times without a time
+/// zone are taken as UTC
+pub fn parse_timestamp_millis(value: &str) -> Result<i64, Exception> {
+    let value = value.trim();
+    if let Ok(millis) = value.parse::<i64>() {
+        return Ok(millis);
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.timestamp_millis());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(timestamp.and_utc().timestamp_millis());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc()
+            .timestamp_millis());
+    }
+    Err(Exception::bad_request_error(format!(
+        "Invalid timestamp '{}', expected e.g. '2025-06-01 12:00:00'",
+        value
+    )))
+}
+
+/// 表在某个版本的内容：数据列、分区列以及数据文件
+/// The content of a table at one version: the data columns, the partition columns and the data files
+pub struct LakeSnapshot {
+    pub fields: Vec<Field>,
+    pub partition_fields: Vec<Field>,
+    pub files: Vec<LakeFile>,
+}
+
+/// 一个 Parquet 数据文件，分区值与 `partition_fields` 一一对应
+/// A Parquet data file, whose partition values match `partition_fields` one by one
+pub struct LakeFile {
+    pub path: String,
+    pub size: u64,
+    pub partition_values: Vec<ScalarValue>,
+}
+
+/// 根据目录结构识别 Delta Lake（`_delta_log/`）与 Iceberg（`metadata/*.metadata.json`）表
+/// Recognize Delta Lake (`_delta_log/`) and Iceberg (`metadata/*.metadata.json`) tables by their
+/// directory layout
+pub fn detect_lake_format(table_path: &str) -> Option<DataSourceFormat> {
+    if table_path.ends_with(".metadata.json") {
+        return Some(DataSourceFormat::Iceberg);
+    }
+    if is_object_store_path(table_path) {
+        if !table_path.ends_with('/') {
+            return None;
+        }
+        let has_files = |pattern: &str| {
+            find_files(&join_path(table_path, pattern)).is_ok_and(|files| !files.is_empty())
+        };
+        if has_files("_delta_log/") {
+            Some(DataSourceFormat::Delta)
+        } else if has_files("metadata/*.metadata.json") {
+            Some(DataSourceFormat::Iceberg)
+        } else {
+            None
+        }
+    } else if Path::new(table_path).join("_delta_log").is_dir() {
+        Some(DataSourceFormat::Delta)
+    } else if Path::new(table_path).join("metadata").is_dir()
+        && find_files(&join_path(table_path, "metadata/*.metadata.json"))
+            .is_ok_and(|files| !files.is_empty())
+    {
+        Some(DataSourceFormat::Iceberg)
+    } else {
+        None
+    }
+}
+
+/// 注册 Delta Lake 或 Iceberg 表；设置 `history` 选项时注册表的提交历史
+/// Register a Delta Lake or Iceberg table, or the commit history of the table with the `history` option
+pub fn register_lake_table(
+    ctx: &SessionContext,
+    table_ref: &str,
+    table_path: &str,
+    format: &DataSourceFormat,
+    table_options: &TableOptions,
+) -> Result<(), Exception> {
+    if table_options.get_bool("history")?.unwrap_or(false) {
+        let history = match format {
+            DataSourceFormat::Delta => delta::read_history(table_path)?,
+            _ => iceberg::read_history(table_path)?,
+        };
+        ctx.register_batch(table_ref, history)?;
+        return Ok(());
+    }
+    let time_travel = TimeTravel::try_new(table_options)?;
+    let snapshot = match format {
+        DataSourceFormat::Delta => delta::read_snapshot(table_path, time_travel)?,
+        _ => iceberg::read_snapshot(table_path, time_travel)?,
+    };
+    ctx.register_table(table_ref, Arc::new(LakeTable::try_new(snapshot)?))?;
+    Ok(())
+}
+
+/// Delta Lake 或 Iceberg 表的某个版本，按事务日志或元数据确定的文件列表读取 Parquet 文件，
+/// 只涉及分区列的过滤条件用于跳过文件，其余条件用于跳过 row group
+/// One version of a Delta Lake or Iceberg table, reading the Parquet files listed by the
+/// transaction log or metadata; filters on partition columns only skip files, the others skip row groups
+#[derive(Debug)]
+pub struct LakeTable {
+    schema: SchemaRef,
+    file_schema: SchemaRef,
+    partition_fields: Vec<Field>,
+    object_store_url: ObjectStoreUrl,
+    files: Vec<PartitionedFile>,
+}
+
+impl LakeTable {
+    pub fn try_new(snapshot: LakeSnapshot) -> Result<Self, Exception> {
+        let mut object_store_url: Option<ObjectStoreUrl> = None;
+        let mut files = Vec::new();
+        for file in snapshot.files {
+            let (url, location) = get_object_store_location(&file.path)?;
+            match &object_store_url {
+                Some(object_store_url) if object_store_url != &url => {
+                    return Err(Exception::bad_request_error(format!(
+                        "The data files of a table must be in one object store, found {} and {}",
+                        object_store_url.as_str(),
+                        url.as_str()
+                    )))
+                }
+                Some(_) => {}
+                None => object_store_url = Some(url),
+            }
+            let mut partitioned_file = PartitionedFile::new(location.to_string(), file.size);
+            partitioned_file.object_meta.location = location;
+            partitioned_file.partition_values = file.partition_values;
+            files.push(partitioned_file);
+        }
+
+        let file_schema = Arc::new(Schema::new(snapshot.fields.clone()));
+        let schema = Arc::new(Schema::new(
+            snapshot
+                .fields
+                .into_iter()
+                .chain(snapshot.partition_fields.iter().cloned())
+                .collect::<Vec<_>>(),
+        ));
+        Ok(Self {
+            schema,
+            file_schema,
+            partition_fields: snapshot.partition_fields,
+            object_store_url: object_store_url.unwrap_or_else(ObjectStoreUrl::local_filesystem),
+            files,
+        })
+    }
+
+    /// 用只涉及分区列的过滤条件计算每个文件的分区值，跳过不满足条件的文件
+    /// Evaluate the filters on partition columns only against the partition values of every
+    /// file, skipping the files that cannot match
+    fn prune_files(
+        &self,
+        state: &dyn Session,
+        filters: &[Expr],
+    ) -> datafusion::common::Result<Vec<PartitionedFile>> {
+        let predicate = get_predicate(filters, &self.partition_fields)?;
+        let predicate = match predicate {
+            Some(predicate) if !self.partition_fields.is_empty() && !self.files.is_empty() => {
+                predicate
+            }
+            _ => return Ok(self.files.clone()),
+        };
+        let columns = (0..self.partition_fields.len())
+            .map(|index| {
+                ScalarValue::iter_to_array(
+                    self.files
+                        .iter()
+                        .map(|file| file.partition_values[index].clone()),
+                )
+            })
+            .collect::<datafusion::common::Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(self.partition_fields.clone())),
+            columns,
+        )?;
+        let df_schema = DFSchema::try_from(batch.schema().as_ref().clone())?;
+        let mask = state
+            .create_physical_expr(predicate, &df_schema)?
+            .evaluate(&batch)?
+            .into_array(batch.num_rows())?;
+        let mask = mask.as_boolean();
+        Ok(self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| mask.is_valid(*index) && mask.value(*index))
+            .map(|(_, file)| file.clone())
+            .collect())
+    }
+}
+
+/// 合并只引用给定列的过滤条件，去掉表名限定以便在不带限定的 schema 上求值
+/// Combine the filters referencing only the given columns, dropping the table qualifiers so they
+/// can be evaluated against an unqualified schema
+fn get_predicate(filters: &[Expr], fields: &[Field]) -> datafusion::common::Result<Option<Expr>> {
+    let filters = filters
+        .iter()
+        .filter(|filter| {
+            filter
+                .column_refs()
+                .iter()
+                .all(|column| fields.iter().any(|field| field.name() == &column.name))
+        })
+        .map(|filter| {
+            filter
+                .clone()
+                .transform(|expr| match expr {
+                    Expr::Column(column) => Ok(Transformed::yes(Expr::Column(
+                        Column::new_unqualified(column.name),
+                    ))),
+                    expr => Ok(Transformed::no(expr)),
+                })
+                .map(|transformed| transformed.data)
+        })
+        .collect::<datafusion::common::Result<Vec<_>>>()?;
+    Ok(conjunction(filters))
+}
+
+#[async_trait]
+impl TableProvider for LakeTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    /// 过滤条件只用于跳过文件与 row group，仍由 DataFusion 逐行过滤
+    /// Filters only skip files and row groups, DataFusion still applies them to every row
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> datafusion::common::Result<Vec<TableProviderFilterPushDown>> {
+        Ok(vec![TableProviderFilterPushDown::Inexact; filters.len()])
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion::common::Result<Arc<dyn ExecutionPlan>> {
+        let files = self.prune_files(state, filters)?;
+        if files.is_empty() {
+            let schema = match projection {
+                Some(projection) => Arc::new(self.schema.project(projection)?),
+                None => self.schema.clone(),
+            };
+            return Ok(Arc::new(EmptyExec::new(schema)));
+        }
+
+        let mut source = ParquetSource::new(state.table_options().parquet.clone());
+        let file_fields = self
+            .file_schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone())
+            .collect::<Vec<_>>();
+        if let Some(predicate) = get_predicate(filters, &file_fields)? {
+            let df_schema = DFSchema::try_from(self.file_schema.as_ref().clone())?;
+            let predicate = state.create_physical_expr(predicate, &df_schema)?;
+            source = source.with_predicate(self.file_schema.clone(), predicate);
+        }
+        let file_groups = FileGroup::new(files).split_files(state.config().target_partitions());
+        let config = FileScanConfigBuilder::new(
+            self.object_store_url.clone(),
+            self.file_schema.clone(),
+            Arc::new(source),
+        )
+        .with_file_groups(file_groups)
+        .with_table_partition_cols(self.partition_fields.clone())
+        .with_projection(projection.cloned())
+        .with_limit(limit)
+        .build();
+        Ok(DataSourceExec::from_data_source(config))
+    }
+}
+
+/// 读取本地文件或对象存储中对象的全部文本
+/// Read the whole text of a local file or an object in object storage
+pub fn read_text(path: &str) -> Result<String, Exception> {
+    let mut text = String::new();
+    open_decompressed(path, FileCompressionType::UNCOMPRESSED)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// 拼接表目录与其中的相对路径
+/// Join the table directory and a path relative to it
+pub fn join_path(root: &str, relative: &str) -> String {
+    format!(
+        "{}/{}",
+        root.trim_end_matches('/'),
+        relative.trim_start_matches('/')
+    )
+}
+
+/// 去掉 `file:` 前缀，`file:///data/a.parquet` 与 `file:/data/a.parquet` 均转换为 `/data/a.parquet`
+/// Drop the `file:` scheme, turning both `file:///data/a.parquet` and `file:/data/a.parquet` into `/data/a.parquet`
+pub fn strip_file_scheme(path: &str) -> &str {
+    match path.strip_prefix("file:") {
+        Some(path) => match path.strip_prefix("//") {
+            Some(path) if path.starts_with('/') => path,
+            _ => path,
+        },
+        None => path,
+    }
+}
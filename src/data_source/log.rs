@@ -0,0 +1,236 @@
+use crate::data_source::compression::{get_compression_type, open_decompressed};
+use crate::data_source::schema::TableOptions;
+use crate::data_source::utils::{find_files, parse_data_type};
+use crate::response::http_error::Exception;
+use crate::server::schema::TableFieldSchema;
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow_array::{
+    Array, ArrayRef, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray,
+};
+use chrono::{DateTime, NaiveDateTime};
+use regex::Regex;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+const NGINX_COMBINED: &str = r#"^(?P<remote_addr>\S+) \S+ (?P<remote_user>\S+) \[(?P<time_local>[^\]]+)\] "(?P<request>[^"]*)" (?P<status>\d{3}) (?P<body_bytes_sent>\d+|-) "(?P<http_referer>[^"]*)" "(?P<http_user_agent>[^"]*)""#;
+const APACHE_COMMON: &str = r#"^(?P<host>\S+) (?P<ident>\S+) (?P<user>\S+) \[(?P<time>[^\]]+)\] "(?P<request>[^"]*)" (?P<status>\d{3}) (?P<bytes>\d+|-)"#;
+const SYSLOG: &str = r#"^(?P<timestamp>[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}) (?P<hostname>\S+) (?P<app_name>[^:\[\s]+)(?:\[(?P<pid>\d+)\])?: (?P<message>.*)$"#;
+const ACCESS_LOG_TIME_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+pub struct LogOptions {
+    pub pattern: String,
+    pub field_types: Vec<(String, DataType)>,
+    pub timestamp_format: Option<String>,
+    pub keep_unmatched: bool,
+}
+
+impl LogOptions {
+    /// 根据 `preset` 或 `pattern` 选项创建，`CREATE TABLE` 中声明的列类型作为分组的类型提示
+    /// Build from the `preset` or `pattern` option, using the column types declared in
+    /// `CREATE TABLE` as type hints for the capture groups
+    pub fn try_new(
+        table_options: &TableOptions,
+        table_schema: &[TableFieldSchema],
+    ) -> Result<Self, Exception> {
+        let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, None);
+        let (pattern, mut field_types, timestamp_format) = match table_options.get("preset") {
+            Some(preset) => match preset.to_lowercase().as_str() {
+                "nginx_combined" => (
+                    NGINX_COMBINED,
+                    vec![
+                        ("time_local".to_string(), timestamp),
+                        ("status".to_string(), DataType::Int32),
+                        ("body_bytes_sent".to_string(), DataType::Int64),
+                    ],
+                    Some(ACCESS_LOG_TIME_FORMAT),
+                ),
+                "apache_common" => (
+                    APACHE_COMMON,
+                    vec![
+                        ("time".to_string(), timestamp),
+                        ("status".to_string(), DataType::Int32),
+                        ("bytes".to_string(), DataType::Int64),
+                    ],
+                    Some(ACCESS_LOG_TIME_FORMAT),
+                ),
+                "syslog" => (SYSLOG, vec![("pid".to_string(), DataType::Int64)], None),
+                _ => {
+                    return Err(Exception::bad_request_error(format!(
+                        "Unknown log preset '{}', expected nginx_combined, apache_common or syslog",
+                        preset
+                    )))
+                }
+            },
+            None => ("", Vec::new(), None),
+        };
+        let pattern = match table_options.get("pattern") {
+            Some(pattern) => pattern,
+            None if !pattern.is_empty() => pattern,
+            None => {
+                return Err(Exception::bad_request_error(
+                    "Log tables require a 'pattern' or 'preset' option",
+                ))
+            }
+        };
+
+        for field in table_schema {
+            let data_type = parse_data_type(&field.field_type).ok_or_else(|| {
+                Exception::bad_request_error(format!(
+                    "Unsupported type '{}' for column {}",
+                    field.field_type, field.field
+                ))
+            })?;
+            field_types.retain(|(name, _)| name != &field.field);
+            field_types.push((field.field.clone(), data_type));
+        }
+
+        Ok(LogOptions {
+            pattern: pattern.to_string(),
+            field_types,
+            timestamp_format: table_options
+                .get("timestamp_format")
+                .or(timestamp_format)
+                .map(|format| format.to_string()),
+            keep_unmatched: table_options.get_bool("keep_unmatched")?.unwrap_or(false),
+        })
+    }
+}
+
+/// 使用带命名分组的正则表达式逐行解析日志文件
+/// Parse log files line by line with a regular expression containing named capture groups
+pub fn from_file_to_record_batch(
+    file_path: &str,
+    options: &LogOptions,
+) -> Result<RecordBatch, Exception> {
+    let regex = Regex::new(&options.pattern)?;
+    let names = regex
+        .capture_names()
+        .flatten()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return Err(Exception::bad_request_error(
+            "The log pattern must contain named capture groups such as (?P<name>...)",
+        ));
+    }
+
+    let mut values: Vec<Vec<Option<String>>> = vec![Vec::new(); names.len()];
+    let mut lines: Vec<String> = Vec::new();
+    let mut files: Vec<String> = Vec::new();
+    let mut line_numbers: Vec<i64> = Vec::new();
+    let mut errors: Vec<Option<String>> = Vec::new();
+
+    for file in find_files(file_path)? {
+        let mut reader = BufReader::new(open_decompressed(&file, get_compression_type(&file))?);
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+            line_number += 1;
+            let line = String::from_utf8_lossy(&buffer);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            match regex.captures(line) {
+                Some(captures) => {
+                    for (i, name) in names.iter().enumerate() {
+                        // 日志中 `-` 表示空值
+                        // `-` stands for an empty value in logs
+                        let value = captures
+                            .name(name)
+                            .map(|value| value.as_str())
+                            .filter(|value| *value != "-");
+                        values[i].push(value.map(|value| value.to_string()));
+                    }
+                    errors.push(None);
+                }
+                None if options.keep_unmatched => {
+                    values.iter_mut().for_each(|column| column.push(None));
+                    errors.push(Some("Line does not match the pattern".to_string()));
+                }
+                None => continue,
+            }
+            lines.push(line.to_string());
+            files.push(file.clone());
+            line_numbers.push(line_number);
+        }
+    }
+
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for (name, column) in names.iter().zip(values) {
+        let data_type = options
+            .field_types
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, data_type)| data_type.clone())
+            .unwrap_or(DataType::Utf8);
+        let array = StringArray::from(column);
+        let converted = convert_column(&array, &data_type, options.timestamp_format.as_deref())?;
+
+        for (row, error) in errors.iter_mut().enumerate() {
+            if error.is_none() && array.is_valid(row) && converted.is_null(row) {
+                *error = Some(format!(
+                    "Cannot convert '{}' to {} for column {}",
+                    array.value(row),
+                    data_type,
+                    name
+                ));
+            }
+        }
+
+        fields.push(Field::new(name, data_type, true));
+        arrays.push(converted);
+    }
+
+    fields.push(Field::new("_line", DataType::Utf8, false));
+    arrays.push(Arc::new(StringArray::from(lines)));
+    fields.push(Field::new("_file", DataType::Utf8, false));
+    arrays.push(Arc::new(StringArray::from(files)));
+    fields.push(Field::new("_line_number", DataType::Int64, false));
+    arrays.push(Arc::new(Int64Array::from(line_numbers)));
+    if options.keep_unmatched {
+        fields.push(Field::new("_error", DataType::Utf8, true));
+        arrays.push(Arc::new(StringArray::from(errors)));
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(Exception::from)
+}
+
+pub fn convert_column(
+    array: &StringArray,
+    data_type: &DataType,
+    timestamp_format: Option<&str>,
+) -> Result<ArrayRef, Exception> {
+    match (data_type, timestamp_format) {
+        (DataType::Timestamp(TimeUnit::Nanosecond, None), Some(format)) => {
+            Ok(Arc::new(TimestampNanosecondArray::from(
+                array
+                    .iter()
+                    .map(|value| value.and_then(|value| parse_timestamp(value, format)))
+                    .collect::<Vec<_>>(),
+            )))
+        }
+        (DataType::Utf8, _) => Ok(Arc::new(array.clone())),
+        _ => Ok(cast_with_options(
+            array,
+            data_type,
+            &CastOptions {
+                safe: true,
+                ..Default::default()
+            },
+        )?),
+    }
+}
+
+fn parse_timestamp(value: &str, format: &str) -> Option<i64> {
+    match DateTime::parse_from_str(value, format) {
+        Ok(datetime) => datetime.timestamp_nanos_opt(),
+        Err(_) => NaiveDateTime::parse_from_str(value, format)
+            .ok()
+            .and_then(|datetime| datetime.and_utc().timestamp_nanos_opt()),
+    }
+}
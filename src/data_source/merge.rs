@@ -0,0 +1,110 @@
+use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::common::ScalarValue;
+use datafusion::dataframe::DataFrame;
+use datafusion::prelude::{cast, ident, lit};
+use std::sync::Arc;
+
+/// 合并多个文件的 schema：列取并集，同名列类型不同时按 Int64 → Float64 → Utf8 放宽；
+/// 严格模式下任一文件与第一个文件的 schema 不一致时报错并指出该文件
+/// Merge the schemas of several files: the columns are unioned and differing types are widened
+/// along Int64 → Float64 → Utf8; in strict mode any file whose schema differs from the first file
+/// is reported as an error
+pub fn merge_schemas(
+    schemas: &[(String, SchemaRef)],
+    strict: bool,
+) -> Result<SchemaRef, Exception> {
+    let mut fields: Vec<Field> = Vec::new();
+    for (file, schema) in schemas {
+        if strict {
+            if let Some((first_file, first_schema)) = schemas.first() {
+                if let Some(difference) = get_schema_difference(first_schema, schema) {
+                    return Err(Exception::bad_request_error(format!(
+                        "Schema of '{}' does not match '{}': {}",
+                        file, first_file, difference
+                    )));
+                }
+            }
+        }
+        for field in schema.fields() {
+            match fields
+                .iter_mut()
+                .find(|merged| merged.name() == field.name())
+            {
+                Some(merged) => {
+                    let data_type = widen_data_type(merged.data_type(), field.data_type());
+                    *merged = Field::new(field.name(), data_type, true);
+                }
+                None => fields.push(Field::new(field.name(), field.data_type().clone(), true)),
+            }
+        }
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// 放宽两个类型：整数之间为 Int64，数值之间为 Float64，其余为 Utf8
+/// Widen two types: Int64 between integers, Float64 between numbers and Utf8 otherwise
+pub fn widen_data_type(left: &DataType, right: &DataType) -> DataType {
+    match (left, right) {
+        (left, right) if left == right => left.clone(),
+        (DataType::Null, other) | (other, DataType::Null) => other.clone(),
+        (left, right) if left.is_integer() && right.is_integer() => DataType::Int64,
+        (left, right) if left.is_numeric() && right.is_numeric() => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+/// 判断 schema 是否只在可空性上不同以外完全一致
+/// Whether two schemas have the same columns and types, ignoring nullability
+pub fn is_same_schema(left: &Schema, right: &Schema) -> bool {
+    get_schema_difference(left, right).is_none()
+        && left
+            .fields()
+            .iter()
+            .zip(right.fields().iter())
+            .all(|(left, right)| left.name() == right.name())
+}
+
+fn get_schema_difference(expected: &Schema, actual: &Schema) -> Option<String> {
+    for field in expected.fields() {
+        match actual.field_with_name(field.name()) {
+            Ok(actual_field) if actual_field.data_type() != field.data_type() => {
+                return Some(format!(
+                    "column '{}' is {} instead of {}",
+                    field.name(),
+                    actual_field.data_type(),
+                    field.data_type()
+                ))
+            }
+            Ok(_) => {}
+            Err(_) => return Some(format!("column '{}' is missing", field.name())),
+        }
+    }
+    actual
+        .fields()
+        .iter()
+        .find(|field| expected.field_with_name(field.name()).is_err())
+        .map(|field| format!("unexpected column '{}'", field.name()))
+}
+
+/// 按合并后的 schema 对齐 DataFrame：缺少的列补空值，类型不同的列转换为放宽后的类型
+/// Align a DataFrame to the merged schema: missing columns are filled with nulls and columns
+/// are cast to the widened types
+pub fn align_data_frame(data_frame: DataFrame, schema: &Schema) -> Result<DataFrame, Exception> {
+    let exprs = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let expr = if data_frame
+                .schema()
+                .has_column_with_unqualified_name(field.name())
+            {
+                ident(field.name())
+            } else {
+                lit(ScalarValue::Null)
+            };
+            cast(expr, field.data_type().clone()).alias(field.name())
+        })
+        .collect::<Vec<_>>();
+    Ok(data_frame.select(exprs)?)
+}
@@ -0,0 +1,188 @@
+use crate::data_source::utils::get_modified_time;
+use arrow::array::new_null_array;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow_array::{
+    ArrayRef, Int64Array, RecordBatch, RecordBatchOptions, StringArray, TimestampNanosecondArray,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::Result;
+use datafusion::datasource::TableType;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::any::Any;
+use std::sync::Arc;
+
+pub const FILE_COLUMN: &str = "_file";
+pub const ROW_COLUMN: &str = "_row";
+pub const MODIFIED_COLUMN: &str = "_modified";
+
+pub const MODIFIED_DATA_TYPE: DataType = DataType::Timestamp(TimeUnit::Nanosecond, None);
+
+/// 读取文件的修改时间（纳秒时间戳）
+/// Read the modification time of a file as a nanosecond timestamp
+pub fn get_modified(file: &str) -> Option<i64> {
+    let modified = get_modified_time(file)?;
+    DateTime::<Utc>::from(modified).timestamp_nanos_opt()
+}
+
+/// 在 schema 末尾加上 `_file`、`_row`、`_modified` 虚拟列
+/// Append the `_file`, `_row` and `_modified` virtual columns to a schema
+pub fn with_metadata_fields(schema: &Schema) -> Schema {
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    fields.push(Arc::new(Field::new(FILE_COLUMN, DataType::Utf8, false)));
+    fields.push(Arc::new(Field::new(ROW_COLUMN, DataType::Int64, false)));
+    fields.push(Arc::new(Field::new(
+        MODIFIED_COLUMN,
+        MODIFIED_DATA_TYPE,
+        true,
+    )));
+    Schema::new(fields)
+}
+
+/// 为单个文件的表添加 `_file`、`_row`、`_modified` 虚拟列；文件按顺序作为一个分区读取，
+/// `_row` 在读取时编号，因此与文件中的行号一致且不受查询的并行度影响
+/// A single file's table with the `_file`, `_row` and `_modified` virtual columns added; the file
+/// is read in order as one partition and `_row` is numbered while reading, so it matches the row
+/// number in the file whatever the parallelism of the query
+#[derive(Debug)]
+pub struct MetadataTable {
+    table: Arc<dyn TableProvider>,
+    file: String,
+    modified: Option<i64>,
+    schema: SchemaRef,
+}
+
+impl MetadataTable {
+    pub fn new(table: Arc<dyn TableProvider>, file: &str) -> Self {
+        MetadataTable {
+            schema: Arc::new(with_metadata_fields(&table.schema())),
+            table,
+            file: file.to_string(),
+            modified: get_modified(file),
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for MetadataTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = match projection {
+            Some(projection) => projection.clone(),
+            None => (0..self.schema.fields().len()).collect(),
+        };
+        // 过滤条件不下推，否则 `_row` 只会为过滤后的行编号
+        // Filters are not pushed down, otherwise `_row` would only number the filtered rows
+        let table_columns = self.table.schema().fields().len();
+        let table_projection = projection
+            .iter()
+            .copied()
+            .filter(|index| *index < table_columns)
+            .collect::<Vec<_>>();
+        let plan = self
+            .table
+            .scan(state, Some(&table_projection), &[], limit)
+            .await?;
+        let schema = Arc::new(self.schema.project(&projection)?);
+        let partition = MetadataPartition {
+            plan,
+            schema: schema.clone(),
+            file: self.file.clone(),
+            modified: self.modified,
+        };
+        // 文件的执行计划由分区自己执行，优化器看不到它，也就不会把文件拆分为多个分区
+        // The partition executes the file's plan itself, so the optimizer never sees it and
+        // cannot split the file into several partitions
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(partition) as Arc<dyn PartitionStream>],
+            None,
+            Vec::new(),
+            false,
+            limit,
+        )?))
+    }
+}
+
+#[derive(Debug)]
+struct MetadataPartition {
+    plan: Arc<dyn ExecutionPlan>,
+    schema: SchemaRef,
+    file: String,
+    modified: Option<i64>,
+}
+
+impl PartitionStream for MetadataPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let plan = self.plan.clone();
+        let partitions = plan.properties().output_partitioning().partition_count();
+        let schema = self.schema.clone();
+        let file = self.file.clone();
+        let modified = self.modified;
+        let mut next_row = 1;
+        let batches = stream::iter(
+            (0..partitions).map(move |partition| plan.execute(partition, ctx.clone())),
+        )
+        .try_flatten()
+        .map(move |batch| {
+            let batch = batch?;
+            let rows = batch.num_rows();
+            let first_row = next_row;
+            next_row += rows as i64;
+            let mut columns = batch.columns().iter();
+            let arrays = schema
+                .fields()
+                .iter()
+                .map(|field| -> ArrayRef {
+                    match field.name().as_str() {
+                        FILE_COLUMN => Arc::new(StringArray::from(vec![file.as_str(); rows])),
+                        ROW_COLUMN => Arc::new(Int64Array::from_iter_values(
+                            first_row..first_row + rows as i64,
+                        )),
+                        MODIFIED_COLUMN => {
+                            Arc::new(TimestampNanosecondArray::from(vec![modified; rows]))
+                        }
+                        _ => columns
+                            .next()
+                            .cloned()
+                            .unwrap_or_else(|| new_null_array(field.data_type(), rows)),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let options = RecordBatchOptions::new().with_row_count(Some(rows));
+            Ok(RecordBatch::try_new_with_options(
+                schema.clone(),
+                arrays,
+                &options,
+            )?)
+        });
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
@@ -1,4 +1,26 @@
+mod avro;
+mod avro_decoder;
+mod compression;
 pub mod context;
+mod deadline;
+mod delta;
+pub mod discovery;
+mod encoding;
+mod excel;
+mod fixed_width;
+mod flatten;
+mod iceberg;
+mod lake;
+mod log;
+mod merge;
+mod metadata;
+mod partition;
+mod pushdown;
+pub mod remote;
 pub mod schema;
+mod sniff;
+mod sqlite_file;
+mod store;
 mod utils;
-mod excel;
\ No newline at end of file
+pub mod watcher;
+mod xml;
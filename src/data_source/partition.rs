@@ -0,0 +1,113 @@
+use crate::data_source::schema::TableOptions;
+use crate::data_source::store::is_object_store_path;
+use crate::data_source::utils::{find_files, find_files_recursive, is_dir, parse_data_type};
+use crate::response::http_error::Exception;
+use arrow::datatypes::DataType;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 获取分区列：优先使用 `partition_columns` 选项（如 `dt DATE, region STRING`），
+/// 否则从目录中 `key=value` 形式的子目录推断，推断出的分区列均为字符串
+/// Get the partition columns from the `partition_columns` option (e.g. `dt DATE, region STRING`),
+/// or infer them as strings from `key=value` subdirectories when the location is a directory
+pub fn get_partition_columns(
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<Vec<(String, DataType)>, Exception> {
+    match table_options.get("partition_columns") {
+        Some(columns) => parse_partition_columns(columns),
+        None if is_dir(table_path) => Ok(infer_partition_columns(table_path)?
+            .into_iter()
+            .map(|column| (column, DataType::Utf8))
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// 表是否为分区目录（配置或推断出分区列的目录）
+/// Whether a table is a partitioned directory, a directory with configured or inferred partition columns
+pub fn is_partitioned(table_path: &str, table_options: &TableOptions) -> Result<bool, Exception> {
+    Ok(is_dir(table_path) && !get_partition_columns(table_path, table_options)?.is_empty())
+}
+
+/// 查找表的文件：分区目录递归查找各分区子目录中的文件，其他目录只查找其中的文件
+/// Find the files of a table: partitioned directories are searched through their partition
+/// subdirectories, other directories only give the files directly inside them
+pub fn find_table_files(
+    table_path: &str,
+    table_options: &TableOptions,
+) -> Result<Vec<String>, Exception> {
+    if is_partitioned(table_path, table_options)? {
+        find_files_recursive(table_path)
+    } else {
+        find_files(table_path)
+    }
+}
+
+/// 解析 `dt DATE, region STRING` 形式的分区列定义，省略类型时为字符串
+/// Parse partition column definitions like `dt DATE, region STRING`, defaulting to strings
+pub fn parse_partition_columns(columns: &str) -> Result<Vec<(String, DataType)>, Exception> {
+    columns
+        .split(',')
+        .map(|column| column.trim())
+        .filter(|column| !column.is_empty())
+        .map(|column| match column.split_once(char::is_whitespace) {
+            Some((name, sql_type)) => parse_data_type(sql_type)
+                .map(|data_type| (name.to_string(), data_type))
+                .ok_or_else(|| {
+                    Exception::bad_request_error(format!(
+                        "Unsupported type '{}' for partition column {}",
+                        sql_type.trim(),
+                        name
+                    ))
+                }),
+            None => Ok((column.to_string(), DataType::Utf8)),
+        })
+        .collect()
+}
+
+/// 沿目录中第一个 `key=value` 形式的子目录逐层向下推断分区列，例如 `dt=2025-01-01/region=eu/`；
+/// 对象存储没有目录，根据第一个对象的相对路径推断
+/// Infer the partition columns by following the first `key=value` subdirectory level by level,
+/// e.g. `dt=2025-01-01/region=eu/`; object storage has no directories, so the relative path of
+/// the first object is used instead
+fn infer_partition_columns(table_path: &str) -> Result<Vec<String>, Exception> {
+    if is_object_store_path(table_path) {
+        let files = find_files_recursive(table_path)?;
+        let file = match files.first() {
+            Some(file) => file,
+            None => return Ok(Vec::new()),
+        };
+        let relative_path = file
+            .strip_prefix(table_path)
+            .unwrap_or(file)
+            .trim_start_matches('/');
+        let parent = relative_path
+            .rsplit_once('/')
+            .map_or("", |(parent, _)| parent);
+        return Ok(parent
+            .split('/')
+            .map_while(|segment| segment.split_once('=').map(|(name, _)| name.to_string()))
+            .collect());
+    }
+
+    let mut columns = Vec::new();
+    let mut dir = PathBuf::from(table_path);
+    loop {
+        let mut partitions = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with(['_', '.']) && name.contains('='))
+            .collect::<Vec<_>>();
+        partitions.sort();
+        let partition = match partitions.into_iter().next() {
+            Some(partition) => partition,
+            None => return Ok(columns),
+        };
+        if let Some((name, _)) = partition.split_once('=') {
+            columns.push(name.to_string());
+        }
+        dir = dir.join(Path::new(&partition));
+    }
+}
@@ -0,0 +1,118 @@
+use datafusion::common::ScalarValue;
+use datafusion::logical_expr::expr::{InList, Like};
+use datafusion::logical_expr::{BinaryExpr, Expr, Operator};
+
+/// 将简单的过滤条件（列与字面量比较、`IS NULL`、`IN`、`LIKE` 及其 `AND`/`OR` 组合）转换为 SQL，
+/// 无法转换时返回 `None`；`quote` 引用列名，`literal` 输出字面量（或参数占位符）
+/// Translate a simple filter (comparisons between a column and a literal, `IS NULL`, `IN`,
+/// `LIKE` and their `AND`/`OR` combinations) into SQL, returning `None` when it cannot be
+/// translated; `quote` quotes column names and `literal` renders literals (or parameter placeholders)
+///
+/// DataFusion 的 `LIKE` 以 `\` 为转义字符，默认不转义的数据库（如 SQLite）需通过 `like_escape` 给出 `ESCAPE` 子句
+/// DataFusion's `LIKE` escapes with `\`, databases without a default escape character (such as
+/// SQLite) need the `ESCAPE` clause given through `like_escape`
+///
+/// 数据库的排序规则可能忽略大小写或按语言排序，字符串只下推 `=`、`IN` 与 `LIKE`，这些条件在数据库中匹配的行
+/// 总是包含 DataFusion 匹配的行，DataFusion 会再次过滤
+/// Databases may compare strings ignoring case or in locale order, so strings are only pushed
+/// down with `=`, `IN` and `LIKE`, which always match at least the rows DataFusion matches in
+/// the database, and DataFusion filters the result again
+pub fn filter_to_sql(
+    expr: &Expr,
+    quote: &dyn Fn(&str) -> String,
+    literal: &mut dyn FnMut(&ScalarValue) -> Option<String>,
+    like_escape: Option<&str>,
+) -> Option<String> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+            Operator::And | Operator::Or => {
+                let left = filter_to_sql(left, quote, literal, like_escape)?;
+                let right = filter_to_sql(right, quote, literal, like_escape)?;
+                Some(format!("({} {} {})", left, op, right))
+            }
+            Operator::Eq
+            | Operator::NotEq
+            | Operator::Lt
+            | Operator::LtEq
+            | Operator::Gt
+            | Operator::GtEq => match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), Expr::Literal(value))
+                    if *op == Operator::Eq || !is_string(value) =>
+                {
+                    Some(format!(
+                        "{} {} {}",
+                        quote(&column.name),
+                        op,
+                        literal(value)?
+                    ))
+                }
+                (Expr::Literal(value), Expr::Column(column))
+                    if *op == Operator::Eq || !is_string(value) =>
+                {
+                    Some(format!(
+                        "{} {} {}",
+                        literal(value)?,
+                        op,
+                        quote(&column.name)
+                    ))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        Expr::IsNull(expr) => match expr.as_ref() {
+            Expr::Column(column) => Some(format!("{} is null", quote(&column.name))),
+            _ => None,
+        },
+        Expr::IsNotNull(expr) => match expr.as_ref() {
+            Expr::Column(column) => Some(format!("{} is not null", quote(&column.name))),
+            _ => None,
+        },
+        Expr::InList(InList {
+            expr,
+            list,
+            negated,
+        }) => match expr.as_ref() {
+            Expr::Column(column) if !list.is_empty() => {
+                let values = list
+                    .iter()
+                    .map(|item| match item {
+                        Expr::Literal(value) if !*negated || !is_string(value) => literal(value),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(format!(
+                    "{} {}in ({})",
+                    quote(&column.name),
+                    if *negated { "not " } else { "" },
+                    values.join(", ")
+                ))
+            }
+            _ => None,
+        },
+        Expr::Like(Like {
+            negated: false,
+            expr,
+            pattern,
+            escape_char: None,
+            case_insensitive: false,
+        }) => match (expr.as_ref(), pattern.as_ref()) {
+            (Expr::Column(column), Expr::Literal(value)) if is_string(value) => {
+                let sql = format!("{} like {}", quote(&column.name), literal(value)?);
+                match like_escape {
+                    Some(escape) => Some(format!("{} escape {}", sql, escape)),
+                    None => Some(sql),
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_string(value: &ScalarValue) -> bool {
+    matches!(
+        value,
+        ScalarValue::Utf8(_) | ScalarValue::LargeUtf8(_) | ScalarValue::Utf8View(_)
+    )
+}
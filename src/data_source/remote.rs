@@ -0,0 +1,525 @@
+use crate::data_source::pushdown::filter_to_sql;
+use crate::data_source::utils::parse_data_type;
+use crate::response::http_error::Exception;
+use crate::server::schema::TableFieldSchema;
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions, StringArray};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::runtime::SpawnedTask;
+use datafusion::common::{DataFusionError, ScalarValue};
+use datafusion::datasource::TableType;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::channel::mpsc;
+use futures::{pin_mut, stream, SinkExt, StreamExt};
+use mysql_async::prelude::Queryable;
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+
+type Rows = Vec<Vec<Option<String>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoteKind {
+    MySQL,
+    Postgres,
+}
+
+impl RemoteKind {
+    /// 根据 `STORED AS` 或 `format` 选项中的名称确定数据库类型
+    /// Determine the database from the name in `STORED AS` or the `format` option
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "mysql" => Some(RemoteKind::MySQL),
+            "postgres" | "postgresql" => Some(RemoteKind::Postgres),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RemoteKind::MySQL => "mysql",
+            RemoteKind::Postgres => "postgres",
+        }
+    }
+
+    fn schemes(&self) -> &'static [&'static str] {
+        match self {
+            RemoteKind::MySQL => &["mysql"],
+            RemoteKind::Postgres => &["postgres", "postgresql"],
+        }
+    }
+
+    fn quote(&self, name: &str) -> String {
+        match self {
+            RemoteKind::MySQL => format!("`{}`", name.replace('`', "``")),
+            RemoteKind::Postgres => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    /// `schema.table` 形式的表名分别引用
+    /// Quote both parts of a `schema.table` name
+    fn quote_table(&self, table: &str) -> String {
+        match table.split_once('.') {
+            Some((schema, table)) => format!("{}.{}", self.quote(schema), self.quote(table)),
+            None => self.quote(table),
+        }
+    }
+
+    /// MySQL 默认将反斜杠作为字符串中的转义符，需同时转义反斜杠与单引号；PostgreSQL 默认开启
+    /// `standard_conforming_strings`，反斜杠按原样保留，只需将单引号加倍
+    /// MySQL treats backslashes in strings as escapes by default, so both backslashes and quotes
+    /// are escaped, while PostgreSQL keeps backslashes as they are with `standard_conforming_strings`
+    /// on by default and only needs single quotes doubled
+    fn string(&self, value: &str) -> String {
+        match self {
+            RemoteKind::MySQL => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''")),
+            RemoteKind::Postgres => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+
+    fn literal(&self, value: &ScalarValue) -> Option<String> {
+        match value {
+            ScalarValue::Boolean(Some(value)) => Some(value.to_string()),
+            ScalarValue::Int8(Some(value)) => Some(value.to_string()),
+            ScalarValue::Int16(Some(value)) => Some(value.to_string()),
+            ScalarValue::Int32(Some(value)) => Some(value.to_string()),
+            ScalarValue::Int64(Some(value)) => Some(value.to_string()),
+            ScalarValue::UInt8(Some(value)) => Some(value.to_string()),
+            ScalarValue::UInt16(Some(value)) => Some(value.to_string()),
+            ScalarValue::UInt32(Some(value)) => Some(value.to_string()),
+            ScalarValue::UInt64(Some(value)) => Some(value.to_string()),
+            ScalarValue::Float32(Some(value)) if value.is_finite() => Some(value.to_string()),
+            ScalarValue::Float64(Some(value)) if value.is_finite() => Some(value.to_string()),
+            ScalarValue::Utf8(Some(value))
+            | ScalarValue::LargeUtf8(Some(value))
+            | ScalarValue::Utf8View(Some(value)) => Some(self.string(value)),
+            ScalarValue::Date32(Some(days)) => {
+                // 1970-01-01 是公元第 719163 天
+                // 1970-01-01 is day 719163 of the common era
+                let date = NaiveDate::from_num_days_from_ce_opt(days.checked_add(719_163)?)?;
+                Some(self.string(&date.format("%Y-%m-%d").to_string()))
+            }
+            ScalarValue::TimestampNanosecond(Some(nanoseconds), None) => {
+                let datetime = DateTime::from_timestamp_nanos(*nanoseconds).naive_utc();
+                Some(self.string(&datetime.format("%Y-%m-%d %H:%M:%S%.f").to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// 将数据库的列类型转换为 Arrow 数据类型，其他类型按文本读取
+    /// Convert a database column type into an Arrow data type, reading other types as text
+    fn data_type(&self, data_type: &str) -> DataType {
+        match data_type.to_lowercase().as_str() {
+            "tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" | "year" => {
+                DataType::Int64
+            }
+            "float" | "double" | "real" | "double precision" | "decimal" | "numeric" => {
+                DataType::Float64
+            }
+            "boolean" => DataType::Boolean,
+            "date" => DataType::Date32,
+            "datetime" | "timestamp" | "timestamp without time zone" => {
+                DataType::Timestamp(TimeUnit::Nanosecond, None)
+            }
+            _ => DataType::Utf8,
+        }
+    }
+}
+
+/// 将连接地址中的用户名与密码分离，返回不含凭据的地址与 `user:password` 部分
+/// Split the user and password off a connection URL, returning the URL without credentials and
+/// the `user:password` part
+pub fn split_credentials(url: &str) -> (String, Option<String>) {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return (url.to_string(), None);
+    };
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    match rest[..authority_end].rsplit_once('@') {
+        Some((credentials, host)) => (
+            format!("{}://{}{}", scheme, host, &rest[authority_end..]),
+            Some(credentials.to_string()),
+        ),
+        None => (url.to_string(), None),
+    }
+}
+
+/// 为不含凭据的连接地址加上 `user:password`
+/// Add `user:password` to a connection URL without credentials
+pub fn with_credentials(url: &str, credentials: Option<&str>) -> String {
+    match (credentials, url.split_once("://")) {
+        (Some(credentials), Some((scheme, rest))) if split_credentials(url).1.is_none() => {
+            format!("{}://{}@{}", scheme, credentials, rest)
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// MySQL 或 PostgreSQL 中的表，投影、简单的过滤条件与 limit 转换为 SQL 在数据库中执行，结果以文本读取后按列类型转换
+/// A table in MySQL or PostgreSQL, with projections, simple filters and limits translated into
+/// SQL that the database executes, reading the results as text and converting them to the column types
+pub struct RemoteTable {
+    kind: RemoteKind,
+    url: String,
+    table: String,
+    schema: SchemaRef,
+}
+
+// 连接地址包含凭据，不输出到执行计划等调试信息中
+// The connection URL contains credentials, so it is left out of debug output such as plans
+impl fmt::Debug for RemoteTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteTable")
+            .field("kind", &self.kind)
+            .field("table", &self.table)
+            .finish()
+    }
+}
+
+impl RemoteTable {
+    /// 已声明列时只读取这些列，否则从 `information_schema` 读取表的所有列
+    /// Read only the declared columns, or every column of the table from `information_schema`
+    pub async fn try_new(
+        kind: RemoteKind,
+        url: &str,
+        table: &str,
+        table_schema: &[TableFieldSchema],
+    ) -> Result<Self, Exception> {
+        let scheme = url
+            .split_once("://")
+            .map(|(scheme, _)| scheme.to_lowercase());
+        if !scheme.is_some_and(|scheme| kind.schemes().contains(&scheme.as_str())) {
+            return Err(Exception::bad_request_error(format!(
+                "Invalid {} connection URL, expected {}://user:password@host/database",
+                kind.name(),
+                kind.schemes()[0]
+            )));
+        }
+
+        let fields = if table_schema.is_empty() {
+            let (schema_name, table_name) = match table.split_once('.') {
+                Some((schema_name, table_name)) => (kind.string(schema_name), table_name),
+                None => (
+                    match kind {
+                        RemoteKind::MySQL => "database()".to_string(),
+                        RemoteKind::Postgres => "current_schema()".to_string(),
+                    },
+                    table,
+                ),
+            };
+            let sql = format!(
+                "select column_name, data_type from information_schema.columns where table_schema = {} and table_name = {} order by ordinal_position",
+                schema_name,
+                kind.string(table_name)
+            );
+            let mut columns = Vec::new();
+            fetch_rows(kind, url, &sql, usize::MAX, |rows| {
+                columns.extend(rows);
+                async { true }
+            })
+            .await?;
+            if columns.is_empty() {
+                return Err(Exception::bad_request_error(format!(
+                    "Table '{}' not found in the {} database",
+                    table,
+                    kind.name()
+                )));
+            }
+            columns
+                .into_iter()
+                .map(|column| {
+                    let name = column[0].clone().unwrap_or_default();
+                    let data_type = kind.data_type(column[1].as_deref().unwrap_or_default());
+                    Field::new(name, data_type, true)
+                })
+                .collect::<Vec<_>>()
+        } else {
+            table_schema
+                .iter()
+                .map(|field| {
+                    let data_type = parse_data_type(&field.field_type).ok_or_else(|| {
+                        Exception::bad_request_error(format!(
+                            "Unsupported type '{}' for column {}",
+                            field.field_type, field.field
+                        ))
+                    })?;
+                    Ok(Field::new(field.field.clone(), data_type, true))
+                })
+                .collect::<Result<Vec<_>, Exception>>()?
+        };
+
+        Ok(RemoteTable {
+            kind,
+            url: url.to_string(),
+            table: table.to_string(),
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+}
+
+#[async_trait]
+impl TableProvider for RemoteTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    /// 数据库的比较语义（如排序规则）与 DataFusion 不完全相同，下推的条件仍由 DataFusion 再次过滤
+    /// Databases compare values slightly differently from DataFusion (e.g. collations), so pushed
+    /// down filters are still applied again by DataFusion
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> datafusion::common::Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match self.to_sql(filter) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion::common::Result<Arc<dyn ExecutionPlan>> {
+        let projection = match projection {
+            Some(projection) => projection.clone(),
+            None => (0..self.schema.fields().len()).collect(),
+        };
+        let schema = Arc::new(self.schema.project(&projection)?);
+
+        let columns = match schema.fields().is_empty() {
+            // `count(*)` 等查询不需要任何列
+            // Queries such as `count(*)` need no columns at all
+            true => "1".to_string(),
+            false => schema
+                .fields()
+                .iter()
+                .map(|field| self.kind.quote(field.name()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mut sql = format!(
+            "select {} from {}",
+            columns,
+            self.kind.quote_table(&self.table)
+        );
+        let conditions = filters
+            .iter()
+            .filter_map(|filter| self.to_sql(filter))
+            .collect::<Vec<_>>();
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" where {}", conditions.join(" and ")));
+        }
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" limit {}", limit));
+        }
+
+        let partition = RemotePartition {
+            kind: self.kind,
+            url: self.url.clone(),
+            sql,
+            schema: schema.clone(),
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(partition)],
+            None,
+            Vec::new(),
+            false,
+            limit,
+        )?))
+    }
+}
+
+impl RemoteTable {
+    fn to_sql(&self, filter: &Expr) -> Option<String> {
+        // MySQL 与 PostgreSQL 的 `LIKE` 默认以 `\` 转义
+        // MySQL and PostgreSQL escape `LIKE` with `\` by default
+        filter_to_sql(
+            filter,
+            &|name| self.kind.quote(name),
+            &mut |value| self.kind.literal(value),
+            None,
+        )
+    }
+}
+
+#[derive(Clone)]
+struct RemotePartition {
+    kind: RemoteKind,
+    url: String,
+    sql: String,
+    schema: SchemaRef,
+}
+
+impl fmt::Debug for RemotePartition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemotePartition")
+            .field("kind", &self.kind)
+            .field("sql", &self.sql)
+            .finish()
+    }
+}
+
+impl PartitionStream for RemotePartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let batch_size = ctx.session_config().batch_size().max(1);
+        let partition = self.clone();
+        // 查询在后台任务中执行，每读取 batch_size 行发送一个批次，查询结束（如达到 limit）后停止读取
+        // The query runs in a background task and sends a batch every batch_size rows, stopping
+        // as soon as the consumer is done (e.g. the limit is reached)
+        let (sender, receiver) = mpsc::channel(2);
+        let task = SpawnedTask::spawn(async move {
+            let mut error_sender = sender.clone();
+            if let Err(e) = partition.read(batch_size, sender).await {
+                let _ = error_sender
+                    .send(Err(DataFusionError::External(Box::new(e))))
+                    .await;
+            }
+        });
+        let batches = receiver.chain(stream::once(async move { task.join().await }).filter_map(
+            |result| async move {
+                result
+                    .err()
+                    .map(|e| Err(DataFusionError::External(Box::new(e))))
+            },
+        ));
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+impl RemotePartition {
+    async fn read(
+        &self,
+        batch_size: usize,
+        sender: mpsc::Sender<datafusion::common::Result<RecordBatch>>,
+    ) -> Result<(), Exception> {
+        fetch_rows(self.kind, &self.url, &self.sql, batch_size, |rows| {
+            let mut sender = sender.clone();
+            let batch = self
+                .to_record_batch(rows)
+                .map_err(|e| DataFusionError::External(Box::new(e)));
+            async move {
+                let failed = batch.is_err();
+                sender.send(batch).await.is_ok() && !failed
+            }
+        })
+        .await
+    }
+
+    fn to_record_batch(&self, rows: Rows) -> Result<RecordBatch, Exception> {
+        let arrays = self
+            .schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let array = StringArray::from(
+                    rows.iter()
+                        .map(|row| row.get(i).cloned().flatten())
+                        .collect::<Vec<_>>(),
+                );
+                // 无法转换为列类型的值为 null
+                // Values that cannot be converted to the column type become null
+                cast(&array, field.data_type()).map_err(Exception::from)
+            })
+            .collect::<Result<Vec<ArrayRef>, _>>()?;
+        let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+        RecordBatch::try_new_with_options(self.schema.clone(), arrays, &options)
+            .map_err(Exception::from)
+    }
+}
+
+/// 以文本协议执行查询，每读取 chunk_size 行调用一次 `on_rows`，其返回 false 时停止读取
+/// Run a query with the text protocol, calling `on_rows` every chunk_size rows and stopping
+/// once it returns false
+async fn fetch_rows<F, Fut>(
+    kind: RemoteKind,
+    url: &str,
+    sql: &str,
+    chunk_size: usize,
+    mut on_rows: F,
+) -> Result<(), Exception>
+where
+    F: FnMut(Rows) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut rows: Rows = Vec::new();
+    match kind {
+        RemoteKind::MySQL => {
+            let opts = mysql_async::Opts::from_url(url).map_err(mysql_async::Error::from)?;
+            let mut conn = mysql_async::Conn::new(opts).await?;
+            let mut result = conn.query_iter(sql).await?;
+            if let Some(stream) = result.stream::<mysql_async::Row>().await? {
+                pin_mut!(stream);
+                while let Some(row) = stream.next().await {
+                    let row = row?;
+                    rows.push((0..row.len()).map(|i| mysql_text(row.as_ref(i))).collect());
+                    if rows.len() >= chunk_size && !on_rows(std::mem::take(&mut rows)).await {
+                        return Ok(());
+                    }
+                }
+            }
+            drop(result);
+            conn.disconnect().await?;
+        }
+        RemoteKind::Postgres => {
+            let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+            // 连接在后台任务中处理通信，客户端释放后结束
+            // The connection handles the communication in a background task until the client is dropped
+            let _connection = SpawnedTask::spawn(connection);
+            let stream = client.simple_query_raw(sql).await?;
+            pin_mut!(stream);
+            while let Some(message) = stream.next().await {
+                if let SimpleQueryMessage::Row(row) = message? {
+                    rows.push(
+                        (0..row.len())
+                            .map(|i| row.get(i).map(|value| value.to_string()))
+                            .collect(),
+                    );
+                    if rows.len() >= chunk_size && !on_rows(std::mem::take(&mut rows)).await {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+    if !rows.is_empty() {
+        on_rows(rows).await;
+    }
+    Ok(())
+}
+
+fn mysql_text(value: Option<&mysql_async::Value>) -> Option<String> {
+    match value? {
+        mysql_async::Value::NULL => None,
+        mysql_async::Value::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        value => Some(value.as_sql(true).trim_matches('\'').to_string()),
+    }
+}
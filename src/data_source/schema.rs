@@ -1,4 +1,6 @@
-use serde::Deserialize;
+use crate::response::http_error::Exception;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, PartialEq, Deserialize)]
 pub enum DataSourceFormat {
@@ -6,5 +8,121 @@ pub enum DataSourceFormat {
     JSON,
     NdJson { file_extension: String },
     TSV,
-    XLSX
+    XLSX,
+    Parquet,
+    Text,
+    Log,
+    FixedWidth,
+    XML,
+    SQLite,
+    Delta,
+    Iceberg,
+    Avro,
+    Arrow,
+}
+
+/// 读取文件时支持的选项，表函数中的其他命名参数会被拒绝
+/// The options supported when reading files, other named arguments of table functions are rejected
+pub const TABLE_OPTION_KEYS: [&str; 29] = [
+    "cache",
+    "comment",
+    "compression",
+    "delimiter",
+    "encoding",
+    "escape",
+    "file_extension",
+    "flatten",
+    "format",
+    "header",
+    "header_row",
+    "keep_unmatched",
+    "metadata_columns",
+    "partition_columns",
+    "pattern",
+    "preset",
+    "quote",
+    "range",
+    "row_path",
+    "schema_infer_max_records",
+    "sheet",
+    "snapshot_id",
+    "strict_schema",
+    "table",
+    "timestamp",
+    "timestamp_format",
+    "trim",
+    "version",
+    "widths",
+];
+
+/// 表级读取选项，例如 `delimiter`、`header`、`sheet`
+/// Table-level read options such as `delimiter`, `header` or `sheet`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TableOptions(BTreeMap<String, String>);
+
+impl TableOptions {
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into().to_lowercase(), value.into());
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    /// 补充尚未设置的选项，已有选项保持不变
+    /// Fill in options that are not set yet, keeping the existing ones
+    pub fn merge_defaults(&mut self, defaults: &TableOptions) {
+        for (key, value) in &defaults.0 {
+            self.0.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(|value| value.as_str())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Exception> {
+        match self.get(key) {
+            Some(value) => match value.to_lowercase().as_str() {
+                "true" | "1" | "yes" => Ok(Some(true)),
+                "false" | "0" | "no" => Ok(Some(false)),
+                _ => Err(Exception::bad_request_error(format!(
+                    "Option '{}' expects a boolean, got '{}'",
+                    key, value
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_byte(&self, key: &str) -> Result<Option<u8>, Exception> {
+        match self.get(key) {
+            Some(value) => {
+                let value = match value {
+                    "\\t" => "\t",
+                    _ => value,
+                };
+                match value.as_bytes() {
+                    [byte] => Ok(Some(*byte)),
+                    _ => Err(Exception::bad_request_error(format!(
+                        "Option '{}' expects a single character, got '{}'",
+                        key, value
+                    ))),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_usize(&self, key: &str) -> Result<Option<usize>, Exception> {
+        match self.get(key) {
+            Some(value) => value.parse::<usize>().map(Some).map_err(|_| {
+                Exception::bad_request_error(format!(
+                    "Option '{}' expects a non-negative integer, got '{}'",
+                    key, value
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
 }
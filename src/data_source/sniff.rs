@@ -0,0 +1,168 @@
+use crate::data_source::compression::{
+    detect_compression_type, get_compression_type, open_decompressed,
+};
+use crate::data_source::encoding::decode_head;
+use crate::data_source::schema::{DataSourceFormat, TableOptions};
+use crate::data_source::utils::{find_files, get_file_extension};
+use crate::response::http_error::Exception;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use std::io::Read;
+
+const SNIFF_SIZE: usize = 64 * 1024;
+const SNIFF_LINES: usize = 20;
+const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+pub struct SniffResult {
+    pub format: DataSourceFormat,
+    pub options: TableOptions,
+    pub description: String,
+}
+
+/// 读取第一个匹配文件的开头部分，根据魔数与内容推断数据格式
+/// Read the head of the first matching file and infer its format from magic bytes and content
+pub fn sniff_format(table_path: &str) -> Result<Option<SniffResult>, Exception> {
+    let file = match find_files(table_path)?.into_iter().next() {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let file_compression_type = get_compression_type(&file);
+    let buffer = read_head(&file, file_compression_type)?;
+    let file_extension = get_file_extension(table_path);
+
+    // 扩展名未标明压缩但内容是压缩数据时，解压后再推断，并记录压缩类型
+    // When the content is compressed but the extension does not say so, sniff the decompressed
+    // data and record the compression type
+    if let Some((compression_type, compression)) = detect_compression_type(&buffer) {
+        if file_compression_type == FileCompressionType::UNCOMPRESSED {
+            let mut sniff = sniff_bytes(&read_head(&file, compression_type)?, &file_extension)?;
+            sniff.options.insert("compression", compression);
+            sniff.description = format!("{} compressed {}", compression, sniff.description);
+            return Ok(Some(sniff));
+        }
+    }
+
+    sniff_bytes(&buffer, &file_extension).map(Some)
+}
+
+fn read_head(file: &str, compression_type: FileCompressionType) -> Result<Vec<u8>, Exception> {
+    let mut buffer = Vec::with_capacity(SNIFF_SIZE);
+    open_decompressed(file, compression_type)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn sniff_bytes(buffer: &[u8], file_extension: &str) -> Result<SniffResult, Exception> {
+    let mut options = TableOptions::default();
+
+    if buffer.starts_with(b"PAR1") {
+        return Ok(result(DataSourceFormat::Parquet, options, "Parquet"));
+    }
+    if buffer.starts_with(b"PK\x03\x04") {
+        return Ok(result(
+            DataSourceFormat::XLSX,
+            options,
+            "spreadsheet (xlsx, xlsb or ods)",
+        ));
+    }
+    // 旧版 `.xls` 为 OLE2 复合文档
+    // Legacy `.xls` files are OLE2 compound documents
+    if buffer.starts_with(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+        return Ok(result(DataSourceFormat::XLSX, options, "spreadsheet (xls)"));
+    }
+    if buffer.starts_with(b"SQLite format 3\0") {
+        return Ok(result(DataSourceFormat::SQLite, options, "SQLite database"));
+    }
+    if buffer.starts_with(b"ARROW1") {
+        return Ok(result(DataSourceFormat::Arrow, options, "Arrow IPC"));
+    }
+    if buffer.starts_with(b"Obj\x01") {
+        return Ok(result(DataSourceFormat::Avro, options, "Avro"));
+    }
+    if let Some((_, compression)) = detect_compression_type(buffer) {
+        return Err(Exception::unprocessable_entity_error(format!(
+            "Detected nested {} compressed content, which is currently not supported.",
+            compression
+        )));
+    }
+
+    // 非 UTF-8 的文本（如 GBK、UTF-16）先转换为 UTF-8
+    // Text in another encoding than UTF-8 (e.g. GBK or UTF-16) is converted into UTF-8 first
+    let decoded = decode_head(buffer);
+    let text = String::from_utf8_lossy(decoded.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&decoded));
+    let mut lines = text.lines().collect::<Vec<_>>();
+    // 最后一行可能被截断，不参与推断
+    // The last line may be truncated, so it is excluded from inference
+    if buffer.len() >= SNIFF_SIZE {
+        lines.pop();
+    }
+    lines.truncate(SNIFF_LINES);
+    lines.retain(|line| !line.trim().is_empty());
+
+    if text.trim_start().starts_with("<?xml") {
+        return Ok(result(DataSourceFormat::XML, options, "XML"));
+    }
+
+    match text.trim_start().chars().next() {
+        Some('{') if lines.iter().all(|line| line.trim_start().starts_with('{')) => {
+            let format = DataSourceFormat::NdJson {
+                file_extension: file_extension.to_string(),
+            };
+            return Ok(result(format, options, "NdJson"));
+        }
+        Some('[') => return Ok(result(DataSourceFormat::JSON, options, "JSON")),
+        _ => {}
+    }
+
+    match detect_delimiter(&lines) {
+        Some(b'\t') => Ok(result(DataSourceFormat::TSV, options, "TSV")),
+        Some(delimiter) => {
+            options.insert("delimiter", (delimiter as char).to_string());
+            let description = format!("CSV (delimiter '{}')", delimiter as char);
+            Ok(result(DataSourceFormat::CSV, options, &description))
+        }
+        None => Ok(result(DataSourceFormat::Text, options, "plain text")),
+    }
+}
+
+fn result(format: DataSourceFormat, options: TableOptions, description: &str) -> SniffResult {
+    SniffResult {
+        format,
+        options,
+        description: description.to_string(),
+    }
+}
+
+/// 选择在每一行中出现次数一致且最多的分隔符
+/// Pick the delimiter that occurs the same number of times on every line, preferring the most frequent
+fn detect_delimiter(lines: &[&str]) -> Option<u8> {
+    DELIMITERS
+        .iter()
+        .filter_map(|delimiter| {
+            let counts = lines
+                .iter()
+                .map(|line| count_unquoted(line, *delimiter))
+                .collect::<Vec<_>>();
+            let first = *counts.first()?;
+            if first > 0 && counts.iter().all(|count| *count == first) {
+                Some((*delimiter, first))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(_, count)| *count)
+        .map(|(delimiter, _)| delimiter)
+}
+
+fn count_unquoted(line: &str, delimiter: u8) -> usize {
+    let mut quoted = false;
+    line.bytes()
+        .filter(|byte| {
+            if *byte == b'"' {
+                quoted = !quoted;
+            }
+            !quoted && *byte == delimiter
+        })
+        .count()
+}
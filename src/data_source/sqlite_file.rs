@@ -0,0 +1,416 @@
+use crate::data_source::pushdown::filter_to_sql;
+use crate::response::http_error::Exception;
+use arrow::array::{
+    ArrayBuilder, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use async_trait::async_trait;
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::runtime::SpawnedTask;
+use datafusion::common::{DataFusionError, ScalarValue};
+use datafusion::datasource::TableType;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::{stream, SinkExt, StreamExt};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::any::Any;
+use std::path::Path;
+use std::sync::Arc;
+
+/// SQLite 数据库文件中的表或视图，以只读方式打开，投影与简单的过滤条件转换为 SQL 在 SQLite 中执行
+/// A table or view inside a SQLite database file, opened read-only, with projections and
+/// simple filters translated into SQL that SQLite executes
+#[derive(Debug)]
+pub struct SqliteTable {
+    file: String,
+    table: String,
+    schema: SchemaRef,
+}
+
+impl SqliteTable {
+    /// 表名来自路径中 `#` 之后的片段（如 `app.db#users`）或 `table` 选项，数据库只有一张表时可以省略
+    /// The table comes from the fragment after `#` in the path (e.g. `app.db#users`) or the
+    /// `table` option, and may be omitted when the database has a single table
+    pub fn try_new(file_path: &str, table: Option<&str>) -> Result<Self, Exception> {
+        let (file, table) = match file_path.split_once('#') {
+            Some((file, fragment)) if !fragment.is_empty() => (file, Some(fragment)),
+            Some((file, _)) => (file, table),
+            None => (file_path, table),
+        };
+        if !Path::new(file).is_file() {
+            return Err(Exception::file_not_found_error(file));
+        }
+        let conn = open(file)?;
+
+        let mut statement = conn.prepare(
+            "select name from sqlite_master where type in ('table', 'view') and name not like 'sqlite_%' order by name",
+        )?;
+        let tables = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let table = match table {
+            Some(table) => match tables.iter().find(|name| name.eq_ignore_ascii_case(table)) {
+                Some(name) => name.clone(),
+                None => {
+                    return Err(Exception::bad_request_error(format!(
+                        "Table '{}' not found in '{}', available tables: {}",
+                        table,
+                        file,
+                        tables.join(", ")
+                    )))
+                }
+            },
+            None if tables.len() == 1 => tables[0].clone(),
+            None => {
+                return Err(Exception::bad_request_error(format!(
+                    "'{}' contains {} tables, select one with '{}#table', available tables: {}",
+                    file,
+                    tables.len(),
+                    file,
+                    tables.join(", ")
+                )))
+            }
+        };
+
+        let mut statement = conn.prepare(&format!("pragma table_info({})", quote(&table)))?;
+        let fields = statement
+            .query_map([], |row| {
+                let name: String = row.get(1)?;
+                let declared_type: String = row.get(2)?;
+                Ok(Field::new(name, to_data_type(&declared_type), true))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SqliteTable {
+            file: file.to_string(),
+            table,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+}
+
+#[async_trait]
+impl TableProvider for SqliteTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    /// SQLite 的比较与 `LIKE` 语义与 DataFusion 不完全相同（如 `LIKE` 不区分 ASCII 大小写），
+    /// 因此下推的条件仍由 DataFusion 再次过滤
+    /// SQLite compares values and evaluates `LIKE` slightly differently from DataFusion (e.g.
+    /// `LIKE` ignores ASCII case), so pushed down filters are still applied again by DataFusion
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> datafusion::common::Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match to_sql(filter, &mut Vec::new()) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion::common::Result<Arc<dyn ExecutionPlan>> {
+        let projection = match projection {
+            Some(projection) => projection.clone(),
+            None => (0..self.schema.fields().len()).collect(),
+        };
+        let schema = Arc::new(self.schema.project(&projection)?);
+
+        let columns = match schema.fields().is_empty() {
+            // `count(*)` 等查询不需要任何列
+            // Queries such as `count(*)` need no columns at all
+            true => "1".to_string(),
+            false => schema
+                .fields()
+                .iter()
+                .map(|field| quote(field.name()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mut sql = format!("select {} from {}", columns, quote(&self.table));
+        let mut parameters = Vec::new();
+        let conditions = filters
+            .iter()
+            .filter_map(|filter| to_sql(filter, &mut parameters))
+            .collect::<Vec<_>>();
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" where {}", conditions.join(" and ")));
+        }
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" limit {}", limit));
+        }
+
+        let partition = SqlitePartition {
+            file: self.file.clone(),
+            sql,
+            parameters,
+            schema: schema.clone(),
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(partition)],
+            None,
+            Vec::new(),
+            false,
+            limit,
+        )?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SqlitePartition {
+    file: String,
+    sql: String,
+    parameters: Vec<Value>,
+    schema: SchemaRef,
+}
+
+type BatchSender = mpsc::Sender<datafusion::common::Result<RecordBatch>>;
+
+impl PartitionStream for SqlitePartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let batch_size = ctx.session_config().batch_size().max(1);
+        let partition = self.clone();
+        // 查询在阻塞线程池中执行，每读取 batch_size 行发送一个批次，查询结束（如达到 limit）后停止读取
+        // The query runs on the blocking thread pool and sends a batch every batch_size rows,
+        // stopping as soon as the consumer is done (e.g. the limit is reached)
+        let (mut sender, receiver) = mpsc::channel(2);
+        let task = SpawnedTask::spawn_blocking(move || {
+            if let Err(e) = partition.read(batch_size, &mut sender) {
+                let _ = block_on(sender.send(Err(DataFusionError::External(Box::new(e)))));
+            }
+        });
+        let batches = receiver.chain(stream::once(async move { task.join().await }).filter_map(
+            |result| async move {
+                result
+                    .err()
+                    .map(|e| Err(DataFusionError::External(Box::new(e))))
+            },
+        ));
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+impl SqlitePartition {
+    fn read(&self, batch_size: usize, sender: &mut BatchSender) -> Result<(), Exception> {
+        let conn = open(&self.file)?;
+        let mut statement = conn.prepare(&self.sql)?;
+        let mut rows = statement.query(params_from_iter(self.parameters.iter()))?;
+
+        let mut builders = self.new_builders(batch_size);
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            for (i, (field, builder)) in self.schema.fields().iter().zip(&mut builders).enumerate()
+            {
+                append_value(builder.as_mut(), field.data_type(), row.get_ref(i)?);
+            }
+            count += 1;
+            if count == batch_size {
+                let batch = self.finish(&mut builders, count)?;
+                if block_on(sender.send(Ok(batch))).is_err() {
+                    return Ok(());
+                }
+                count = 0;
+            }
+        }
+        if count > 0 {
+            let batch = self.finish(&mut builders, count)?;
+            let _ = block_on(sender.send(Ok(batch)));
+        }
+        Ok(())
+    }
+
+    fn new_builders(&self, capacity: usize) -> Vec<Box<dyn ArrayBuilder>> {
+        self.schema
+            .fields()
+            .iter()
+            .map(|field| -> Box<dyn ArrayBuilder> {
+                match field.data_type() {
+                    DataType::Int64 => Box::new(Int64Builder::with_capacity(capacity)),
+                    DataType::Float64 => Box::new(Float64Builder::with_capacity(capacity)),
+                    DataType::Boolean => Box::new(BooleanBuilder::with_capacity(capacity)),
+                    DataType::Binary => Box::new(BinaryBuilder::with_capacity(capacity, 0)),
+                    _ => Box::new(StringBuilder::with_capacity(capacity, 0)),
+                }
+            })
+            .collect()
+    }
+
+    fn finish(
+        &self,
+        builders: &mut [Box<dyn ArrayBuilder>],
+        rows: usize,
+    ) -> Result<RecordBatch, Exception> {
+        let arrays = builders
+            .iter_mut()
+            .map(|builder| builder.finish())
+            .collect::<Vec<ArrayRef>>();
+        let options = RecordBatchOptions::new().with_row_count(Some(rows));
+        RecordBatch::try_new_with_options(self.schema.clone(), arrays, &options)
+            .map_err(Exception::from)
+    }
+}
+
+fn open(file: &str) -> Result<Connection, Exception> {
+    Connection::open_with_flags(
+        file,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(Exception::from)
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// 按 SQLite 的类型亲和性规则，将声明的列类型转换为 Arrow 数据类型；日期时间在 SQLite 中通常保存为文本
+/// Convert a declared column type into an Arrow data type following SQLite's type affinity
+/// rules; dates and times are usually stored as text in SQLite
+fn to_data_type(declared_type: &str) -> DataType {
+    let declared_type = declared_type.to_uppercase();
+    if declared_type.contains("INT") {
+        DataType::Int64
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|name| declared_type.contains(name))
+    {
+        DataType::Utf8
+    } else if declared_type.contains("BLOB") {
+        DataType::Binary
+    } else if ["REAL", "FLOA", "DOUB", "NUMERIC", "DECIMAL"]
+        .iter()
+        .any(|name| declared_type.contains(name))
+    {
+        DataType::Float64
+    } else if declared_type.contains("BOOL") {
+        DataType::Boolean
+    } else {
+        DataType::Utf8
+    }
+}
+
+/// SQLite 的列可以保存任意类型的值，无法转换为列类型的值为 null
+/// A SQLite column may hold values of any type, and values that cannot be converted to the
+/// column type become null
+fn append_value(builder: &mut dyn ArrayBuilder, data_type: &DataType, value: ValueRef) {
+    let text = |value: ValueRef| match value {
+        ValueRef::Null => None,
+        ValueRef::Integer(value) => Some(value.to_string()),
+        ValueRef::Real(value) => Some(value.to_string()),
+        ValueRef::Text(value) | ValueRef::Blob(value) => {
+            Some(String::from_utf8_lossy(value).into_owned())
+        }
+    };
+    match data_type {
+        DataType::Int64 => {
+            let value = match value {
+                ValueRef::Integer(value) => Some(value),
+                ValueRef::Real(value) if value.fract() == 0.0 => Some(value as i64),
+                value => text(value).and_then(|value| value.trim().parse().ok()),
+            };
+            downcast::<Int64Builder>(builder).append_option(value)
+        }
+        DataType::Float64 => {
+            let value = match value {
+                ValueRef::Integer(value) => Some(value as f64),
+                ValueRef::Real(value) => Some(value),
+                value => text(value).and_then(|value| value.trim().parse().ok()),
+            };
+            downcast::<Float64Builder>(builder).append_option(value)
+        }
+        DataType::Boolean => {
+            let value = match value {
+                ValueRef::Integer(value) => Some(value != 0),
+                value => match text(value)
+                    .map(|value| value.trim().to_lowercase())
+                    .as_deref()
+                {
+                    Some("true" | "1") => Some(true),
+                    Some("false" | "0") => Some(false),
+                    _ => None,
+                },
+            };
+            downcast::<BooleanBuilder>(builder).append_option(value)
+        }
+        DataType::Binary => {
+            let builder = downcast::<BinaryBuilder>(builder);
+            match value {
+                ValueRef::Null => builder.append_null(),
+                ValueRef::Text(value) | ValueRef::Blob(value) => builder.append_value(value),
+                value => builder.append_option(text(value)),
+            }
+        }
+        _ => downcast::<StringBuilder>(builder).append_option(text(value)),
+    }
+}
+
+fn downcast<T: 'static>(builder: &mut dyn ArrayBuilder) -> &mut T {
+    builder
+        .as_any_mut()
+        .downcast_mut::<T>()
+        .expect("builder created for the column type")
+}
+
+/// 过滤条件中的字面量作为参数绑定，SQLite 的 `LIKE` 默认没有转义字符，需指定 DataFusion 使用的 `\`
+/// Literals in the filters are bound as parameters, and `LIKE` is given DataFusion's `\` escape
+/// character since SQLite has none by default
+fn to_sql(expr: &Expr, parameters: &mut Vec<Value>) -> Option<String> {
+    let mut values = Vec::new();
+    let sql = filter_to_sql(
+        expr,
+        &quote,
+        &mut |value| {
+            values.push(to_value(value)?);
+            Some("?".to_string())
+        },
+        Some("'\\'"),
+    )?;
+    parameters.extend(values);
+    Some(sql)
+}
+
+fn to_value(value: &ScalarValue) -> Option<Value> {
+    match value {
+        ScalarValue::Boolean(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::Int8(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::Int16(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::Int32(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::Int64(Some(value)) => Some(Value::Integer(*value)),
+        ScalarValue::UInt8(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::UInt16(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::UInt32(Some(value)) => Some(Value::Integer(*value as i64)),
+        ScalarValue::Float32(Some(value)) => Some(Value::Real(*value as f64)),
+        ScalarValue::Float64(Some(value)) => Some(Value::Real(*value)),
+        ScalarValue::Utf8(Some(value))
+        | ScalarValue::LargeUtf8(Some(value))
+        | ScalarValue::Utf8View(Some(value)) => Some(Value::Text(value.clone())),
+        _ => None,
+    }
+}
@@ -0,0 +1,465 @@
+use crate::config::get_config;
+use crate::data_source::utils::{find_files_recursive, get_local_copy_path};
+use crate::response::http_error::Exception;
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::execution::object_store::ObjectStoreUrl;
+use datafusion::prelude::SessionContext;
+use futures::TryStreamExt;
+use glob::{MatchOptions, Pattern};
+use object_store::aws::{AmazonS3Builder, AmazonS3ConfigKey};
+use object_store::http::HttpBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ClientOptions, ObjectStore};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
+use tokio::runtime::Runtime;
+use url::Url;
+
+const S3_SCHEME: &str = "s3://";
+const HTTP_SCHEMES: [&str; 2] = ["http://", "https://"];
+/// 缓存的远程文件保存在临时目录下的该子目录中
+/// Cached remote files are kept in this subdirectory of the tmp dir
+pub const CACHE_DIR: &str = "cache";
+/// 按桶覆盖的 S3 配置项，对应环境变量 `S3_<BUCKET>_<KEY>`，未设置时使用 `AWS_<KEY>`
+/// The S3 settings that can be overridden per bucket through `S3_<BUCKET>_<KEY>` environment
+/// variables, falling back to `AWS_<KEY>`
+const S3_SETTINGS: [&str; 8] = [
+    "ACCESS_KEY_ID",
+    "SECRET_ACCESS_KEY",
+    "SESSION_TOKEN",
+    "REGION",
+    "ENDPOINT",
+    "ALLOW_HTTP",
+    "VIRTUAL_HOSTED_STYLE_REQUEST",
+    "SKIP_SIGNATURE",
+];
+/// 分段读取对象时第一段的大小，之后每段翻倍直到上限
+/// The size of the first range read from an object, doubled for every following range up to the limit
+const FIRST_RANGE_SIZE: u64 = 64 * 1024;
+const MAX_RANGE_SIZE: u64 = 64 * 1024 * 1024;
+/// 执行同步代码中对象存储请求的运行时的线程数
+/// The number of threads of the runtime running object store requests from synchronous code
+const RUNTIME_THREADS: usize = 2;
+
+/// 按桶或 HTTP 服务器缓存的对象存储客户端，复用其中的连接池
+/// The object store clients cached per bucket or HTTP server, so their connection pools are reused
+static STORES: LazyLock<Mutex<HashMap<String, Arc<dyn ObjectStore>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 同步代码中的对象存储请求共用的运行时
+/// The runtime shared by the object store requests made from synchronous code
+static RUNTIME: LazyLock<io::Result<Runtime>> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(RUNTIME_THREADS)
+        .thread_name("object-store")
+        .enable_all()
+        .build()
+});
+
+/// 是否为对象存储中的路径，例如 `s3://bucket/prefix/*.csv` 或 `https://host/path/file.csv`
+/// Whether a path points into object storage, e.g. `s3://bucket/prefix/*.csv` or `https://host/path/file.csv`
+pub fn is_object_store_path(path: &str) -> bool {
+    get_scheme(path).is_some()
+}
+
+fn get_scheme(path: &str) -> Option<&'static str> {
+    let path = path.trim_matches('\'');
+    std::iter::once(S3_SCHEME)
+        .chain(HTTP_SCHEMES)
+        .find(|scheme| path.starts_with(scheme))
+}
+
+/// 对象存储中的位置：S3 的桶或 HTTP 服务器，以及对象键（可以包含 glob）
+/// A location in object storage: the S3 bucket or HTTP server, and the object key, which may contain a glob
+#[derive(Debug, Clone)]
+struct ObjectLocation {
+    scheme: &'static str,
+    bucket: String,
+    key: String,
+}
+
+impl ObjectLocation {
+    fn parse(path: &str) -> Result<Self, Exception> {
+        let path = path.trim_matches('\'');
+        let scheme = get_scheme(path).ok_or_else(|| {
+            Exception::bad_request_error(format!("Unsupported object store location '{}'", path))
+        })?;
+        let rest = &path[scheme.len()..];
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(Exception::bad_request_error(format!(
+                "The bucket or host is missing in '{}'",
+                path
+            )));
+        }
+        Ok(Self {
+            scheme,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    fn bucket_url(&self) -> String {
+        format!("{}{}", self.scheme, self.bucket)
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.bucket_url(), key)
+    }
+
+    /// 获取对象存储的客户端，每个桶或 HTTP 服务器只创建一次
+    /// Get the client of the object store, created only once per bucket or HTTP server
+    fn store(&self) -> Result<Arc<dyn ObjectStore>, Exception> {
+        let bucket_url = self.bucket_url();
+        if let Some(store) = STORES.lock().unwrap().get(&bucket_url) {
+            return Ok(store.clone());
+        }
+        let store = self.build_store()?;
+        Ok(STORES
+            .lock()
+            .unwrap()
+            .entry(bucket_url)
+            .or_insert(store)
+            .clone())
+    }
+
+    /// 创建对象存储的客户端，HTTP 地址使用 HTTP 客户端；S3 桶先读取 `AWS_ACCESS_KEY_ID`、`AWS_ENDPOINT` 等环境变量，再按桶使用
+    /// 配置文件中的 `[s3.<bucket>]` 与 `S3_<BUCKET>_ENDPOINT` 等覆盖（桶名大写，非字母数字字符替换为 `_`），便于连接 MinIO 等兼容服务
+    /// Create the client of the object store, an HTTP client for HTTP locations; an S3 bucket reads the `AWS_ACCESS_KEY_ID`, `AWS_ENDPOINT`, ...
+    /// environment variables, overridden per bucket by `[s3.<bucket>]` in the configuration file
+    /// and then by `S3_<BUCKET>_ENDPOINT`, ... (the bucket name in upper case with other characters
+    /// than letters and digits replaced by `_`), so compatible services such as MinIO can be used
+    fn build_store(&self) -> Result<Arc<dyn ObjectStore>, Exception> {
+        if self.scheme != S3_SCHEME {
+            // HTTP 服务器通过 HEAD 与 Range 请求读取文件，列出目录需要服务器支持 WebDAV；只有 `http://` 地址允许明文 HTTP
+            // HTTP servers are read with HEAD and range requests, listing directories requires WebDAV;
+            // plain HTTP is only allowed for `http://` locations
+            return Ok(Arc::new(
+                HttpBuilder::new()
+                    .with_url(self.bucket_url())
+                    .with_client_options(
+                        ClientOptions::new().with_allow_http(self.scheme == HTTP_SCHEMES[0]),
+                    )
+                    .build()?,
+            ));
+        }
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&self.bucket);
+        let prefix = format!(
+            "S3_{}_",
+            self.bucket
+                .to_uppercase()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+        if let Some(bucket) = get_config().s3.get(&self.bucket) {
+            for (setting, value) in bucket.settings() {
+                let key = AmazonS3ConfigKey::from_str(&format!("aws_{}", setting.to_lowercase()))?;
+                builder = builder.with_config(key, value);
+            }
+        }
+        for setting in S3_SETTINGS {
+            if let Ok(value) = env::var(format!("{}{}", prefix, setting)) {
+                let key = AmazonS3ConfigKey::from_str(&format!("aws_{}", setting.to_lowercase()))?;
+                builder = builder.with_config(key, value);
+            }
+        }
+        // 使用 http 的 endpoint（如本地的 MinIO）需要允许 http
+        // An http endpoint such as a local MinIO requires allowing http
+        if builder
+            .get_config_value(&AmazonS3ConfigKey::Endpoint)
+            .is_some_and(|endpoint| endpoint.starts_with("http://"))
+        {
+            builder = builder.with_allow_http(true);
+        }
+        Ok(Arc::new(builder.build()?))
+    }
+
+    /// 列出匹配的对象：包含 glob 时从 glob 之前的目录开始列出并匹配，以 `/` 结尾时为目录下除标记文件以外的对象，
+    /// `recursive` 时包含子目录中的对象
+    /// List the matching objects: a glob is matched against the objects listed from the directory
+    /// before it, and a key ending with `/` is a directory whose objects are listed except marker
+    /// files, including the objects in subdirectories when `recursive`
+    async fn list(&self, recursive: bool) -> Result<Vec<String>, Exception> {
+        let store = self.store()?;
+        let (prefix, pattern) = match self.key.find(['*', '?', '[']) {
+            Some(index) => {
+                let prefix = match self.key[..index].rfind('/') {
+                    Some(end) => &self.key[..end],
+                    None => "",
+                };
+                (prefix, Some(Pattern::new(&self.key)?))
+            }
+            None if self.key.is_empty() || self.key.ends_with('/') => (self.key.as_str(), None),
+            None => {
+                return match store.head(&ObjectPath::from(self.key.as_str())).await {
+                    Ok(_) => Ok(vec![self.url(&self.key)]),
+                    Err(object_store::Error::NotFound { .. }) => Ok(Vec::new()),
+                    Err(e) => Err(e.into()),
+                };
+            }
+        };
+
+        let prefix = Some(ObjectPath::from(prefix)).filter(|prefix| !prefix.as_ref().is_empty());
+        let objects = store.list(prefix.as_ref()).try_collect::<Vec<_>>().await?;
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        let mut files = objects
+            .into_iter()
+            .map(|object| object.location.to_string())
+            .filter(|key| match &pattern {
+                Some(pattern) => pattern.matches_with(key, options),
+                None => {
+                    let start = prefix
+                        .as_ref()
+                        .map_or(0, |prefix| prefix.as_ref().len() + 1);
+                    let relative_key = key.get(start..).unwrap_or(key);
+                    (recursive || !relative_key.contains('/'))
+                        && !relative_key
+                            .rsplit('/')
+                            .next()
+                            .is_some_and(|name| name.starts_with(['_', '.']))
+                }
+            })
+            .map(|key| self.url(&key))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// 在共用的运行时中执行对象存储请求并等待结果：读取文件的代码是同步的，而且可能运行在 actix 的单线程运行时中，
+/// 不能在当前线程上启动运行时
+/// Run an object store request on the shared runtime and wait for the result, because the code
+/// reading files is synchronous and may run inside an actix single-threaded runtime, where no
+/// runtime can be started on the current thread
+fn block_on<T, F>(future: F) -> Result<T, Exception>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T, Exception>> + Send + 'static,
+{
+    let runtime = RUNTIME.as_ref().map_err(|e| {
+        Exception::internal_server_error(format!("Cannot start the object store runtime: {}", e))
+    })?;
+    futures::executor::block_on(runtime.spawn(future))
+        .map_err(|_| Exception::internal_server_error("The object store request panicked"))?
+}
+
+/// 为路径所在的桶在会话中注册对象存储，DataFusion 的 ListingTable 通过它列出与读取对象；
+/// 每个会话中每个桶只注册一次，使用缓存的客户端
+/// Register the object store of the path's bucket on the session, through which DataFusion's
+/// listing tables list and read the objects; every bucket is registered once per session with
+/// the cached client
+pub fn register_object_store(ctx: &SessionContext, path: &str) -> Result<(), Exception> {
+    let location = ObjectLocation::parse(path)?;
+    let url = ObjectStoreUrl::parse(location.bucket_url())?;
+    if ctx.runtime_env().object_store(&url).is_err() {
+        ctx.register_object_store(url.as_ref(), location.store()?);
+    }
+    Ok(())
+}
+
+/// 文件在 DataFusion 中的对象存储 URL 与对象路径，本地文件使用本地文件系统
+/// The object store URL and object path of a file in DataFusion, local files using the local file system
+pub fn get_object_store_location(path: &str) -> Result<(ObjectStoreUrl, ObjectPath), Exception> {
+    if is_object_store_path(path) {
+        let location = ObjectLocation::parse(path)?;
+        return Ok((
+            ObjectStoreUrl::parse(location.bucket_url())?,
+            ObjectPath::from(location.key.as_str()),
+        ));
+    }
+    let location = ObjectPath::from_absolute_path(std::path::absolute(path)?)
+        .map_err(|e| Exception::bad_request_error(format!("Invalid path '{}': {}", path, e)))?;
+    Ok((ObjectStoreUrl::local_filesystem(), location))
+}
+
+/// 解析 ListingTable 的 URL：DataFusion 只为本地路径解析 glob，对象存储中的 glob 需要拆分为目录与模式
+/// Parse the URL of a listing table: DataFusion only parses globs in local paths, so a glob in
+/// object storage is split into the directory and the pattern
+pub fn parse_listing_table_url(path: &str) -> Result<ListingTableUrl, Exception> {
+    let index = match path.find(['*', '?', '[']) {
+        Some(index) if is_object_store_path(path) => index,
+        _ => return Ok(ListingTableUrl::parse(path)?),
+    };
+    let end = path[..index].rfind('/').map_or(index, |end| end + 1);
+    let url = Url::parse(&path[..end])
+        .map_err(|e| Exception::bad_request_error(format!("Invalid location '{}': {}", path, e)))?;
+    Ok(ListingTableUrl::try_new(
+        url,
+        Some(Pattern::new(&path[end..])?),
+    )?)
+}
+
+/// 查找对象存储中匹配 glob 的对象，返回完整的 URL
+/// Find the objects matching a glob in object storage, returning their full URLs
+pub fn find_objects(pattern: &str, recursive: bool) -> Result<Vec<String>, Exception> {
+    let location = ObjectLocation::parse(pattern)?;
+    block_on(async move { location.list(recursive).await })
+}
+
+/// 将匹配的远程文件下载到临时目录，返回对应的本地路径（保留 glob 与目录结构）；
+/// 已下载的文件在远程文件更新前一直复用，目录在 `recursive` 时包含子目录中的文件
+/// Download the matching remote files into the tmp dir and return the matching local path, which
+/// keeps the glob and directory structure; downloaded files are reused until the remote file
+/// changes, and directories include the files in subdirectories when `recursive`
+pub fn cache_objects(path: &str, recursive: bool) -> Result<String, Exception> {
+    let files = find_objects(path, recursive)?;
+    if files.is_empty() {
+        return Err(Exception::file_not_found_error(path));
+    }
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let root = get_config()
+        .storage
+        .tmp_dir
+        .join(CACHE_DIR)
+        .join(format!("{:016x}", hasher.finish()));
+    let mut targets = Vec::new();
+    for file in files {
+        let location = ObjectLocation::parse(&file)?;
+        let target = get_local_copy_path(&root, &file);
+        let cached = fs::metadata(&target)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        block_on(download(location, target.clone(), cached))?;
+        targets.push(target);
+    }
+    // 删除远程已不存在的文件，避免 glob 匹配到过期的副本
+    // Remove files that are gone remotely, so the glob does not match stale copies
+    for file in find_files_recursive(&root.display().to_string())? {
+        if !targets.iter().any(|target| Path::new(&file) == target) {
+            fs::remove_file(&file)?;
+        }
+    }
+    Ok(get_local_copy_path(&root, path).display().to_string())
+}
+
+/// 远程文件比缓存的副本新时重新下载，先写入临时文件，避免复用中断的下载
+/// Download a remote file again when it is newer than the cached copy, writing a temporary file
+/// first so an interrupted download is never reused
+async fn download(
+    location: ObjectLocation,
+    target: PathBuf,
+    cached: Option<SystemTime>,
+) -> Result<(), Exception> {
+    let store = location.store()?;
+    let key = ObjectPath::from(location.key.as_str());
+    let meta = store.head(&key).await?;
+    if cached.is_some_and(|cached| cached >= SystemTime::from(meta.last_modified)) {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = PathBuf::from(format!("{}.part", target.display()));
+    let mut file = File::create(&part)?;
+    let mut stream = store.get(&key).await?.into_stream();
+    while let Some(bytes) = stream.try_next().await? {
+        file.write_all(&bytes)?;
+    }
+    fs::rename(&part, &target)?;
+    Ok(())
+}
+
+/// 读取对象的最后修改时间
+/// Read the last modification time of an object
+pub fn get_object_modified(path: &str) -> Option<SystemTime> {
+    let location = ObjectLocation::parse(path).ok()?;
+    block_on(async move {
+        let meta = location
+            .store()?
+            .head(&ObjectPath::from(location.key.as_str()))
+            .await?;
+        Ok(SystemTime::from(meta.last_modified))
+    })
+    .ok()
+}
+
+/// 按需分段读取对象的 Reader，只下载实际读取的部分，例如推断格式时只读取开头
+/// A reader fetching ranges of an object on demand, so only the bytes actually read are
+/// downloaded, e.g. just the head when sniffing the format
+pub struct ObjectReader {
+    store: Arc<dyn ObjectStore>,
+    key: ObjectPath,
+    size: u64,
+    position: u64,
+    range_size: u64,
+    buffer: Vec<u8>,
+    offset: usize,
+}
+
+impl ObjectReader {
+    pub fn open(path: &str) -> Result<Self, Exception> {
+        let location = ObjectLocation::parse(path)?;
+        let store = location.store()?;
+        let key = ObjectPath::from(location.key.as_str());
+        let (head_store, head_key) = (store.clone(), key.clone());
+        let size = block_on(async move { Ok(head_store.head(&head_key).await?.size) })?;
+        Ok(Self {
+            store,
+            key,
+            size,
+            position: 0,
+            range_size: FIRST_RANGE_SIZE,
+            buffer: Vec::new(),
+            offset: 0,
+        })
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.buffer.len() {
+            if self.position >= self.size {
+                return Ok(0);
+            }
+            let range = self.position..(self.position + self.range_size).min(self.size);
+            let (store, key) = (self.store.clone(), self.key.clone());
+            let bytes = block_on(async move { Ok(store.get_range(&key, range).await?) })
+                .map_err(io::Error::other)?;
+            self.position += bytes.len() as u64;
+            self.range_size = (self.range_size * 2).min(MAX_RANGE_SIZE);
+            self.buffer = bytes.to_vec();
+            self.offset = 0;
+            if self.buffer.is_empty() {
+                return Ok(0);
+            }
+        }
+        let length = buf.len().min(self.buffer.len() - self.offset);
+        buf[..length].copy_from_slice(&self.buffer[self.offset..self.offset + length]);
+        self.offset += length;
+        Ok(length)
+    }
+}
+
+/// 移动位置时丢弃已下载的分段，例如 Arrow IPC 文件先读取文件尾
+/// Seeking drops the fetched range, e.g. when an Arrow IPC file reads its footer first
+impl Seek for ObjectReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let current = self.position - (self.buffer.len() - self.offset) as u64;
+        let position = match position {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => current.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+        if position != current {
+            self.position = position;
+            self.range_size = FIRST_RANGE_SIZE;
+            self.buffer.clear();
+            self.offset = 0;
+        }
+        Ok(position)
+    }
+}
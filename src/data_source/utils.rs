@@ -1,9 +1,24 @@
+use crate::data_source::compression::split_compression_extension;
 use crate::data_source::schema::DataSourceFormat;
+use crate::data_source::store::{find_objects, get_object_modified, is_object_store_path};
 use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, TimeUnit};
 use glob::glob;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use tokio::task;
 
 pub fn get_format(file_name: &str) -> Option<DataSourceFormat> {
     let file_name = file_name.trim_end_matches('\'');
+    // `#` 之后是工作表等片段，例如 `report.xlsx#Sheet1`
+    // A `#` starts a fragment such as the sheet in `report.xlsx#Sheet1`
+    let file_name = match file_name.find('#') {
+        Some(index) => &file_name[..index],
+        None => file_name,
+    };
+    let (file_name, _) = split_compression_extension(file_name);
     if file_name.ends_with(".csv") {
         Some(DataSourceFormat::CSV)
     } else if file_name.ends_with(".json") {
@@ -18,19 +33,196 @@ pub fn get_format(file_name: &str) -> Option<DataSourceFormat> {
         })
     } else if file_name.ends_with(".tsv") {
         Some(DataSourceFormat::TSV)
-    } else if file_name.ends_with(".xlsx") {
+    } else if [".xlsx", ".xlsm", ".xls", ".xlsb", ".ods"]
+        .iter()
+        .any(|extension| file_name.ends_with(extension))
+    {
         Some(DataSourceFormat::XLSX)
+    } else if file_name.ends_with(".parquet") {
+        Some(DataSourceFormat::Parquet)
+    } else if file_name.ends_with(".xml") {
+        Some(DataSourceFormat::XML)
+    } else if file_name.ends_with(".avro") {
+        Some(DataSourceFormat::Avro)
+    } else if [".arrow", ".feather", ".ipc"]
+        .iter()
+        .any(|extension| file_name.ends_with(extension))
+    {
+        Some(DataSourceFormat::Arrow)
+    } else if [".db", ".sqlite", ".sqlite3"]
+        .iter()
+        .any(|extension| file_name.ends_with(extension))
+    {
+        Some(DataSourceFormat::SQLite)
     } else {
         None
     }
 }
 
+/// 扩展名无法确定格式时（如 `.txt`、`.log`、`.dat`），需要根据文件内容推断
+/// Whether the extension alone cannot decide the format (e.g. `.txt`, `.log`, `.dat`) and the content must be sniffed
+pub fn is_ambiguous_format(format: &Option<DataSourceFormat>) -> bool {
+    matches!(
+        format,
+        None | Some(DataSourceFormat::JSON) | Some(DataSourceFormat::NdJson { .. })
+    )
+}
+
+/// 判断 SQL 中的表名是否为文件路径，例如 `'/path/example.dat'`
+/// Whether a table name in SQL is a file path such as `'/path/example.dat'`
+pub fn is_file_path(table_name: &str) -> bool {
+    table_name.starts_with('\'') || get_format(table_name).is_some()
+}
+
+/// 根据格式名称（如 `csv`、`ndjson`）确定数据源格式
+/// Determine the data source format from a format name such as `csv` or `ndjson`
+pub fn get_format_by_name(name: &str, file_name: &str) -> Option<DataSourceFormat> {
+    match name.to_lowercase().as_str() {
+        "csv" => Some(DataSourceFormat::CSV),
+        "tsv" => Some(DataSourceFormat::TSV),
+        "json" | "ndjson" => Some(DataSourceFormat::NdJson {
+            file_extension: get_file_extension(file_name),
+        }),
+        "xlsx" | "xlsm" | "xls" | "xlsb" | "ods" => Some(DataSourceFormat::XLSX),
+        "parquet" => Some(DataSourceFormat::Parquet),
+        "text" => Some(DataSourceFormat::Text),
+        "log" => Some(DataSourceFormat::Log),
+        "fixed_width" | "fwf" => Some(DataSourceFormat::FixedWidth),
+        "xml" => Some(DataSourceFormat::XML),
+        "sqlite" => Some(DataSourceFormat::SQLite),
+        "delta" => Some(DataSourceFormat::Delta),
+        "iceberg" => Some(DataSourceFormat::Iceberg),
+        "avro" => Some(DataSourceFormat::Avro),
+        "arrow" | "feather" | "ipc" => Some(DataSourceFormat::Arrow),
+        _ => None,
+    }
+}
+
+/// 获取文件扩展名（包含 `.` 与压缩扩展名，如 `.csv.gz`），没有扩展名或扩展名含通配符时返回空字符串
+/// Get the file extension including the `.` and any compression suffix (e.g. `.csv.gz`),
+/// or an empty string if there is none or it contains wildcards
+pub fn get_file_extension(file_name: &str) -> String {
+    let file_name = file_name.trim_matches('\'');
+    let file_name = match file_name.find('#') {
+        Some(index) => &file_name[..index],
+        None => file_name,
+    };
+    let (file_name, compression_extension) = split_compression_extension(file_name);
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| format!(".{}{}", extension, compression_extension))
+        .unwrap_or_else(|| compression_extension.to_string());
+
+    if extension.contains(['*', '?', '[']) {
+        String::new()
+    } else {
+        extension
+    }
+}
+
+/// 将 `CREATE TABLE` 中的列类型（如 `BIGINT`、`VARCHAR(20)`）转换为 Arrow 数据类型
+/// Convert a column type from `CREATE TABLE` (e.g. `BIGINT`, `VARCHAR(20)`) into an Arrow data type
+pub fn parse_data_type(sql_type: &str) -> Option<DataType> {
+    let sql_type = sql_type.trim();
+    let upper_type = sql_type.to_uppercase();
+    let name = match upper_type.find('(') {
+        Some(index) => upper_type[..index].trim(),
+        None => upper_type.as_str(),
+    };
+    match name {
+        "TINYINT" => Some(DataType::Int8),
+        "SMALLINT" => Some(DataType::Int16),
+        "INT" | "INTEGER" => Some(DataType::Int32),
+        "BIGINT" => Some(DataType::Int64),
+        "FLOAT" | "REAL" => Some(DataType::Float32),
+        "DOUBLE" | "DOUBLE PRECISION" | "DECIMAL" | "NUMERIC" => Some(DataType::Float64),
+        "BOOLEAN" | "BOOL" => Some(DataType::Boolean),
+        "DATE" => Some(DataType::Date32),
+        "TIMESTAMP" | "DATETIME" => Some(DataType::Timestamp(TimeUnit::Nanosecond, None)),
+        "VARCHAR" | "CHAR" | "TEXT" | "STRING" => Some(DataType::Utf8),
+        // 推断得到的 schema 以 Arrow 类型名保存，例如 `Int64`、`Utf8`
+        // Inferred schemas are stored with Arrow type names such as `Int64` or `Utf8`
+        _ => DataType::from_str(sql_type).ok(),
+    }
+}
+
+/// 路径是否为目录，对象存储中以 `/` 结尾的路径为目录
+/// Whether a path is a directory, paths in object storage are when they end with `/`
+pub fn is_dir(path: &str) -> bool {
+    if is_object_store_path(path) {
+        path.ends_with('/')
+    } else {
+        Path::new(path).is_dir()
+    }
+}
+
+/// 在阻塞线程池中执行同步读取文件的代码（可能请求对象存储），不占用 actix 的工作线程
+/// Run code reading files synchronously (possibly requesting object storage) on the blocking
+/// thread pool, so it does not hold an actix worker thread
+pub async fn run_blocking<T, F>(f: F) -> Result<T, Exception>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Exception> + Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .map_err(|_| Exception::internal_server_error("The blocking task panicked"))?
+}
+
+/// 读取本地文件或对象存储中对象的修改时间
+/// Read the modification time of a local file or an object in object storage
+pub fn get_modified_time(path: &str) -> Option<SystemTime> {
+    if is_object_store_path(path) {
+        get_object_modified(path)
+    } else {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// 文件在临时目录中的副本路径（转码结果或缓存）：本地文件按绝对路径保存，`s3://bucket/key` 等 URL 保存为 `s3:/bucket/key`
+/// The path of a copy of a file in the tmp dir (transcoded or cached): local files are kept under
+/// their absolute path and URLs such as `s3://bucket/key` as `s3:/bucket/key`
+pub fn get_local_copy_path(root: &Path, file: &str) -> PathBuf {
+    root.join(file.replacen("://", ":/", 1).trim_start_matches('/'))
+}
+
+/// 查找匹配 glob 的文件，目录则查找其中除 `_`、`.` 开头的标记文件以外的文件，不包含子目录；
+/// 对象存储中的路径以 `/` 结尾时为目录
+/// Find the files matching a glob, a directory giving its files except marker files that start
+/// with `_` or `.`, without subdirectories; paths in object storage are directories when they end with `/`
 pub fn find_files(pattern: &str) -> Result<Vec<String>, Exception> {
+    list_files(pattern, false)
+}
+
+/// 与 `find_files` 相同，但递归查找目录的子目录，用于分区表与临时目录
+/// Like `find_files`, but searching the subdirectories of a directory too, used for partitioned
+/// tables and the tmp dir
+pub fn find_files_recursive(pattern: &str) -> Result<Vec<String>, Exception> {
+    list_files(pattern, true)
+}
+
+fn list_files(pattern: &str, recursive: bool) -> Result<Vec<String>, Exception> {
+    if is_object_store_path(pattern) {
+        return find_objects(pattern, recursive);
+    }
     let mut files = Vec::new();
+    let is_dir = Path::new(pattern).is_dir();
+    let pattern = match (is_dir, recursive) {
+        (true, true) => format!("{}/**/*", pattern.trim_end_matches('/')),
+        (true, false) => format!("{}/*", pattern.trim_end_matches('/')),
+        (false, _) => pattern.to_string(),
+    };
 
-    for entry in glob(pattern)? {
+    for entry in glob(&pattern)? {
         let path = entry?;
-        if path.is_file() {
+        let is_marker = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(['_', '.']));
+        if path.is_file() && !(is_dir && is_marker) {
             files.push(path.to_str().unwrap().to_owned());
         }
     }
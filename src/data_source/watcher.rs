@@ -0,0 +1,201 @@
+use crate::data_source::context::{get_data_dir, infer_table_schema};
+use crate::data_source::discovery::discover_file;
+use crate::data_source::partition::find_table_files;
+use crate::response::http_error::Exception;
+use crate::server::schema::TableCatalog;
+use crate::sqlite;
+use crate::sqlite::from_json_column;
+use actix_web::rt::Runtime;
+use glob::{MatchOptions, Pattern};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rusqlite::params;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// 连续的文件事件合并处理，避免文件逐块写入时反复推断 schema
+/// Consecutive file events are handled together so a file written in chunks is not inferred repeatedly
+const DEBOUNCE: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// 在后台线程中监听 DATA_DIR（Linux 下使用 inotify），使 catalog 与目录中的文件保持同步；
+/// 首次同步在启动扫描结束后进行，避免两者同时写入 catalog，扫描期间的文件事件在其后处理
+/// Watch DATA_DIR in a background thread (inotify on Linux) and keep the catalog in sync with its files;
+/// the first sync waits for the startup scan so they never write the catalog at the same time,
+/// and the file events raised meanwhile are handled after it
+pub fn watch_data_dir(startup_scan: JoinHandle<()>) {
+    thread::spawn(|| {
+        if let Err(e) = run(startup_scan) {
+            eprintln!("Error: the data directory watcher stopped: {:?}", e);
+        }
+    });
+}
+
+fn run(startup_scan: JoinHandle<()>) -> Result<(), Exception> {
+    let data_dir = fs::canonicalize(get_data_dir())?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&data_dir, RecursiveMode::Recursive)?;
+    if startup_scan.join().is_err() {
+        eprintln!("Error: the data directory scan panicked");
+    }
+
+    let runtime = Runtime::new()?;
+    if let Err(e) = runtime.block_on(sync_catalog(&data_dir, &[])) {
+        eprintln!("Error: failed to sync the catalog: {:?}", e);
+    }
+
+    while let Ok(event) = receiver.recv() {
+        let mut paths = Vec::new();
+        collect_paths(event, &mut paths);
+        let deadline = Instant::now() + MAX_DELAY;
+        while Instant::now() < deadline {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => collect_paths(event, &mut paths),
+                Err(_) => break,
+            }
+        }
+        if paths.is_empty() {
+            continue;
+        }
+        if let Err(e) = runtime.block_on(sync_catalog(&data_dir, &paths)) {
+            eprintln!("Error: failed to sync the catalog: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+fn collect_paths(event: notify::Result<Event>, paths: &mut Vec<PathBuf>) {
+    let event = match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => event,
+        _ => return,
+    };
+    for path in event.paths {
+        let is_marker = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(['_', '.']));
+        if !is_marker && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+}
+
+/// 根据变化的文件更新 catalog：
+/// 标记位置不再匹配任何文件的表，重新推断位置匹配变化文件的表的 schema，并为不属于任何表的新文件注册表
+/// Update the catalog for the changed files: mark tables whose location no longer matches any file,
+/// re-infer the schema of the tables whose location matches a changed file and register tables
+/// for new files that belong to no table
+async fn sync_catalog(data_dir: &Path, paths: &[PathBuf]) -> Result<(), Exception> {
+    let conn = sqlite::conn();
+    let mut stmt = conn.prepare(
+        "select table_ref, table_path, table_options, table_schema, status from catalog where type != 'TEMP'",
+    )?;
+    let tables = stmt
+        .query_map([], |row| {
+            Ok((
+                TableCatalog {
+                    table_name: row.get(0)?,
+                    table_path: row.get(1)?,
+                    table_options: from_json_column(row, 2)?,
+                    table_schema: from_json_column(row, 3)?,
+                },
+                row.get::<usize, String>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut unmatched = paths
+        .iter()
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    for (table, status) in tables {
+        let location = match get_local_location(data_dir, &table.table_path) {
+            Some(location) => location,
+            None => continue,
+        };
+        let changed = paths.iter().any(|path| matches_location(&location, path));
+        unmatched.retain(|path| !matches_location(&location, path));
+
+        let exists = !find_table_files(&location, &table.table_options)
+            .unwrap_or_default()
+            .is_empty();
+        let new_status = if exists { "OK" } else { "MISSING" };
+        if new_status != status {
+            conn.execute(
+                "update catalog set status = ?1 where table_ref = ?2",
+                params![new_status, &table.table_name],
+            )?;
+        }
+
+        // 保存的 schema 来自文件（固定宽度等格式按声明的列读取），文件变化后需要重新推断
+        // The stored schema comes from the files (formats such as fixed width read the declared
+        // columns), so it must be inferred again after the files change
+        if exists && changed {
+            match infer_table_schema(&table).await {
+                Ok(table_schema) => {
+                    conn.execute(
+                        "update catalog set table_schema = ?1 where table_ref = ?2",
+                        params![serde_json::to_string(&table_schema)?, &table.table_name],
+                    )?;
+                }
+                Err(e) => eprintln!(
+                    "Error: failed to infer the schema of {}: {:?}",
+                    table.table_name, e
+                ),
+            }
+        }
+    }
+
+    let mut locations: Vec<String> = Vec::new();
+    for path in unmatched {
+        if locations
+            .iter()
+            .any(|location| matches_location(location, path))
+        {
+            continue;
+        }
+        match discover_file(path).await {
+            Ok(tables) => {
+                for table in tables {
+                    println!(
+                        "Discovered table {} ({}): {}",
+                        table.table_ref, table.table_path, table.status
+                    );
+                    locations.extend(get_local_location(data_dir, &table.table_path));
+                }
+            }
+            Err(e) => eprintln!("Error: failed to discover {}: {:?}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+/// 将表位置解析为本地路径，远程位置返回 `None`
+/// Resolve a table location into a local path, returning `None` for remote locations
+fn get_local_location(data_dir: &Path, table_path: &str) -> Option<String> {
+    if table_path.contains("://") {
+        return None;
+    }
+    let table_path = match table_path.find('#') {
+        Some(index) => &table_path[..index],
+        None => table_path,
+    };
+    Some(data_dir.join(table_path).display().to_string())
+}
+
+fn matches_location(location: &str, path: &Path) -> bool {
+    if Path::new(location).is_dir() {
+        return path.starts_with(location);
+    }
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    Pattern::new(location)
+        .map(|pattern| pattern.matches_path_with(path, options))
+        .unwrap_or(false)
+}
@@ -0,0 +1,373 @@
+use crate::data_source::compression::{get_compression_type, open_decompressed};
+use crate::data_source::encoding::{detect_encoding, parse_encoding};
+use crate::data_source::metadata::{
+    get_modified, FILE_COLUMN, MODIFIED_COLUMN, MODIFIED_DATA_TYPE, ROW_COLUMN,
+};
+use crate::data_source::schema::TableOptions;
+use crate::data_source::utils::find_files;
+use crate::response::http_error::Exception;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::json::reader::infer_json_schema_from_iterator;
+use arrow::json::ReaderBuilder;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray};
+use encoding_rs::Encoding;
+use roxmltree::{Document, Node, ParsingOptions};
+use serde_json::{Map, Number, Value};
+use std::io::Read;
+use std::sync::Arc;
+
+/// 默认选取根元素的子元素作为行
+/// By default the children of the root element are the rows
+const DEFAULT_ROW_PATH: &str = "/*/*";
+/// 同时有子元素（或属性）与文本的元素，文本保存在该字段中
+/// The text of an element that also has children (or attributes) is kept in this field
+const TEXT_FIELD: &str = "_text";
+
+pub struct XmlOptions {
+    pub row_path: String,
+    pub encoding: Option<&'static Encoding>,
+    pub metadata_columns: bool,
+}
+
+impl XmlOptions {
+    pub fn try_new(table_options: &TableOptions) -> Result<Self, Exception> {
+        Ok(XmlOptions {
+            row_path: table_options
+                .get("row_path")
+                .unwrap_or(DEFAULT_ROW_PATH)
+                .to_string(),
+            encoding: match table_options.get("encoding") {
+                Some(name) if !name.eq_ignore_ascii_case("auto") => Some(parse_encoding(name)?),
+                _ => None,
+            },
+            metadata_columns: table_options.get_bool("metadata_columns")?.unwrap_or(false),
+        })
+    }
+}
+
+/// 行路径中的一步，例如 `//item[@type='book']`
+/// A step of the row path, e.g. `//item[@type='book']`
+struct Step {
+    descendant: bool,
+    name: String,
+    attribute: Option<(String, Option<String>)>,
+}
+
+/// 解析类似 XPath 的行路径：支持 `/a/b`、`//b`、通配符 `*` 以及 `[@attr]`、`[@attr='value']` 条件，
+/// 不以 `/` 开头的路径从任意层级匹配
+/// Parse an XPath-like row path supporting `/a/b`, `//b`, the `*` wildcard and `[@attr]` or
+/// `[@attr='value']` predicates; a path that does not start with `/` matches at any depth
+fn parse_row_path(row_path: &str) -> Result<Vec<Step>, Exception> {
+    let invalid = || Exception::bad_request_error(format!("Invalid row path '{}'", row_path));
+    let path = row_path.trim();
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("//{}", path)
+    };
+
+    let mut steps = Vec::new();
+    let mut rest = path.as_str();
+    while !rest.is_empty() {
+        let descendant = rest.starts_with("//");
+        rest = rest
+            .strip_prefix("//")
+            .or_else(|| rest.strip_prefix('/'))
+            .ok_or_else(invalid)?;
+        let end = rest
+            .char_indices()
+            .scan(false, |quoted, (i, c)| {
+                if c == '\'' || c == '"' {
+                    *quoted = !*quoted;
+                }
+                Some((i, c, *quoted))
+            })
+            .find(|(_, c, quoted)| *c == '/' && !quoted)
+            .map(|(i, _, _)| i)
+            .unwrap_or(rest.len());
+        let (step, next) = rest.split_at(end);
+        rest = next;
+
+        let (name, attribute) = match step.split_once('[') {
+            Some((name, predicate)) => {
+                let predicate = predicate
+                    .strip_suffix(']')
+                    .and_then(|predicate| predicate.trim().strip_prefix('@'))
+                    .ok_or_else(invalid)?;
+                let attribute = match predicate.split_once('=') {
+                    Some((attribute, value)) => (
+                        attribute.trim().to_string(),
+                        Some(value.trim().trim_matches(['\'', '"']).to_string()),
+                    ),
+                    None => (predicate.trim().to_string(), None),
+                };
+                (name.trim(), Some(attribute))
+            }
+            None => (step.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        steps.push(Step {
+            descendant,
+            name: name.to_string(),
+            attribute,
+        });
+    }
+    if steps.is_empty() {
+        return Err(invalid());
+    }
+    Ok(steps)
+}
+
+fn select_rows<'a, 'input>(
+    document: &'a Document<'input>,
+    steps: &[Step],
+) -> Vec<Node<'a, 'input>> {
+    let mut nodes = vec![document.root()];
+    for step in steps {
+        let mut selected: Vec<Node> = Vec::new();
+        for node in &nodes {
+            let candidates: Box<dyn Iterator<Item = Node>> = if step.descendant {
+                Box::new(node.descendants().skip(1))
+            } else {
+                Box::new(node.children())
+            };
+            for candidate in candidates.filter(|candidate| matches_step(candidate, step)) {
+                if !selected.contains(&candidate) {
+                    selected.push(candidate);
+                }
+            }
+        }
+        nodes = selected;
+    }
+    nodes
+}
+
+fn matches_step(node: &Node, step: &Step) -> bool {
+    node.is_element()
+        && (step.name == "*" || node.tag_name().name() == step.name)
+        && match &step.attribute {
+            Some((name, Some(value))) => node.attribute(name.as_str()) == Some(value.as_str()),
+            Some((name, None)) => node.has_attribute(name.as_str()),
+            None => true,
+        }
+}
+
+/// 将元素转换为 JSON：属性与子元素成为字段，只含文本的子元素为标量值，其余子元素为嵌套对象，
+/// 重复的子元素为数组
+/// Convert an element into JSON: attributes and child elements become fields, children with
+/// only text become scalar values, other children nested objects and repeated children arrays
+fn element_to_json(node: Node) -> Value {
+    let mut map = Map::new();
+    for attribute in node.attributes() {
+        map.insert(attribute.name().to_string(), typed_value(attribute.value()));
+    }
+    let children = node
+        .children()
+        .filter(|child| child.is_element())
+        .collect::<Vec<_>>();
+    for child in &children {
+        let name = child.tag_name().name();
+        let value = if is_leaf(child) {
+            child.text().map(typed_value).unwrap_or(Value::Null)
+        } else {
+            element_to_json(*child)
+        };
+        let repeated = children
+            .iter()
+            .filter(|other| other.tag_name().name() == name)
+            .count()
+            > 1;
+        match map.get_mut(name) {
+            Some(Value::Array(values)) if repeated => values.push(value),
+            _ if repeated => {
+                map.insert(name.to_string(), Value::Array(vec![value]));
+            }
+            _ => {
+                map.insert(name.to_string(), value);
+            }
+        }
+    }
+    let text = node
+        .children()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect::<String>();
+    if !text.trim().is_empty() {
+        map.insert(TEXT_FIELD.to_string(), typed_value(&text));
+    }
+    Value::Object(map)
+}
+
+/// 按文档顺序列出元素的字段名：先属性，后子元素，最后为文本
+/// List the field names of an element in document order: attributes, child elements, then text
+fn field_names(node: Node) -> Vec<String> {
+    let mut names = node
+        .attributes()
+        .map(|attribute| attribute.name().to_string())
+        .collect::<Vec<_>>();
+    for child in node.children().filter(|child| child.is_element()) {
+        let name = child.tag_name().name().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let has_text = node
+        .children()
+        .any(|child| child.is_text() && child.text().is_some_and(|text| !text.trim().is_empty()));
+    if has_text {
+        names.push(TEXT_FIELD.to_string());
+    }
+    names
+}
+
+/// 元素只在部分行中重复时推断为列表，其余行中的单个值需要包装为列表
+/// An element repeated in only some rows is inferred as a list, so single values in the other
+/// rows are wrapped into lists
+fn conform(value: Value, data_type: &DataType) -> Value {
+    match (value, data_type) {
+        (Value::Null, _) => Value::Null,
+        (Value::Array(values), DataType::List(field)) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| conform(value, field.data_type()))
+                .collect(),
+        ),
+        (value, DataType::List(field)) => Value::Array(vec![conform(value, field.data_type())]),
+        (Value::Object(map), DataType::Struct(fields)) => Value::Object(
+            map.into_iter()
+                .map(|(name, value)| {
+                    let value = match fields.find(&name) {
+                        Some((_, field)) => conform(value, field.data_type()),
+                        None => value,
+                    };
+                    (name, value)
+                })
+                .collect(),
+        ),
+        (value, _) => value,
+    }
+}
+
+fn is_leaf(node: &Node) -> bool {
+    node.attributes().len() == 0 && !node.children().any(|child| child.is_element())
+}
+
+/// 文本值按整数、浮点数、布尔值识别，带前导零的数字（如编号 `007`）保留为字符串
+/// Text values are recognised as integers, floats and booleans, while numbers with leading
+/// zeros (such as the code `007`) stay strings
+fn typed_value(text: &str) -> Value {
+    let text = text.trim();
+    if text.is_empty() {
+        return Value::Null;
+    }
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    if !leading_zero {
+        if let Ok(value) = text.parse::<i64>() {
+            return Value::Number(value.into());
+        }
+        if let Some(value) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(value);
+        }
+    }
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+fn read_text(file: &str, encoding: Option<&'static Encoding>) -> Result<String, Exception> {
+    let mut buffer = Vec::new();
+    open_decompressed(file, get_compression_type(file))?.read_to_end(&mut buffer)?;
+    let encoding = encoding.unwrap_or_else(|| detect_encoding(&buffer).0);
+    let (text, _, _) = encoding.decode(&buffer);
+    Ok(text.into_owned())
+}
+
+/// 按行路径选取元素作为行，根据所有行推断 schema
+/// Select the row elements with the row path and infer the schema from all rows
+pub fn from_file_to_record_batch(
+    file_path: &str,
+    options: &XmlOptions,
+) -> Result<RecordBatch, Exception> {
+    let files = find_files(file_path)?;
+    if files.is_empty() {
+        return Err(Exception::file_not_found_error(file_path));
+    }
+    let steps = parse_row_path(&options.row_path)?;
+
+    let mut rows: Vec<Value> = Vec::new();
+    let mut row_files: Vec<String> = Vec::new();
+    let mut row_numbers: Vec<i64> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for file in &files {
+        let text = read_text(file, options.encoding)?;
+        let parsing_options = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let document = Document::parse_with_options(&text, parsing_options)
+            .map_err(|e| Exception::bad_request_error(format!("Cannot parse '{}': {}", file, e)))?;
+        for (i, node) in select_rows(&document, &steps).into_iter().enumerate() {
+            for name in field_names(node) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            rows.push(element_to_json(node));
+            row_files.push(file.clone());
+            row_numbers.push(i as i64 + 1);
+        }
+    }
+    if rows.is_empty() {
+        return Err(Exception::bad_request_error(format!(
+            "The row path '{}' matches no elements in '{}'",
+            options.row_path, file_path
+        )));
+    }
+
+    // 推断得到的列按字段名排序，这里恢复为文档中的顺序
+    // The inferred columns are sorted by name, so they are put back in document order
+    let inferred = infer_json_schema_from_iterator(rows.iter().map(Ok))?;
+    let fields = names
+        .iter()
+        .filter_map(|name| inferred.field_with_name(name).ok().cloned())
+        .collect::<Vec<_>>();
+    let schema = Arc::new(Schema::new(fields));
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(rows.len().max(1))
+        .with_coerce_primitive(true)
+        .build_decoder()?;
+    let rows = rows
+        .into_iter()
+        .map(|row| conform(row, &DataType::Struct(schema.fields().clone())))
+        .collect::<Vec<_>>();
+    decoder.serialize(&rows)?;
+    let batch = decoder
+        .flush()?
+        .unwrap_or_else(|| RecordBatch::new_empty(schema.clone()));
+    if !options.metadata_columns {
+        return Ok(batch);
+    }
+
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    let mut arrays: Vec<ArrayRef> = batch.columns().to_vec();
+    let modified = row_files
+        .iter()
+        .map(|file| get_modified(file))
+        .collect::<Vec<_>>();
+    fields.push(Arc::new(Field::new(FILE_COLUMN, DataType::Utf8, false)));
+    arrays.push(Arc::new(StringArray::from(row_files)));
+    fields.push(Arc::new(Field::new(ROW_COLUMN, DataType::Int64, false)));
+    arrays.push(Arc::new(Int64Array::from(row_numbers)));
+    fields.push(Arc::new(Field::new(
+        MODIFIED_COLUMN,
+        MODIFIED_DATA_TYPE,
+        true,
+    )));
+    arrays.push(Arc::new(TimestampNanosecondArray::from(modified)));
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(Exception::from)
+}
@@ -1,3 +1,6 @@
+#![allow(clippy::upper_case_acronyms)]
+
+mod config;
 mod controllers;
 mod sqlite;
 
@@ -12,13 +15,21 @@ use actix_web::{middleware, App, HttpServer};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    config::init_config();
     sqlite::init_db();
-    HttpServer::new(|| {
+    // 启动时在后台扫描 DATA_DIR，自动注册发现的表，扫描结束后开始同步目录变化
+    // Scan DATA_DIR in the background at startup to register the discovered tables, and sync the
+    // directory changes once the scan is done
+    let startup_scan = data_source::discovery::scan_data_dir_in_background();
+    data_source::watcher::watch_data_dir(startup_scan);
+    let server_config = &config::get_config().server;
+    let mut server = HttpServer::new(|| {
         App::new()
             .wrap(middleware::Logger::default())
             .configure(controllers::init)
-    })
-    .bind("0.0.0.0:8080")?
-    .run()
-    .await
+    });
+    if let Some(workers) = server_config.workers {
+        server = server.workers(workers);
+    }
+    server.bind(&server_config.listen)?.run().await
 }
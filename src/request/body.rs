@@ -10,4 +10,8 @@ pub struct Fetch {
 pub struct ExportFile {
     pub sql: String,
     pub file_type: FileType,
-}
\ No newline at end of file
+}
+#[derive(Deserialize)]
+pub struct ScanDirectory {
+    pub path: Option<String>,
+}
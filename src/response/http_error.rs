@@ -17,11 +17,11 @@ struct ExceptionAttributes {
 }
 
 impl ExceptionAttributes {
-    fn new(message: &String, status_code: StatusCode) -> Self {
+    fn new(message: &str, status_code: StatusCode) -> Self {
         ExceptionAttributes {
             resp_code: 1,
             status_code,
-            resp_msg: message.clone(),
+            resp_msg: message.to_string(),
         }
     }
 }
@@ -36,6 +36,8 @@ pub enum Exception {
     FileNotFound { file_name: String },
     #[display("The data is not as expected. Expected: {message}")]
     UnprocessableEntity { message: String },
+    #[display("Timeout: {message}")]
+    Timeout { message: String },
 }
 
 impl ResponseError for Exception {
@@ -64,6 +66,7 @@ impl Exception {
             UnprocessableEntity { message } => {
                 ExceptionAttributes::new(message, StatusCode::UNPROCESSABLE_ENTITY)
             }
+            Timeout { message } => ExceptionAttributes::new(message, StatusCode::REQUEST_TIMEOUT),
         }
     }
 
@@ -94,6 +97,12 @@ impl Exception {
             message: message.into(),
         }
     }
+
+    pub fn timeout_error(message: impl Into<String>) -> Self {
+        Timeout {
+            message: message.into(),
+        }
+    }
 }
 
 impl From<DataFusionError> for Exception {
@@ -186,6 +195,15 @@ impl From<calamine::Error> for Exception {
     }
 }
 
+impl From<regex::Error> for Exception {
+    fn from(error: regex::Error) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        BadRequest {
+            message: error.to_string(),
+        }
+    }
+}
+
 impl From<XlsxError> for Exception {
     fn from(error: XlsxError) -> Self {
         println!("Error: {:?}", Backtrace::new());
@@ -193,4 +211,53 @@ impl From<XlsxError> for Exception {
             message: error.to_string(),
         }
     }
-}
\ No newline at end of file
+}
+impl From<notify::Error> for Exception {
+    fn from(error: notify::Error) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        BadRequest {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<object_store::Error> for Exception {
+    fn from(error: object_store::Error) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        BadRequest {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<datafusion::parquet::errors::ParquetError> for Exception {
+    fn from(error: datafusion::parquet::errors::ParquetError) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        BadRequest {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<mysql_async::Error> for Exception {
+    fn from(error: mysql_async::Error) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        BadRequest {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for Exception {
+    fn from(error: tokio_postgres::Error) -> Self {
+        println!("Error: {:?}", Backtrace::new());
+        // 数据库错误的 Display 只有 "db error"，使用数据库返回的消息
+        // The Display of a database error is only "db error", so use the message from the database
+        BadRequest {
+            message: match error.as_db_error() {
+                Some(db_error) => db_error.message().to_string(),
+                None => error.to_string(),
+            },
+        }
+    }
+}
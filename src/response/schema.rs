@@ -59,6 +59,8 @@ pub struct TableCatalog {
     pub table_ref: String,
     pub table_path: String,
     pub table_schema: Vec<TableFieldSchema>,
+    pub table_type: String,
+    pub status: String,
 }
 
 #[derive(Serialize)]
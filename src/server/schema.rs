@@ -1,11 +1,15 @@
+use crate::data_source::schema::TableOptions;
 use serde::{Deserialize, Serialize};
 
+#[derive(Clone)]
 pub struct TableCatalog {
     pub table_name: String,
     pub table_path: String,
+    pub table_options: TableOptions,
+    pub table_schema: Vec<TableFieldSchema>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct TableFieldSchema {
     pub field: String,
     pub field_type: String,
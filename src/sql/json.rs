@@ -0,0 +1,415 @@
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::json::LineDelimitedWriter;
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+};
+use datafusion::common::{DFSchema, DataFusionError, Result, ScalarValue};
+use datafusion::execution::FunctionRegistry;
+use datafusion::logical_expr::planner::{ExprPlanner, PlannerResult, RawBinaryExpr};
+use datafusion::logical_expr::sqlparser::ast::BinaryOperator;
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::prelude::SessionContext;
+use serde_json::Value as JsonValue;
+use std::any::Any;
+use std::sync::Arc;
+
+/// JSON 函数，参数为 JSON 文本或结构体、列表等嵌套列，之后是路径
+/// The JSON functions, taking JSON text or a nested column such as a struct or list, followed by a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JsonFunction {
+    Extract,
+    GetStr,
+    GetInt,
+    GetFloat,
+    GetBool,
+    AsText,
+    ArrayElements,
+    Keys,
+    Length,
+    ToJson,
+}
+
+const JSON_FUNCTIONS: [JsonFunction; 10] = [
+    JsonFunction::Extract,
+    JsonFunction::GetStr,
+    JsonFunction::GetInt,
+    JsonFunction::GetFloat,
+    JsonFunction::GetBool,
+    JsonFunction::AsText,
+    JsonFunction::ArrayElements,
+    JsonFunction::Keys,
+    JsonFunction::Length,
+    JsonFunction::ToJson,
+];
+
+impl JsonFunction {
+    fn name(&self) -> &'static str {
+        match self {
+            JsonFunction::Extract => "json_extract",
+            JsonFunction::GetStr => "json_get_str",
+            JsonFunction::GetInt => "json_get_int",
+            JsonFunction::GetFloat => "json_get_float",
+            JsonFunction::GetBool => "json_get_bool",
+            JsonFunction::AsText => "json_as_text",
+            JsonFunction::ArrayElements => "json_array_elements",
+            JsonFunction::Keys => "json_keys",
+            JsonFunction::Length => "json_length",
+            JsonFunction::ToJson => "to_json",
+        }
+    }
+
+    fn return_type(&self) -> DataType {
+        match self {
+            JsonFunction::GetInt | JsonFunction::Length => DataType::Int64,
+            JsonFunction::GetFloat => DataType::Float64,
+            JsonFunction::GetBool => DataType::Boolean,
+            JsonFunction::ArrayElements | JsonFunction::Keys => {
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)))
+            }
+            _ => DataType::Utf8,
+        }
+    }
+}
+
+/// 注册 JSON 函数以及 `->`（返回 JSON 文本）与 `->>`（返回文本）运算符
+/// Register the JSON functions and the `->` (returning JSON text) and `->>` (returning text) operators
+pub fn register_json_functions(ctx: &mut SessionContext) -> Result<()> {
+    for function in JSON_FUNCTIONS {
+        ctx.register_udf(Arc::new(json_udf(function)))?;
+    }
+    ctx.register_expr_planner(Arc::new(JsonExprPlanner))
+}
+
+fn json_udf(function: JsonFunction) -> ScalarUDF {
+    ScalarUDF::new_from_impl(JsonUdf {
+        function,
+        signature: Signature::variadic_any(Volatility::Immutable),
+    })
+}
+
+#[derive(Debug)]
+struct JsonExprPlanner;
+
+impl ExprPlanner for JsonExprPlanner {
+    fn plan_binary_op(
+        &self,
+        expr: RawBinaryExpr,
+        _schema: &DFSchema,
+    ) -> Result<PlannerResult<RawBinaryExpr>> {
+        let function = match expr.op {
+            BinaryOperator::Arrow => JsonFunction::Extract,
+            BinaryOperator::LongArrow => JsonFunction::AsText,
+            _ => return Ok(PlannerResult::Original(expr)),
+        };
+        Ok(PlannerResult::Planned(
+            json_udf(function).call(vec![expr.left, expr.right]),
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct JsonUdf {
+    function: JsonFunction,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for JsonUdf {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.function.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        if arg_types.is_empty() || (self.function == JsonFunction::ToJson && arg_types.len() > 1) {
+            return Err(DataFusionError::Plan(format!(
+                "{} expects a JSON value{}",
+                self.name(),
+                if self.function == JsonFunction::ToJson {
+                    ""
+                } else {
+                    " followed by an optional path"
+                }
+            )));
+        }
+        Ok(self.function.return_type())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let is_scalar = args
+            .args
+            .iter()
+            .all(|arg| matches!(arg, ColumnarValue::Scalar(_)));
+        let rows = if is_scalar { 1 } else { args.number_rows };
+        let arrays = args
+            .args
+            .iter()
+            .map(|arg| arg.to_array(rows))
+            .collect::<Result<Vec<_>>>()?;
+
+        let documents = to_json_values(&arrays[0], self.function != JsonFunction::ToJson)?;
+        // 路径通常是常量，只解析一次
+        // The path is usually a constant and is parsed only once
+        let constant_path = args.args[1..]
+            .iter()
+            .all(|arg| matches!(arg, ColumnarValue::Scalar(_)));
+        let path = if constant_path {
+            Some(get_path(&arrays[1..], 0)?)
+        } else {
+            None
+        };
+        let mut values = Vec::with_capacity(rows);
+        for (row, document) in documents.iter().enumerate() {
+            let value = match (document, &path) {
+                (Some(document), Some(path)) => select(document, path),
+                (Some(document), None) => select(document, &get_path(&arrays[1..], row)?),
+                (None, _) => None,
+            };
+            values.push(value);
+        }
+
+        let array = self.to_array(&values);
+        if is_scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &array, 0,
+            )?))
+        } else {
+            Ok(ColumnarValue::Array(array))
+        }
+    }
+}
+
+impl JsonUdf {
+    fn to_array(&self, values: &[Option<&JsonValue>]) -> ArrayRef {
+        match self.function {
+            JsonFunction::Extract | JsonFunction::ToJson => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.map(|value| value.to_string()))
+                    .collect::<StringArray>(),
+            ),
+            JsonFunction::GetStr => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.and_then(|value| value.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            JsonFunction::GetInt => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.and_then(|value| value.as_i64()))
+                    .collect::<Int64Array>(),
+            ),
+            JsonFunction::GetFloat => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.and_then(|value| value.as_f64()))
+                    .collect::<Float64Array>(),
+            ),
+            JsonFunction::GetBool => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.and_then(|value| value.as_bool()))
+                    .collect::<BooleanArray>(),
+            ),
+            JsonFunction::AsText => Arc::new(
+                values
+                    .iter()
+                    .map(|value| value.and_then(as_text))
+                    .collect::<StringArray>(),
+            ),
+            JsonFunction::Length => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Some(JsonValue::Array(items)) => Some(items.len() as i64),
+                        Some(JsonValue::Object(object)) => Some(object.len() as i64),
+                        _ => None,
+                    })
+                    .collect::<Int64Array>(),
+            ),
+            JsonFunction::ArrayElements | JsonFunction::Keys => {
+                let mut builder = ListBuilder::new(StringBuilder::new());
+                for value in values {
+                    match (self.function, value) {
+                        (JsonFunction::ArrayElements, Some(JsonValue::Array(items))) => {
+                            for item in items {
+                                builder.values().append_option(as_text(item));
+                            }
+                            builder.append(true);
+                        }
+                        (JsonFunction::Keys, Some(JsonValue::Object(object))) => {
+                            for key in object.keys() {
+                                builder.values().append_value(key);
+                            }
+                            builder.append(true);
+                        }
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+        }
+    }
+}
+
+/// 字符串返回其内容，其他值返回 JSON 文本，JSON 的 null 返回 SQL 的 NULL
+/// Strings return their content, other values their JSON text and a JSON null an SQL NULL
+fn as_text(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// 将一列转换为 JSON 值：字符串按 JSON 文本解析（无效的 JSON 为 NULL），结构体、列表等嵌套列按 Arrow 的 JSON 编码转换
+/// Convert a column into JSON values: strings are parsed as JSON text (invalid JSON being NULL),
+/// nested columns such as structs and lists use the JSON encoding of Arrow
+fn to_json_values(array: &ArrayRef, parse_strings: bool) -> Result<Vec<Option<JsonValue>>> {
+    match array.data_type() {
+        DataType::Null => Ok(vec![None; array.len()]),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View if parse_strings => {
+            let strings = cast(array, &DataType::Utf8)?;
+            let strings = strings
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .ok_or_else(|| DataFusionError::Internal("Expected a string array".into()))?;
+            Ok(strings
+                .iter()
+                .map(|value| value.and_then(|value| serde_json::from_str(value).ok()))
+                .collect())
+        }
+        data_type => {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "value",
+                data_type.clone(),
+                true,
+            )]));
+            let batch = RecordBatch::try_new(schema, vec![array.clone()])?;
+            let mut writer = LineDelimitedWriter::new(Vec::new());
+            writer.write(&batch)?;
+            writer.finish()?;
+            // 每行一个对象，值为 null 时省略 `value`
+            // One object per row, `value` being omitted for nulls
+            writer
+                .into_inner()
+                .split(|byte| *byte == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    let mut row: JsonValue = serde_json::from_slice(line)
+                        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                    Ok(row.get_mut("value").map(JsonValue::take))
+                })
+                .collect()
+        }
+    }
+}
+
+enum PathStep {
+    Key(String),
+    Index(i64),
+}
+
+/// 路径可以是一个以 `$` 开头的 JSONPath（如 `$.request.headers[0]`），也可以是多个参数，字符串为键，整数为数组下标（负数从末尾计数）
+/// A path is either one JSONPath starting with `$` (e.g. `$.request.headers[0]`) or several
+/// arguments, strings being keys and integers array indexes (negative ones counting from the end)
+fn get_path(arrays: &[ArrayRef], row: usize) -> Result<Vec<PathStep>> {
+    let mut steps = Vec::new();
+    for array in arrays {
+        match ScalarValue::try_from_array(array, row)? {
+            ScalarValue::Utf8(Some(path))
+            | ScalarValue::LargeUtf8(Some(path))
+            | ScalarValue::Utf8View(Some(path))
+                if path.starts_with('$') =>
+            {
+                steps.extend(parse_json_path(&path)?)
+            }
+            ScalarValue::Utf8(Some(key))
+            | ScalarValue::LargeUtf8(Some(key))
+            | ScalarValue::Utf8View(Some(key)) => steps.push(PathStep::Key(key)),
+            value if value.data_type().is_integer() && !value.is_null() => {
+                let index = value.cast_to(&DataType::Int64)?;
+                if let ScalarValue::Int64(Some(index)) = index {
+                    steps.push(PathStep::Index(index));
+                }
+            }
+            value => {
+                return Err(DataFusionError::Execution(format!(
+                    "A JSON path expects keys or array indexes, got {}",
+                    value
+                )))
+            }
+        }
+    }
+    Ok(steps)
+}
+
+fn parse_json_path(path: &str) -> Result<Vec<PathStep>> {
+    let invalid_path = || DataFusionError::Execution(format!("Invalid JSON path '{}'", path));
+    let chars = path.chars().collect::<Vec<_>>();
+    let mut steps = Vec::new();
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(invalid_path());
+                }
+                steps.push(PathStep::Key(chars[start..i].iter().collect()));
+            }
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == ']')
+                    .map(|position| i + position)
+                    .ok_or_else(invalid_path)?;
+                let inner = chars[i + 1..end].iter().collect::<String>();
+                let inner = inner.trim();
+                let quoted = inner.len() >= 2
+                    && (inner.starts_with('\'') && inner.ends_with('\'')
+                        || inner.starts_with('"') && inner.ends_with('"'));
+                if quoted {
+                    steps.push(PathStep::Key(inner[1..inner.len() - 1].to_string()));
+                } else {
+                    steps.push(PathStep::Index(
+                        inner.parse::<i64>().map_err(|_| invalid_path())?,
+                    ));
+                }
+                i = end + 1;
+            }
+            _ => return Err(invalid_path()),
+        }
+    }
+    Ok(steps)
+}
+
+fn select<'a>(document: &'a JsonValue, path: &[PathStep]) -> Option<&'a JsonValue> {
+    path.iter().try_fold(document, |value, step| match step {
+        PathStep::Key(key) => value.get(key),
+        PathStep::Index(index) => {
+            let items = value.as_array()?;
+            let index = if *index < 0 {
+                items.len().checked_sub(index.unsigned_abs() as usize)?
+            } else {
+                *index as usize
+            };
+            items.get(index)
+        }
+    })
+}
@@ -1,3 +1,4 @@
+pub mod json;
 pub mod parse;
 pub mod sql_error;
 pub mod schema;
\ No newline at end of file
@@ -1,19 +1,86 @@
+use crate::data_source::remote::RemoteKind;
+use crate::data_source::schema::{TableOptions, TABLE_OPTION_KEYS};
 use crate::response::http_error::Exception;
+use crate::server::schema::TableCatalog;
+use crate::sql::schema::SQLType;
+use crate::sql::schema::SQLType::{DDL, DML};
 use crate::sql::sql_error::SQLError;
+use crate::utils;
+use chrono::Utc;
 use datafusion::logical_expr::sqlparser::ast::{
-    Expr, Query, SetExpr, Statement, TableFactor, TableWithJoins,
+    Expr, FunctionArg, FunctionArgExpr, Ident, ObjectName, Query, SetExpr, SqlOption, Statement,
+    TableFactor, TableWithJoins, VisitMut, VisitorMut,
 };
-use datafusion::logical_expr::sqlparser::dialect::AnsiDialect;
+use datafusion::logical_expr::sqlparser::dialect::GenericDialect;
+use datafusion::logical_expr::sqlparser::keywords::Keyword;
 use datafusion::logical_expr::sqlparser::parser::Parser;
-use crate::sql::schema::SQLType;
-use crate::sql::schema::SQLType::{DDL, DML};
+use datafusion::logical_expr::sqlparser::tokenizer::{Token, Tokenizer};
+use datafusion::sql::parser::{CreateExternalTable, DFParser, Statement as DFStatement};
+use std::ops::ControlFlow;
 
+const TABLE_FUNCTION_PREFIX: &str = "read_";
+/// 读取 Delta Lake 或 Iceberg 表提交历史的表函数
+/// The table function reading the commit history of a Delta Lake or Iceberg table
+const HISTORY_FUNCTION: &str = "table_history";
+/// 时间旅行的命名参数，`VERSION AS OF` 与 `TIMESTAMP AS OF` 改写为 `version`、`timestamp`
+/// The named arguments of time travel, `VERSION AS OF` and `TIMESTAMP AS OF` are rewritten into `version` and `timestamp`
+const TIME_TRAVEL_OPTIONS: [&str; 3] = ["version", "timestamp", "snapshot_id"];
+
+/// 使用通用方言解析：ANSI 方言不接受以 `_` 开头的标识符，无法引用 `_file`、`_line_number` 等元数据列
+/// Parse with the generic dialect: the ANSI dialect rejects identifiers starting with `_`, so
+/// metadata columns such as `_file` and `_line_number` could not be referenced
 pub fn parse_sql(sql: &str) -> Result<Vec<Statement>, Exception> {
-    let dialect = AnsiDialect {};
+    let dialect = GenericDialect {};
     let statements = Parser::parse_sql(&dialect, sql)?;
     Ok(statements)
 }
 
+/// 解析 `SCAN DIRECTORY ['path']` 语句，返回要扫描的目录；不是该语句时返回 `None`
+/// Parse a `SCAN DIRECTORY ['path']` statement into the directory to scan, or `None` for other statements
+pub fn parse_scan_directory(sql: &str) -> Result<Option<Option<String>>, Exception> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    match parser.next_token().token {
+        Token::Word(word) if word.value.eq_ignore_ascii_case("scan") => {}
+        _ => return Ok(None),
+    }
+    parser.expect_keyword_is(Keyword::DIRECTORY)?;
+    let path = match parser.peek_token().token {
+        Token::SingleQuotedString(_) => Some(parser.parse_literal_string()?),
+        _ => None,
+    };
+    while parser.consume_token(&Token::SemiColon) {}
+    parser.expect_token(&Token::EOF)?;
+    Ok(Some(path))
+}
+
+/// 解析 `CREATE EXTERNAL TABLE ... STORED AS mysql/postgres/delta/iceberg LOCATION 'url' OPTIONS (...)`
+/// 语句，其他语句返回 `None`
+/// Parse a `CREATE EXTERNAL TABLE ... STORED AS mysql/postgres/delta/iceberg LOCATION 'url'
+/// OPTIONS (...)` statement, returning `None` for other statements
+pub fn parse_create_external_table(sql: &str) -> Result<Option<CreateExternalTable>, Exception> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    if !parser.parse_keywords(&[Keyword::CREATE, Keyword::EXTERNAL]) {
+        return Ok(None);
+    }
+    match DFParser::parse_sql_with_dialect(sql, &dialect)?.pop_front() {
+        Some(DFStatement::CreateExternalTable(table))
+            if RemoteKind::from_name(&table.file_type).is_some()
+                || is_lake_format(&table.file_type) =>
+        {
+            Ok(Some(table))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `STORED AS DELTA` 或 `STORED AS ICEBERG`
+/// `STORED AS DELTA` or `STORED AS ICEBERG`
+fn is_lake_format(file_type: &str) -> bool {
+    file_type.eq_ignore_ascii_case("delta") || file_type.eq_ignore_ascii_case("iceberg")
+}
+
 pub fn get_table_names(sql: &str) -> Result<Vec<String>, Exception> {
     let statements = parse_sql(sql)?;
     let mut table_names = Vec::new();
@@ -130,24 +197,423 @@ fn extract_table_names_from_expr(expr: &Expr, table_names: &mut Vec<String>) {
     }
 }
 
-pub fn get_sql_type(sql: &str) -> Result<(Vec<Statement>, SQLType), Exception> {
-    let statements = parse_sql(sql)?;
-    if statements.is_empty() {
-        return Err(SQLError::sql_syntax_error(
-            "SQL parsing error: statements are empty",
-            &sql,
-        ))?;
-    }
-
-    for statement in &statements {
-        return match statement {
-            Statement::Query(_) => Ok((statements, DML)),
-            Statement::CreateTable(_) => Ok((statements, DDL)),
-            _ => Err(Exception::unprocessable_entity_error(
-                "Currently, only the Select and Create statements are supported.",
-            )),
+/// 将 `read_csv('path', delimiter => ';')` 等表函数替换为临时表名，返回表函数对应的表，以及按时间旅行或
+/// `table_history('table')` 引用的表（`table_path` 为表名或路径，需要从 catalog 中查找）
+/// Replace table functions such as `read_csv('path', delimiter => ';')` with temporary table
+/// names, returning the tables of the table functions and the tables referenced by time travel or
+/// `table_history('table')`, whose `table_path` is a table name or path to look up in the catalog
+pub fn rewrite_table_functions(
+    sql: &str,
+) -> Result<(String, Vec<TableCatalog>, Vec<TableCatalog>), Exception> {
+    let rewritten_sql = rewrite_time_travel(&rewrite_json_operators(sql)?)?;
+    let mut statements = parse_sql(&rewritten_sql)?;
+    let mut visitor = TableFunctionVisitor {
+        tables: Vec::new(),
+        references: Vec::new(),
+    };
+
+    if let ControlFlow::Break(error) = statements.visit(&mut visitor) {
+        return Err(error);
+    }
+    if visitor.tables.is_empty() && visitor.references.is_empty() {
+        return Ok((rewritten_sql, visitor.tables, visitor.references));
+    }
+
+    let sql = statements
+        .iter()
+        .map(|statement| statement.to_string())
+        .collect::<Vec<_>>()
+        .join("; ");
+    Ok((sql, visitor.tables, visitor.references))
+}
+
+/// 将 `sales VERSION AS OF 3`、`'lake/sales' TIMESTAMP AS OF '2025-06-01'` 改写为表参数
+/// `sales(version => 3)`，表函数则追加命名参数，例如 `read_delta('lake/sales', version => 3)`
+/// Rewrite `sales VERSION AS OF 3` or `'lake/sales' TIMESTAMP AS OF '2025-06-01'` into table
+/// arguments such as `sales(version => 3)`, appending a named argument to table functions, e.g.
+/// `read_delta('lake/sales', version => 3)`; SQL without time travel is returned unchanged
+fn rewrite_time_travel(sql: &str) -> Result<String, Exception> {
+    let lowercase_sql = sql.to_lowercase();
+    if !lowercase_sql.contains("version") && !lowercase_sql.contains("timestamp") {
+        return Ok(sql.to_string());
+    }
+    let dialect = GenericDialect {};
+    let tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(|e| Exception::bad_request_error(e.to_string()))?;
+    let mut pieces: Vec<String> = Vec::new();
+    let mut rewritten = false;
+    let mut index = 0;
+    while index < tokens.len() {
+        let matched = match_time_travel(&tokens, index).filter(|_| {
+            tokens[..index]
+                .iter()
+                .rev()
+                .find(|token| !matches!(token, Token::Whitespace(_)))
+                .is_some_and(is_table_reference_end)
+        });
+        let Some((option, value, next)) = matched else {
+            pieces.push(token_sql(&tokens[index]));
+            index += 1;
+            continue;
         };
+        let argument = format!("{} => {}", option, value);
+        match pieces.iter().rposition(|piece| !piece.trim().is_empty()) {
+            Some(last) if pieces[last] == ")" => pieces.insert(last, format!(", {}", argument)),
+            Some(last) => pieces.insert(last + 1, format!("({})", argument)),
+            None => {
+                return Err(SQLError::sql_syntax_error(
+                    "Time travel without a table",
+                    sql,
+                ))?
+            }
+        }
+        rewritten = true;
+        index = next;
+    }
+    match rewritten {
+        true => Ok(pieces.concat()),
+        false => Ok(sql.to_string()),
+    }
+}
+
+/// 时间旅行之前的记号是否可以是表引用的结尾：表名、路径字符串或表函数的右括号
+/// Whether the token before time travel can end a table reference: a table name, a path string
+/// or the closing parenthesis of a table function
+fn is_table_reference_end(token: &Token) -> bool {
+    match token {
+        Token::Word(word) => word.quote_style.is_some() || word.keyword == Keyword::NoKeyword,
+        Token::SingleQuotedString(_) | Token::RParen => true,
+        _ => false,
+    }
+}
+
+/// 记号在 SQL 中的写法：`Token` 的 `Display` 不转义字符串与带引号标识符中的引号，`'it''s'` 会输出为 `'it's'`
+/// The SQL text of a token: the `Display` of `Token` does not escape quotes inside strings and
+/// quoted identifiers, printing `'it''s'` as `'it's'`
+fn token_sql(token: &Token) -> String {
+    match token {
+        Token::SingleQuotedString(value) => format!("'{}'", value.replace('\'', "''")),
+        Token::Word(word) if word.quote_style == Some('"') => {
+            format!("\"{}\"", word.value.replace('"', "\"\""))
+        }
+        _ => token.to_string(),
+    }
+}
+
+/// 匹配 `VERSION AS OF <value>` 或 `TIMESTAMP AS OF <value>`，返回选项名、值以及之后的位置
+/// Match `VERSION AS OF <value>` or `TIMESTAMP AS OF <value>`, returning the option, the value
+/// and the position after it
+fn match_time_travel(tokens: &[Token], index: usize) -> Option<(&'static str, String, usize)> {
+    let option = match &tokens[index] {
+        Token::Word(word) if word.quote_style.is_none() && word.keyword == Keyword::VERSION => {
+            "version"
+        }
+        Token::Word(word) if word.quote_style.is_none() && word.keyword == Keyword::TIMESTAMP => {
+            "timestamp"
+        }
+        _ => return None,
+    };
+    let mut next = index + 1;
+    let mut next_token = || {
+        while matches!(tokens.get(next), Some(Token::Whitespace(_))) {
+            next += 1;
+        }
+        next += 1;
+        tokens.get(next - 1)
+    };
+    for keyword in [Keyword::AS, Keyword::OF] {
+        match next_token() {
+            Some(Token::Word(word)) if word.keyword == keyword => {}
+            _ => return None,
+        }
+    }
+    let value = match next_token()? {
+        Token::Number(number, _) => number.clone(),
+        token @ Token::SingleQuotedString(_) => token_sql(token),
+        _ => return None,
+    };
+    Some((option, value, next))
+}
+
+/// `->`、`->>` 在通用方言中的优先级低于比较运算符，将 `req ->> 'method' = 'GET'` 中列或字符串与
+/// 之后的 `->`、`->>` 链加上括号，改写为 `(req ->> 'method') = 'GET'`，没有 `->` 的 SQL 原样返回
+/// `->` and `->>` bind looser than comparisons in the generic dialect, so a column or string
+/// followed by a chain of `->` and `->>` is parenthesized, rewriting `req ->> 'method' = 'GET'`
+/// into `(req ->> 'method') = 'GET'`; SQL without `->` is returned unchanged
+fn rewrite_json_operators(sql: &str) -> Result<String, Exception> {
+    if !sql.contains("->") {
+        return Ok(sql.to_string());
+    }
+    let dialect = GenericDialect {};
+    let tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(|e| Exception::bad_request_error(e.to_string()))?;
+    let mut pieces: Vec<String> = Vec::new();
+    let mut rewritten = false;
+    let mut index = 0;
+    while index < tokens.len() {
+        let after_period = matches!(
+            pieces.iter().rev().find(|piece| !piece.trim().is_empty()),
+            Some(piece) if piece == "."
+        );
+        match match_json_operators(&tokens, index) {
+            Some(end) if !after_period => {
+                rewritten = true;
+                pieces.push("(".to_string());
+                pieces.extend(tokens[index..end].iter().map(token_sql));
+                pieces.push(")".to_string());
+                index = end;
+            }
+            _ => {
+                pieces.push(token_sql(&tokens[index]));
+                index += 1;
+            }
+        }
     }
+    match rewritten {
+        true => Ok(pieces.concat()),
+        false => Ok(sql.to_string()),
+    }
+}
+
+/// 匹配 `a.b -> 'key' ->> 0` 形式的列或字符串与运算符链，返回链之后的位置
+/// Match a column or string followed by a chain such as `a.b -> 'key' ->> 0`, returning the
+/// position after the chain
+fn match_json_operators(tokens: &[Token], index: usize) -> Option<usize> {
+    let skip_whitespace = |mut next: usize| {
+        while matches!(tokens.get(next), Some(Token::Whitespace(_))) {
+            next += 1;
+        }
+        next
+    };
+    let mut end = match &tokens[index] {
+        Token::Word(_) | Token::SingleQuotedString(_) => index + 1,
+        _ => return None,
+    };
+    while let (Some(Token::Period), Some(Token::Word(_))) = (
+        tokens.get(skip_whitespace(end)),
+        tokens.get(skip_whitespace(skip_whitespace(end) + 1)),
+    ) {
+        end = skip_whitespace(skip_whitespace(end) + 1) + 1;
+    }
+    let mut chain_end = None;
+    loop {
+        let operator = skip_whitespace(end);
+        let operand = skip_whitespace(operator + 1);
+        match (tokens.get(operator), tokens.get(operand)) {
+            (
+                Some(Token::Arrow | Token::LongArrow),
+                Some(Token::SingleQuotedString(_) | Token::Number(_, _)),
+            ) => {
+                end = operand + 1;
+                chain_end = Some(end);
+            }
+            _ => return chain_end,
+        }
+    }
+}
+
+struct TableFunctionVisitor {
+    tables: Vec<TableCatalog>,
+    references: Vec<TableCatalog>,
+}
+
+impl VisitorMut for TableFunctionVisitor {
+    type Break = Exception;
+
+    fn pre_visit_table_factor(&mut self, table_factor: &mut TableFactor) -> ControlFlow<Exception> {
+        if let TableFactor::Table {
+            name,
+            args: args @ Some(_),
+            ..
+        } = table_factor
+        {
+            let function_name = name.to_string().to_lowercase();
+            let result = if function_name == HISTORY_FUNCTION {
+                let function_args = args.take().map(|args| args.args).unwrap_or_default();
+                get_history_reference(function_args).map(|table| (table, true))
+            } else if let Some(format) = function_name.strip_prefix(TABLE_FUNCTION_PREFIX) {
+                let function_args = args.take().map(|args| args.args).unwrap_or_default();
+                get_table_function_catalog(&function_name, format, function_args)
+                    .map(|table| (table, false))
+            } else if is_time_travel(args.as_ref().map(|args| args.args.as_slice())) {
+                let function_args = args.take().map(|args| args.args).unwrap_or_default();
+                Ok((get_time_travel_reference(name, function_args), true))
+            } else {
+                return ControlFlow::Continue(());
+            };
+            match result {
+                Ok((table, is_reference)) => {
+                    *name = ObjectName::from(vec![Ident::new(&table.table_name)]);
+                    if is_reference {
+                        self.references.push(table);
+                    } else {
+                        self.tables.push(table);
+                    }
+                }
+                Err(error) => return ControlFlow::Break(error),
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// 表参数全部为时间旅行的命名参数时，例如 `sales(version => 3)`
+/// Whether the table arguments are all named time travel arguments, e.g. `sales(version => 3)`
+fn is_time_travel(args: Option<&[FunctionArg]>) -> bool {
+    args.is_some_and(|args| {
+        !args.is_empty()
+            && args.iter().all(|arg| {
+                matches!(arg, FunctionArg::Named { name, .. }
+                    if TIME_TRAVEL_OPTIONS.contains(&name.value.to_lowercase().as_str()))
+            })
+    })
+}
+
+fn get_time_travel_reference(name: &ObjectName, args: Vec<FunctionArg>) -> TableCatalog {
+    let mut table_options = TableOptions::default();
+    for arg in args {
+        if let FunctionArg::Named {
+            name,
+            arg: FunctionArgExpr::Expr(expr),
+            ..
+        } = arg
+        {
+            table_options.insert(name.value, get_literal_string(&expr));
+        }
+    }
+    TableCatalog {
+        table_name: temp_table_name(),
+        table_path: name.to_string().trim_matches('\'').to_string(),
+        table_options,
+        table_schema: Vec::new(),
+    }
+}
+
+/// `table_history('sales')` 的参数是 catalog 中的表名或表的路径
+/// The argument of `table_history('sales')` is a table in the catalog or the path of a table
+fn get_history_reference(args: Vec<FunctionArg>) -> Result<TableCatalog, Exception> {
+    let table_path = match args.as_slice() {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(expr))] => get_literal_string(expr),
+        _ => {
+            return Err(Exception::bad_request_error(format!(
+                "Table function {} expects a table name or path, e.g. {}('sales')",
+                HISTORY_FUNCTION, HISTORY_FUNCTION
+            )))
+        }
+    };
+    let mut table_options = TableOptions::default();
+    table_options.insert("history", "true");
+    Ok(TableCatalog {
+        table_name: temp_table_name(),
+        table_path,
+        table_options,
+        table_schema: Vec::new(),
+    })
+}
+
+pub(crate) fn temp_table_name() -> String {
+    format!(
+        "temp_{}_{}",
+        Utc::now().timestamp(),
+        utils::generate_random_string(4)
+    )
+}
+
+fn get_table_function_catalog(
+    function_name: &str,
+    format: &str,
+    args: Vec<FunctionArg>,
+) -> Result<TableCatalog, Exception> {
+    let mut table_path: Option<String> = None;
+    let mut table_options = TableOptions::default();
+    table_options.insert("format", format);
+
+    for arg in args {
+        match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) if table_path.is_none() => {
+                table_path = Some(get_literal_string(&expr));
+            }
+            FunctionArg::Named {
+                name,
+                arg: FunctionArgExpr::Expr(expr),
+                ..
+            } => {
+                if !TABLE_OPTION_KEYS.contains(&name.value.to_lowercase().as_str()) {
+                    return Err(Exception::bad_request_error(format!(
+                        "Unknown option '{}' in table function {}, expected one of: {}",
+                        name.value,
+                        function_name,
+                        TABLE_OPTION_KEYS.join(", ")
+                    )));
+                }
+                table_options.insert(name.value, get_literal_string(&expr));
+            }
+            _ => {
+                return Err(Exception::bad_request_error(format!(
+                    "Unsupported argument in table function {}, expected {}('path', option => value, ...)",
+                    function_name, function_name
+                )))
+            }
+        }
+    }
+
+    let table_path = table_path.ok_or_else(|| {
+        Exception::bad_request_error(format!(
+            "Table function {} requires a file path",
+            function_name
+        ))
+    })?;
+
+    Ok(TableCatalog {
+        table_name: temp_table_name(),
+        table_path,
+        table_options,
+        table_schema: Vec::new(),
+    })
+}
+
+/// 从 `WITH (key = 'value')` 或 `TBLPROPERTIES (key = 'value')` 中读取表选项
+/// Read table options from `WITH (key = 'value')` or `TBLPROPERTIES (key = 'value')`
+pub fn get_table_options(options: &[SqlOption]) -> TableOptions {
+    let mut table_options = TableOptions::default();
+    for option in options {
+        if let SqlOption::KeyValue { key, value } = option {
+            table_options.insert(key.value.clone(), get_literal_string(value));
+        }
+    }
+    table_options
+}
+
+fn get_literal_string(expr: &Expr) -> String {
+    match expr {
+        Expr::Value(value) => value
+            .value
+            .clone()
+            .into_string()
+            .unwrap_or_else(|| value.value.to_string()),
+        _ => expr.to_string(),
+    }
+}
+
+pub fn get_sql_type(sql: &str) -> Result<(Vec<Statement>, SQLType), Exception> {
+    let statements = parse_sql(&rewrite_time_travel(&rewrite_json_operators(sql)?)?)?;
+    let sql_type = match statements.first() {
+        Some(Statement::Query(_)) => DML,
+        Some(Statement::CreateTable(_)) => DDL,
+        Some(_) => {
+            return Err(Exception::unprocessable_entity_error(
+                "Currently, only the Select and Create statements are supported.",
+            ))
+        }
+        None => {
+            return Err(SQLError::sql_syntax_error(
+                "SQL parsing error: statements are empty",
+                sql,
+            ))?
+        }
+    };
 
-    unreachable!()
+    Ok((statements, sql_type))
 }
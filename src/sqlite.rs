@@ -1,8 +1,10 @@
+use crate::config::get_config;
 use chrono::Local;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
 
 pub fn conn() -> Connection {
-    Connection::open("sqlite/easydb.db").unwrap()
+    Connection::open(&get_config().storage.metadata_db).unwrap()
 }
 
 pub fn init_db() {
@@ -20,6 +22,27 @@ pub fn init_db() {
         [],
     )
     .expect("Failed to create catalog");
+    add_column_if_not_exists(
+        &conn,
+        "catalog",
+        "table_options",
+        "TEXT NOT NULL DEFAULT '{}'",
+    )
+    .expect("Failed to migrate catalog");
+    add_column_if_not_exists(&conn, "catalog", "status", "TEXT NOT NULL DEFAULT 'OK'")
+        .expect("Failed to migrate catalog");
+
+    // 外部数据库的凭据与 catalog 分开保存，catalog 中的连接地址不含用户名与密码
+    // Credentials of external databases are kept apart from the catalog, whose connection URLs
+    // carry no user or password
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS credentials (
+                  table_ref            TEXT PRIMARY KEY,
+                  credentials          TEXT NOT NULL
+                  )",
+        [],
+    )
+    .expect("Failed to create credentials");
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS query_history (
@@ -34,13 +57,73 @@ pub fn init_db() {
     .expect("Failed to create catalog");
 }
 
+/// 为已有的表补充新增的列，兼容旧版本创建的数据库
+/// Add a column to an existing table, keeping databases created by older versions compatible
+fn add_column_if_not_exists(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<usize, String>(1))?
+        .any(|name| name.map(|name| name == column).unwrap_or(false));
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// 读取以 JSON 存储的列，例如 `table_schema`、`table_options`
+/// Read a column stored as JSON, such as `table_schema` or `table_options`
+pub fn from_json_column<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let value = row.get::<usize, String>(index)?;
+    serde_json::from_str(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// 保存外部表的 `user:password` 凭据
+/// Save the `user:password` credentials of an external table
+pub fn save_credentials(table_ref: &str, credentials: &str) -> rusqlite::Result<()> {
+    conn().execute(
+        r#"
+                        insert into credentials ( table_ref, credentials )
+                        values
+                        (?1, ?2)
+                        on conflict ( table_ref ) do update
+                        set credentials = excluded.credentials
+                        "#,
+        params![table_ref, credentials],
+    )?;
+    Ok(())
+}
+
+pub fn get_credentials(table_ref: &str) -> rusqlite::Result<Option<String>> {
+    conn()
+        .query_row(
+            "select credentials from credentials where table_ref = ?1",
+            params![table_ref],
+            |row| row.get(0),
+        )
+        .optional()
+}
+
 pub fn insert_query_history(sql: &str, status: &str) {
-    if let Err(_) = conn().execute(
+    let _ = conn().execute(
         r#"
                         insert into query_history ( sql, status, created_at )
                         values
                         (?1, ?2, ?3)
                         "#,
-        params![sql, status, Local::now().format("%Y-%m-%d %H:%M:%S").to_string()],
-    ) {}
+        params![
+            sql,
+            status,
+            Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+        ],
+    );
 }
@@ -17,7 +17,7 @@ pub enum FileType {
     CSV,
     JSON,
     DnJson,
-    TSV
+    TSV,
 }
 
 impl OperatingSystem {
@@ -78,8 +78,10 @@ pub fn join_paths(base_path: &str, relative_path: &str) -> PathBuf {
     full_path
 }
 
+/// 是否为相对路径，`s3://` 等 URL 不是相对路径
+/// Whether a path is relative, URLs such as `s3://` are not
 pub fn is_relative_path(path: &str) -> bool {
-    Path::new(path).is_relative()
+    !path.contains("://") && Path::new(path).is_relative()
 }
 
 pub fn time_difference_from_now(input_time: DateTime<Utc>) -> String {